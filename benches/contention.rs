@@ -0,0 +1,92 @@
+//! Multi-threaded read-heavy workloads, where every lookup writes the lock word of its shard.
+//!
+//! Without the `cache-padded` feature the locks of neighbouring shards share cache lines, so
+//! threads working on different shards still invalidate each other's caches. To compare, run
+//!
+//! ```text
+//! cargo bench --bench contention -- --save-baseline packed
+//! cargo bench --bench contention --features cache-padded -- --baseline packed
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dashmap::DashMap;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEYS: u64 = 1 << 12;
+const OPS_PER_THREAD: u64 = 1 << 14;
+
+/// Runs `OPS_PER_THREAD` operations on each of `threads` threads, writing once every
+/// `write_every` operations, and returns the time until the last thread finished.
+fn run(map: &Arc<DashMap<u64, u64>>, threads: usize, write_every: u64) -> Duration {
+    let barrier = Arc::new(Barrier::new(threads + 1));
+
+    let handles: Vec<_> = (0..threads as u64)
+        .map(|t| {
+            let map = map.clone();
+            let barrier = barrier.clone();
+
+            thread::spawn(move || {
+                // Each thread walks its own sequence of keys, spreading over all shards.
+                let mut key = t.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+                barrier.wait();
+
+                for i in 0..OPS_PER_THREAD {
+                    key = key.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+
+                    let k = key % KEYS;
+
+                    if write_every != 0 && i % write_every == 0 {
+                        map.insert(k, i);
+                    } else {
+                        black_box(map.get(&k).map(|v| *v));
+                    }
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+
+    let start = Instant::now();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    start.elapsed()
+}
+
+fn contention(c: &mut Criterion) {
+    let map: Arc<DashMap<u64, u64>> = Arc::new((0..KEYS).map(|k| (k, k)).collect());
+
+    let max_threads = num_cpus::get().max(2);
+    let mut thread_counts = vec![1];
+
+    while thread_counts.last().unwrap() * 2 <= max_threads {
+        thread_counts.push(thread_counts.last().unwrap() * 2);
+    }
+
+    for &(name, write_every) in &[("read only", 0), ("read mostly", 20)] {
+        let mut group = c.benchmark_group(name);
+
+        for &threads in &thread_counts {
+            group.throughput(Throughput::Elements(threads as u64 * OPS_PER_THREAD));
+
+            group.bench_with_input(
+                BenchmarkId::new("threads", threads),
+                &threads,
+                |b, &threads| {
+                    b.iter_custom(|iters| (0..iters).map(|_| run(&map, threads, write_every)).sum())
+                },
+            );
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, contention);
+criterion_main!(benches);
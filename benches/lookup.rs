@@ -0,0 +1,120 @@
+//! `get` and `insert` throughput for cheap and expensive keys.
+//!
+//! With the `hashbrown` feature every operation hashes its key once, for both the shard and the
+//! bucket. The default build still hashes it twice, once to pick the shard and once more inside
+//! the std map of the shard, so the single hash only pays off with `hashbrown` enabled.
+//!
+//! With `hashbrown`, the `pre-change` groups measure the same operations with the extra hash every
+//! operation paid before the shard hash was reused, on the same backend:
+//!
+//! ```text
+//! cargo bench --bench lookup --features hashbrown
+//! ```
+//!
+//! To compare the default build against `hashbrown`, run
+//!
+//! ```text
+//! cargo bench --bench lookup -- --save-baseline std
+//! cargo bench --bench lookup --features hashbrown -- --baseline std
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dashmap::DashMap;
+use std::hash::Hash;
+
+const LEN: usize = 10_000;
+
+fn int_keys() -> Vec<u64> {
+    (0..LEN as u64)
+        .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .collect()
+}
+
+/// Long keys sharing a prefix, so hashing dominates the lookup.
+fn string_keys() -> Vec<String> {
+    (0..LEN)
+        .map(|i| format!("{}/{}", "tenant/region/service/".repeat(8), i))
+        .collect()
+}
+
+/// Hashes `key` once more when emulating the lookups from before the shard hash was reused.
+fn rehash<K: Eq + Hash>(map: &DashMap<K, usize>, key: &K, pre_change: bool) {
+    if pre_change {
+        black_box(map.hash_u64(key));
+    }
+}
+
+fn bench_get<K: Eq + Hash + Clone>(
+    c: &mut Criterion,
+    group: &str,
+    pre_change: bool,
+    name: &str,
+    keys: &[K],
+) {
+    let map: DashMap<K, usize> = keys.iter().cloned().zip(0..).collect();
+
+    let mut group = c.benchmark_group(group);
+
+    group.throughput(Throughput::Elements(keys.len() as u64));
+
+    group.bench_with_input(BenchmarkId::from_parameter(name), keys, |b, keys| {
+        b.iter(|| {
+            for key in keys {
+                rehash(&map, key, pre_change);
+                black_box(map.get(key));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_insert<K: Eq + Hash + Clone>(
+    c: &mut Criterion,
+    group: &str,
+    pre_change: bool,
+    name: &str,
+    keys: &[K],
+) {
+    let mut group = c.benchmark_group(group);
+
+    group.throughput(Throughput::Elements(keys.len() as u64));
+
+    group.bench_with_input(BenchmarkId::from_parameter(name), keys, |b, keys| {
+        b.iter_batched(
+            || (DashMap::with_capacity(keys.len()), keys.to_vec()),
+            |(map, keys)| {
+                for (i, key) in keys.into_iter().enumerate() {
+                    rehash(&map, &key, pre_change);
+                    map.insert(key, i);
+                }
+
+                map
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let ints = int_keys();
+    let strings = string_keys();
+
+    bench_get(c, "get", false, "u64", &ints);
+    bench_get(c, "get", false, "long string", &strings);
+    bench_insert(c, "insert", false, "u64", &ints);
+    bench_insert(c, "insert", false, "long string", &strings);
+
+    // The default build did not change, so it has no separate baseline.
+    if cfg!(feature = "hashbrown") {
+        bench_get(c, "get pre-change", true, "u64", &ints);
+        bench_get(c, "get pre-change", true, "long string", &strings);
+        bench_insert(c, "insert pre-change", true, "u64", &ints);
+        bench_insert(c, "insert pre-change", true, "long string", &strings);
+    }
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
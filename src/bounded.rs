@@ -0,0 +1,637 @@
+//! A [`DashMap`] holding at most a fixed weight of entries, evicting per shard with CLOCK.
+//!
+//! [`DashMap`]: ../struct.DashMap.html
+
+use crate::mapref::one;
+use crate::shard::HashedShard;
+use crate::t::Map;
+use crate::util::SharedValue;
+use crate::{DashMap, HashMap};
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Shards are halved until each gets at least this much of the maximum weight.
+const MIN_SHARD_WEIGHT: usize = 16;
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+type Listener<K, V> = Box<dyn Fn(K, V) + Send + Sync>;
+
+struct Slot<V> {
+    value: V,
+    weight: usize,
+    /// Identifies this insert of the key in the ring of its shard.
+    generation: u64,
+    /// Set by lookups, cleared when the clock hand passes the entry.
+    referenced: AtomicBool,
+}
+
+/// The eviction order of one shard. Only touched while holding the write lock of that shard.
+struct ShardClock<K> {
+    /// Keys in insertion order; the hand is the front. Entries whose generation no longer matches
+    /// the slot in the shard were removed and are skipped.
+    ring: VecDeque<(K, u64)>,
+    next_generation: u64,
+    weight: usize,
+    capacity: usize,
+}
+
+/// BoundedMap is a [`DashMap`] that holds at most a fixed total weight of entries.
+///
+/// Entries weigh 1 each unless a weigher is given with [`with_weigher`]. Once an insert pushes
+/// a shard over its part of the maximum weight, entries of that shard are evicted with the CLOCK
+/// algorithm: the hand walks the entries of the shard in insertion order and evicts the first one
+/// that was not looked up since the hand last passed it. Every shard keeps its own ring and its
+/// own part of the capacity, so evictions only ever lock the shard that is being inserted into.
+/// Evicted entries are handed to the listener set with [`with_eviction_listener`] once every lock
+/// has been released.
+///
+/// Since the capacity is split evenly over the shards, a shard may start evicting before the map
+/// as a whole reaches its maximum weight.
+///
+/// [`DashMap`]: ../struct.DashMap.html
+/// [`with_weigher`]: #method.with_weigher
+/// [`with_eviction_listener`]: #method.with_eviction_listener
+pub struct BoundedMap<K, V, S = RandomState> {
+    inner: DashMap<K, Slot<V>, S>,
+    clocks: Box<[Mutex<ShardClock<K>>]>,
+    max_weight: usize,
+    weigher: Weigher<K, V>,
+    listener: Option<Listener<K, V>>,
+}
+
+impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher + Clone> fmt::Debug
+    for BoundedMap<K, V, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pmap = f.debug_map();
+
+        for r in self.inner.iter() {
+            pmap.entry(r.key(), &r.value().value);
+        }
+
+        pmap.finish()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> BoundedMap<K, V, RandomState> {
+    /// Creates a new BoundedMap holding at most `max_weight` entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::BoundedMap;
+    ///
+    /// let cache = BoundedMap::new(1000);
+    /// cache.insert("pi", 3.14);
+    /// assert_eq!(*cache.get("pi").unwrap(), 3.14);
+    /// ```
+    pub fn new(max_weight: usize) -> Self {
+        Self::with_hasher(max_weight, RandomState::default())
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash + Clone, V: 'a, S: BuildHasher + Clone> BoundedMap<K, V, S> {
+    /// Creates a new BoundedMap holding at most `max_weight` entries, with the provided hasher.
+    pub fn with_hasher(max_weight: usize, hasher: S) -> Self {
+        let mut shard_amount = crate::default_shard_amount();
+
+        while shard_amount > 2 && max_weight / shard_amount < MIN_SHARD_WEIGHT {
+            shard_amount /= 2;
+        }
+
+        let inner = DashMap::with_capacity_and_hasher_and_shard_amount(0, hasher, shard_amount);
+
+        // Spread the remainder over the first shards so the parts add up to `max_weight` exactly.
+        let clocks = (0..shard_amount)
+            .map(|i| {
+                let extra = (i < max_weight % shard_amount) as usize;
+
+                Mutex::new(ShardClock {
+                    ring: VecDeque::new(),
+                    next_generation: 0,
+                    weight: 0,
+                    capacity: max_weight / shard_amount + extra,
+                })
+            })
+            .collect();
+
+        Self {
+            inner,
+            clocks,
+            max_weight,
+            weigher: Box::new(|_, _| 1),
+            listener: None,
+        }
+    }
+
+    /// Weighs entries with `weigher` instead of counting each as 1.
+    /// The weight of an entry is computed once, when it is inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::BoundedMap;
+    ///
+    /// let cache = BoundedMap::new(1 << 20).with_weigher(|_, v: &Vec<u8>| v.len());
+    /// cache.insert("blob", vec![0; 1024]);
+    /// assert_eq!(cache.weight(), 1024);
+    /// ```
+    pub fn with_weigher(
+        mut self,
+        weigher: impl Fn(&K, &V) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.weigher = Box::new(weigher);
+
+        self
+    }
+
+    /// Calls `listener` with every entry evicted to make room.
+    /// Entries removed with `remove` or `clear` are not passed to it.
+    ///
+    /// The listener runs after all locks were released, so it may use the map.
+    pub fn with_eviction_listener(
+        mut self,
+        listener: impl Fn(K, V) + Send + Sync + 'static,
+    ) -> Self {
+        self.listener = Some(Box::new(listener));
+
+        self
+    }
+
+    /// Returns the maximum total weight of the entries in the map.
+    pub fn max_weight(&self) -> usize {
+        self.max_weight
+    }
+
+    /// Returns the total weight of the entries in the map.
+    pub fn weight(&self) -> usize {
+        self.clocks.iter().map(|clock| lock(clock).weight).sum()
+    }
+
+    /// Inserts a key and a value into the map, evicting entries of its shard if that goes over
+    /// its part of the maximum weight. Returns the old value associated with the key if there was one.
+    ///
+    /// An entry heavier than the part of a shard is evicted right away, without evicting any other
+    /// entry. If its key was present, the old entry is removed and its value returned.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::BoundedMap;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let evictions = Arc::new(AtomicUsize::new(0));
+    /// let counter = evictions.clone();
+    ///
+    /// let cache = BoundedMap::new(100).with_eviction_listener(move |_, _| {
+    ///     counter.fetch_add(1, Ordering::Relaxed);
+    /// });
+    ///
+    /// for i in 0..1000 {
+    ///     cache.insert(i, i);
+    /// }
+    ///
+    /// assert!(cache.len() <= 100);
+    /// assert_eq!(cache.len() + evictions.load(Ordering::Relaxed), 1000);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let weight = (self.weigher)(&key, &value);
+        let hash = self.inner.hash_u64(&key);
+        let idx = self.inner.determine_shard(hash as usize);
+
+        let (old, evicted) = {
+            let mut shard = unsafe { self.inner._yield_write_shard(idx) };
+            let mut clock = lock(&self.clocks[idx]);
+
+            if weight > clock.capacity {
+                let old = shard.remove_entry_hashed(hash, &key).map(|(_, slot)| {
+                    let slot = slot.into_inner();
+
+                    clock.weight -= slot.weight;
+
+                    slot.value
+                });
+
+                if old.is_some() {
+                    compact(&shard, &mut clock);
+                }
+
+                drop(clock);
+                drop(shard);
+
+                if let Some(listener) = &self.listener {
+                    listener(key, value);
+                }
+
+                return old;
+            }
+
+            let old = match shard.get_mut_hashed(hash, &key) {
+                Some(slot) => {
+                    let slot = slot.get_mut();
+
+                    clock.weight = clock.weight - slot.weight + weight;
+                    slot.weight = weight;
+                    *slot.referenced.get_mut() = true;
+
+                    Some(mem::replace(&mut slot.value, value))
+                }
+                None => {
+                    let generation = clock.next_generation;
+
+                    clock.next_generation += 1;
+                    clock.weight += weight;
+                    clock.ring.push_back((key.clone(), generation));
+
+                    let slot = Slot {
+                        value,
+                        weight,
+                        generation,
+                        referenced: AtomicBool::new(false),
+                    };
+
+                    shard.insert_unique_hashed(hash, key, SharedValue::new(slot));
+
+                    None
+                }
+            };
+
+            (old, evict(&mut shard, &mut clock))
+        };
+
+        if let Some(listener) = &self.listener {
+            for (k, v) in evicted {
+                listener(k, v);
+            }
+        }
+
+        old
+    }
+
+    /// Get an immutable reference to an entry in the map, marking it as recently used.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let r = self.inner.get(key)?;
+
+        r.referenced.store(true, Ordering::Relaxed);
+
+        Some(Ref { inner: r })
+    }
+
+    /// Checks if the map contains a specific key, without marking it as recently used.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.contains_key(key)
+    }
+
+    /// Removes an entry from the map, returning the key and value if they existed in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash_u64(&key);
+        let idx = self.inner.determine_shard(hash as usize);
+        let mut shard = unsafe { self.inner._yield_write_shard(idx) };
+        let (k, slot) = shard.remove_entry_hashed(hash, key)?;
+        let slot = slot.into_inner();
+        let mut clock = lock(&self.clocks[idx]);
+
+        clock.weight -= slot.weight;
+        compact(&shard, &mut clock);
+
+        Some((k, slot.value))
+    }
+
+    /// Fetches the total number of entries stored in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Checks if the map is empty or not.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Removes all entries in the map, without passing them to the eviction listener.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn clear(&self) {
+        for idx in 0..self.clocks.len() {
+            let mut shard = unsafe { self.inner._yield_write_shard(idx) };
+            let mut clock = lock(&self.clocks[idx]);
+
+            shard.clear();
+            clock.ring.clear();
+            clock.weight = 0;
+        }
+    }
+}
+
+fn lock<K>(clock: &Mutex<ShardClock<K>>) -> MutexGuard<'_, ShardClock<K>> {
+    // The clock is only changed while its shard is write locked, which a panic would not leave
+    // half done, so poisoning can be ignored.
+    clock.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether `key` is in `shard` and was inserted as `generation`.
+fn is_current<K: Eq + Hash, V, S: BuildHasher>(
+    shard: &HashMap<K, Slot<V>, S>,
+    key: &K,
+    generation: u64,
+) -> bool {
+    match shard.get(key) {
+        Some(slot) => slot.get().generation == generation,
+        None => false,
+    }
+}
+
+/// Drops the ring entries of removed keys once they make up half of the ring.
+///
+/// Called after removing a key from `shard`, since its entry stays behind in the ring.
+fn compact<K: Eq + Hash, V, S: BuildHasher>(
+    shard: &HashMap<K, Slot<V>, S>,
+    clock: &mut ShardClock<K>,
+) {
+    if clock.ring.len() > 2 * shard.len() + MIN_SHARD_WEIGHT {
+        clock
+            .ring
+            .retain(|(k, generation)| is_current(shard, k, *generation));
+    }
+}
+
+/// Runs the clock hand over `shard` until it fits its capacity again, returning the evicted entries.
+fn evict<K: Eq + Hash, V, S: BuildHasher>(
+    shard: &mut HashMap<K, Slot<V>, S>,
+    clock: &mut ShardClock<K>,
+) -> Vec<(K, V)> {
+    let mut evicted = Vec::new();
+
+    while clock.weight > clock.capacity {
+        let (key, generation) = match clock.ring.pop_front() {
+            Some(entry) => entry,
+            None => break,
+        };
+
+        if !is_current(shard, &key, generation) {
+            continue;
+        }
+
+        let slot = shard.get_mut(&key).unwrap().get_mut();
+
+        if mem::replace(slot.referenced.get_mut(), false) {
+            clock.ring.push_back((key, generation));
+        } else {
+            let (k, slot) = shard.remove_entry(&key).unwrap();
+            let slot = slot.into_inner();
+
+            clock.weight -= slot.weight;
+            evicted.push((k, slot.value));
+        }
+    }
+
+    evicted
+}
+
+/// An immutable reference to an entry of a [`BoundedMap`].
+///
+/// [`BoundedMap`]: struct.BoundedMap.html
+pub struct Ref<'a, K, V, S = RandomState> {
+    inner: one::Ref<'a, K, Slot<V>, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Ref<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> &V {
+        &self.inner.value().value
+    }
+
+    pub fn pair(&self) -> (&K, &V) {
+        (self.key(), self.value())
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Deref for Ref<'a, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoundedMap;
+    use core::hash::{BuildHasher, Hasher};
+    use std::sync::{Arc, Mutex};
+
+    /// Sends every key to the first shard so that eviction order can be checked.
+    #[derive(Clone, Default)]
+    struct OneShard;
+
+    struct ZeroHasher;
+
+    impl Hasher for ZeroHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    impl BuildHasher for OneShard {
+        type Hasher = ZeroHasher;
+
+        fn build_hasher(&self) -> ZeroHasher {
+            ZeroHasher
+        }
+    }
+
+    fn recording(max_weight: usize) -> (BoundedMap<i32, i32, OneShard>, Arc<Mutex<Vec<i32>>>) {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+
+        let map = BoundedMap::with_hasher(max_weight, OneShard)
+            .with_eviction_listener(move |k, _| log.lock().unwrap().push(k));
+
+        (map, evicted)
+    }
+
+    #[test]
+    fn test_clock_gives_second_chance() {
+        // Two shards, four entries fit in the one every key goes to.
+        let (map, evicted) = recording(8);
+
+        for i in 1..=4 {
+            map.insert(i, i);
+        }
+
+        assert_eq!(*map.get(&1).unwrap(), 1);
+
+        map.insert(5, 5);
+        map.insert(6, 6);
+
+        assert_eq!(*evicted.lock().unwrap(), vec![2, 3]);
+
+        map.insert(7, 7);
+
+        assert_eq!(*evicted.lock().unwrap(), vec![2, 3, 4]);
+
+        // 1 went back behind 5 when the hand cleared its bit, and gets no third chance.
+        map.insert(8, 8);
+        map.insert(9, 9);
+
+        assert_eq!(*evicted.lock().unwrap(), vec![2, 3, 4, 5, 1]);
+        assert_eq!(map.len(), 4);
+        assert!((6..=9).all(|i| map.contains_key(&i)));
+    }
+
+    #[test]
+    fn test_removed_keys_are_skipped() {
+        let (map, evicted) = recording(8);
+
+        for i in 1..=4 {
+            map.insert(i, i);
+        }
+
+        assert_eq!(map.remove(&1), Some((1, 1)));
+
+        map.insert(1, 10);
+        map.insert(5, 5);
+        map.insert(6, 6);
+
+        // The ring entry of the removed 1 is skipped instead of evicting the new 1.
+        assert_eq!(*evicted.lock().unwrap(), vec![2, 3]);
+        assert!(map.contains_key(&1));
+        assert_eq!(map.insert(1, 11), Some(10));
+        assert_eq!(map.weight(), 4);
+    }
+
+    #[test]
+    fn test_weigher() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+
+        let map = BoundedMap::new(1000)
+            .with_weigher(|_, v: &String| v.len())
+            .with_eviction_listener(move |k, _| log.lock().unwrap().push(k));
+
+        for i in 0..1000 {
+            map.insert(i, "x".repeat(i % 10));
+
+            assert!(map.weight() <= map.max_weight());
+        }
+
+        assert!(map.weight() > 0);
+        assert_eq!(map.len() + evicted.lock().unwrap().len(), 1000);
+
+        let len = map.len();
+        let weight = map.weight();
+        let evictions = evicted.lock().unwrap().len();
+
+        map.insert(1000, "x".repeat(2000));
+
+        // Only the oversized entry itself is evicted.
+        assert!(!map.contains_key(&1000));
+        assert_eq!(*evicted.lock().unwrap().last().unwrap(), 1000);
+        assert_eq!(evicted.lock().unwrap().len(), evictions + 1);
+        assert_eq!(map.len(), len);
+        assert_eq!(map.weight(), weight);
+
+        // Replacing a present entry by an oversized one removes it.
+        let present = (0..1000).find(|i| map.contains_key(i)).unwrap();
+        let old = "x".repeat(present % 10);
+
+        assert_eq!(map.insert(present, "x".repeat(2000)), Some(old.clone()));
+        assert!(!map.contains_key(&present));
+        assert_eq!(map.len(), len - 1);
+        assert_eq!(map.weight(), weight - old.len());
+
+        map.clear();
+
+        assert!(map.is_empty());
+        assert_eq!(map.weight(), 0);
+    }
+
+    #[test]
+    fn test_oversized_replacements_keep_ring_bounded() {
+        let map = BoundedMap::with_hasher(64, OneShard).with_weigher(|_, v: &usize| *v);
+
+        for _ in 0..1000 {
+            map.insert(1, 1);
+            map.insert(1, 1000);
+        }
+
+        assert!(map.is_empty());
+        assert_eq!(map.weight(), 0);
+
+        for clock in map.clocks.iter() {
+            assert!(super::lock(clock).ring.len() <= super::MIN_SHARD_WEIGHT + 1);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_inserts_stay_bounded() {
+        let map = Arc::new(BoundedMap::new(256));
+
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let map = map.clone();
+
+                std::thread::spawn(move || {
+                    for i in 0..2000 {
+                        map.insert(t * 2000 + i, i);
+
+                        if i % 3 == 0 {
+                            map.get(&(t * 2000 + i / 2));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(map.len() <= 256);
+        assert_eq!(map.weight(), map.len());
+    }
+}
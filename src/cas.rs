@@ -0,0 +1,90 @@
+//! Outcomes of the conditional update methods such as `DashMap::compare_and_swap`.
+//!
+//! Each tells which branch was taken and hands back whatever was moved into the call
+//! but not stored, so that the caller can retry or fall back without losing it.
+
+/// The outcome of `DashMap::compare_and_swap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareAndSwap<V> {
+    /// The value was equal to the expected one and was replaced. Holds the previous value.
+    Swapped(V),
+    /// The value differed from the expected one. Holds the new value.
+    Mismatch(V),
+    /// The key was not present. Holds the new value.
+    Absent(V),
+}
+
+impl<V> CompareAndSwap<V> {
+    /// Returns `true` if the value was replaced.
+    pub fn is_swapped(&self) -> bool {
+        matches!(self, CompareAndSwap::Swapped(_))
+    }
+}
+
+/// The outcome of `DashMap::insert_if_absent` and `DashSet::insert_if_absent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertIfAbsent<K, V> {
+    /// The key was not present and has been inserted.
+    Inserted,
+    /// The key was already present. Holds the key and value that were not inserted.
+    Present(K, V),
+}
+
+impl<K, V> InsertIfAbsent<K, V> {
+    /// Returns `true` if the key was inserted.
+    pub fn is_inserted(&self) -> bool {
+        matches!(self, InsertIfAbsent::Inserted)
+    }
+}
+
+/// The outcome of `DashMap::replace` and `DashSet::replace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replace<V> {
+    /// The key was present. Holds what was replaced.
+    Replaced(V),
+    /// The key was not present. Holds what would have replaced it.
+    Absent(V),
+}
+
+impl<V> Replace<V> {
+    /// Returns `true` if the key was present.
+    pub fn is_replaced(&self) -> bool {
+        matches!(self, Replace::Replaced(_))
+    }
+}
+
+/// The outcome of `DashMap::update_if`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateIf<R> {
+    /// The predicate accepted the entry. Holds the result of the update.
+    Updated(R),
+    /// The predicate rejected the entry, which was left untouched.
+    Rejected,
+    /// The key was not present.
+    Absent,
+}
+
+impl<R> UpdateIf<R> {
+    /// Returns `true` if the entry was updated.
+    pub fn is_updated(&self) -> bool {
+        matches!(self, UpdateIf::Updated(_))
+    }
+}
+
+/// The outcome of `DashMap::remove_if_eq`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoveIfEq<K, V> {
+    /// The value was equal to the expected one. Holds the removed entry.
+    Removed(K, V),
+    /// The value differed from the expected one and was left in the map.
+    Mismatch,
+    /// The key was not present.
+    Absent,
+}
+
+impl<K, V> RemoveIfEq<K, V> {
+    /// Returns `true` if the entry was removed.
+    pub fn is_removed(&self) -> bool {
+        matches!(self, RemoveIfEq::Removed(..))
+    }
+}
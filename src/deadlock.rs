@@ -0,0 +1,302 @@
+//! Bookkeeping of the shard locks held by each thread, used by the `deadlock-detection` feature.
+//!
+//! Every lock guard carries a [`Held`] token that records the lock, the mode and the call site
+//! that acquired it in the registry of the acquiring thread. Before a map operation blocks on a
+//! shard it checks that registry and panics if the current thread already holds the shard in a
+//! mode the request would wait on forever. Without the feature the token is zero sized and the
+//! checks compile to nothing.
+
+use core::fmt;
+
+/// The mode a lock is held or requested in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Read,
+    Upgradeable,
+    Write,
+}
+
+impl Mode {
+    /// Whether requesting `self` can never succeed while the same thread holds `held`.
+    #[allow(dead_code)]
+    fn waits_on(self, held: Mode) -> bool {
+        match (self, held) {
+            // Writer-preferring shards hold back readers once a writer waits behind `held`.
+            (Mode::Read, Mode::Read) | (Mode::Upgradeable, Mode::Read) => {
+                cfg!(feature = "fair-lock")
+            }
+            // `parking_lot` lets new readers in next to an upgradeable reader.
+            (Mode::Read, Mode::Upgradeable) => !cfg!(feature = "parking_lot"),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Read => "read".fmt(f),
+            Mode::Upgradeable => "upgradeable read".fmt(f),
+            Mode::Write => "write".fmt(f),
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "deadlock-detection")] {
+        use std::panic::Location;
+        use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+        struct Record {
+            id: u64,
+            lock: *const (),
+            mode: Mode,
+            location: &'static Location<'static>,
+        }
+
+        #[derive(Default)]
+        struct Records {
+            next_id: u64,
+            held: Vec<Record>,
+        }
+
+        // The raw lock pointers are only compared, never dereferenced.
+        unsafe impl Send for Records {}
+
+        /// The locks held by one thread. Guards keep it alive so that they can be dropped on
+        /// another thread and still remove their record.
+        #[derive(Default)]
+        struct Registry {
+            records: Mutex<Records>,
+        }
+
+        impl Registry {
+            fn records(&self) -> MutexGuard<'_, Records> {
+                self.records.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+        }
+
+        thread_local! {
+            static REGISTRY: Arc<Registry> = Arc::new(Registry::default());
+        }
+
+        /// Token recording that a lock is held, removed from its registry on drop.
+        pub(crate) struct Held {
+            registry: Arc<Registry>,
+            id: u64,
+        }
+
+        impl Held {
+            #[track_caller]
+            pub(crate) fn new(lock: *const (), mode: Mode) -> Self {
+                let location = Location::caller();
+                let registry = REGISTRY.with(Arc::clone);
+
+                let id = {
+                    let mut records = registry.records();
+                    let id = records.next_id;
+
+                    records.next_id += 1;
+                    records.held.push(Record {
+                        id,
+                        lock,
+                        mode,
+                        location,
+                    });
+
+                    id
+                };
+
+                Held { registry, id }
+            }
+
+            pub(crate) fn set_mode(&self, mode: Mode) {
+                let mut records = self.registry.records();
+
+                if let Some(record) = records.held.iter_mut().find(|r| r.id == self.id) {
+                    record.mode = mode;
+                }
+            }
+        }
+
+        impl Drop for Held {
+            fn drop(&mut self) {
+                let mut records = self.registry.records();
+
+                if let Some(pos) = records.held.iter().position(|r| r.id == self.id) {
+                    records.held.remove(pos);
+                }
+            }
+        }
+
+        impl fmt::Debug for Held {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Held").field("id", &self.id).finish()
+            }
+        }
+
+        /// Forgets the most recent record of `lock` in `mode` on the current thread.
+        /// Used when a guard was leaked and the lock is released by hand.
+        pub(crate) fn release_forgotten(lock: *const (), mode: Mode) {
+            REGISTRY.with(|registry| {
+                let mut records = registry.records();
+
+                if let Some(pos) = records
+                    .held
+                    .iter()
+                    .rposition(|r| r.lock == lock && r.mode == mode)
+                {
+                    records.held.remove(pos);
+                }
+            });
+        }
+
+        /// Panics if the current thread holds shard `shard`, locked by `lock`, in a mode that
+        /// makes acquiring it in `wanted` mode wait forever.
+        #[track_caller]
+        pub(crate) fn check_shard(lock: *const (), shard: usize, wanted: Mode) {
+            let conflict = REGISTRY.with(|registry| {
+                registry
+                    .records()
+                    .held
+                    .iter()
+                    .find(|r| r.lock == lock && wanted.waits_on(r.mode))
+                    .map(|r| (r.mode, r.location))
+            });
+
+            if let Some((held, location)) = conflict {
+                panic!(
+                    "deadlock detected: {} locking shard {} at {} while this thread holds a {} lock on it acquired at {}",
+                    wanted,
+                    shard,
+                    Location::caller(),
+                    held,
+                    location,
+                );
+            }
+        }
+    } else {
+        /// Token recording that a lock is held. Zero sized without `deadlock-detection`.
+        #[derive(Debug)]
+        pub(crate) struct Held;
+
+        impl Held {
+            #[inline(always)]
+            pub(crate) fn new(_lock: *const (), _mode: Mode) -> Self {
+                Held
+            }
+
+            #[inline(always)]
+            pub(crate) fn set_mode(&self, _mode: Mode) {}
+        }
+
+        #[inline(always)]
+        pub(crate) fn release_forgotten(_lock: *const (), _mode: Mode) {}
+
+        #[inline(always)]
+        pub(crate) fn check_shard(_lock: *const (), _shard: usize, _wanted: Mode) {}
+    }
+}
+
+#[cfg(all(test, feature = "deadlock-detection"))]
+mod tests {
+    use crate::DashMap;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn panic_message(f: impl FnOnce()) -> String {
+        let err = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+
+        match err.downcast::<String>() {
+            Ok(message) => *message,
+            Err(err) => (*err.downcast::<&str>().unwrap()).to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_insert_while_holding_ref() {
+        let map = DashMap::with_capacity(1);
+        map.insert(1, 1);
+
+        let guard_line = line!() + 1;
+        let guard = map.get(&1).unwrap();
+
+        let insert_line = line!() + 2;
+        let message = panic_message(|| {
+            map.insert(1, 2);
+        });
+        drop(guard);
+
+        assert!(message.starts_with("deadlock detected: write locking shard"));
+        assert!(message.contains(&format!("{}:{}", file!(), insert_line)));
+        assert!(message.contains(&format!(
+            "holds a read lock on it acquired at {}:{}",
+            file!(),
+            guard_line
+        )));
+
+        map.insert(1, 2);
+        assert_eq!(*map.get(&1).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_get_while_holding_ref_mut() {
+        let map = DashMap::new();
+        map.insert(1, 1);
+
+        let guard = map.get_mut(&1).unwrap();
+        let message = panic_message(|| drop(map.get(&1)));
+        drop(guard);
+
+        assert!(message.contains("holds a write lock"));
+    }
+
+    #[test]
+    fn test_len_while_iterating_mutably() {
+        let map = DashMap::new();
+        map.insert(1, 1);
+
+        let message = panic_message(|| {
+            for _ in map.iter_mut() {
+                map.len();
+            }
+        });
+
+        assert!(message.starts_with("deadlock detected: read locking shard"));
+    }
+
+    #[cfg(feature = "fair-lock")]
+    #[test]
+    fn test_get_while_holding_ref_fair() {
+        let map = DashMap::new();
+        map.insert(1, 1);
+
+        let guard = map.get(&1).unwrap();
+        let message = panic_message(|| drop(map.get(&1)));
+        drop(guard);
+
+        assert!(message.starts_with("deadlock detected: read locking shard"));
+        assert!(message.contains("holds a read lock"));
+    }
+
+    #[test]
+    fn test_no_false_positives() {
+        let map: &'static DashMap<i32, i32> = Box::leak(Box::new(DashMap::new()));
+        map.insert(1, 1);
+
+        #[cfg(not(feature = "fair-lock"))]
+        {
+            let a = map.get(&1).unwrap();
+            let b = map.get(&1).unwrap();
+            assert_eq!(map.len(), 1);
+            assert_eq!(*a + *b, 2);
+        }
+
+        let guard = map.get_mut(&1).unwrap();
+        assert!(map.try_get(&1).is_err());
+        std::thread::spawn(move || drop(guard)).join().unwrap();
+
+        map.insert(1, 2);
+        *map.entry(1).or_insert(0) += 1;
+        assert_eq!(*map.get(&1).unwrap(), 3);
+    }
+}
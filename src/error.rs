@@ -0,0 +1,66 @@
+use core::fmt;
+use core::time::Duration;
+
+/// The error returned by the non-blocking `try_*` and timed `*_timeout` methods
+/// when the shard holding the key could not be locked.
+///
+/// Like `std::sync::mpsc::TrySendError`, it hands back whatever was moved into the
+/// call (for example the key and value of `try_insert_now`) so that the caller can retry
+/// or fall back without losing it. Methods that only borrow their arguments use `()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TryLockError<T = ()> {
+    /// The shard was locked and the method was not allowed to wait.
+    WouldBlock(T),
+    /// The shard stayed locked for the whole timeout.
+    TimedOut(T),
+}
+
+impl<T> TryLockError<T> {
+    /// The error for a failed acquisition that waited for `timeout`, if any.
+    pub(crate) fn new(timeout: Option<Duration>, value: T) -> Self {
+        match timeout {
+            Some(_) => TryLockError::TimedOut(value),
+            None => TryLockError::WouldBlock(value),
+        }
+    }
+
+    /// Returns the arguments that were moved into the failed call.
+    pub fn into_inner(self) -> T {
+        match self {
+            TryLockError::WouldBlock(value) | TryLockError::TimedOut(value) => value,
+        }
+    }
+
+    /// Applies `f` to the arguments handed back, keeping the kind of error.
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> TryLockError<U> {
+        match self {
+            TryLockError::WouldBlock(value) => TryLockError::WouldBlock(f(value)),
+            TryLockError::TimedOut(value) => TryLockError::TimedOut(f(value)),
+        }
+    }
+
+    /// Returns `true` if the error was caused by a timeout running out.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, TryLockError::TimedOut(_))
+    }
+}
+
+impl<T> fmt::Debug for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::WouldBlock(_) => "WouldBlock(..)".fmt(f),
+            TryLockError::TimedOut(_) => "TimedOut(..)".fmt(f),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::WouldBlock(_) => "shard is locked".fmt(f),
+            TryLockError::TimedOut(_) => "timed out waiting for the shard lock".fmt(f),
+        }
+    }
+}
+
+impl<T> std::error::Error for TryLockError<T> {}
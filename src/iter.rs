@@ -1,12 +1,12 @@
-use super::mapref::multiple::{RefMulti, RefMutMulti};
+use super::mapref::multiple::{KeyRefMulti, RefMulti, RefMutMulti};
 use super::util;
 use crate::lock::{RwLockReadGuard, RwLockWriteGuard};
+use crate::notify::Observed;
 use crate::t::Map;
 use crate::util::SharedValue;
-use crate::{DashMap, HashMap};
+use crate::{hash_map, DashMap, HashMap};
 use core::hash::{BuildHasher, Hash};
 use core::mem;
-use std::collections::hash_map;
 use std::collections::hash_map::RandomState;
 use std::sync::Arc;
 
@@ -114,6 +114,7 @@ type GuardIterMut<'a, K, V, S> = (
 /// ```
 pub struct Iter<'a, K, V, S = RandomState, M = DashMap<K, V, S>> {
     map: &'a M,
+    _layout: RwLockReadGuard<'a, ()>,
     shard_i: usize,
     current: Option<GuardIter<'a, K, V, S>>,
 }
@@ -137,9 +138,11 @@ where
 }
 
 impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>> Iter<'a, K, V, S, M> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub(crate) fn new(map: &'a M) -> Self {
         Self {
             map,
+            _layout: map._lock_layout(),
             shard_i: 0,
             current: None,
         }
@@ -151,6 +154,7 @@ impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>> Iter
 {
     type Item = RefMulti<'a, K, V, S>;
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             if let Some(current) = self.current.as_mut() {
@@ -192,6 +196,7 @@ impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>> Iter
 /// ```
 pub struct IterMut<'a, K, V, S = RandomState, M = DashMap<K, V, S>> {
     map: &'a M,
+    _layout: RwLockReadGuard<'a, ()>,
     shard_i: usize,
     current: Option<GuardIterMut<'a, K, V, S>>,
 }
@@ -217,9 +222,11 @@ where
 impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>>
     IterMut<'a, K, V, S, M>
 {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub(crate) fn new(map: &'a M) -> Self {
         Self {
             map,
+            _layout: map._lock_layout(),
             shard_i: 0,
             current: None,
         }
@@ -231,6 +238,7 @@ impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>> Iter
 {
     type Item = RefMutMulti<'a, K, V, S>;
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             if let Some(current) = self.current.as_mut() {
@@ -264,6 +272,381 @@ impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone, M: Map<'a, K, V, S>> Iter
     }
 }
 
+/// Iterator over the keys of a DashMap, returned by `DashMap::keys`.
+///
+/// Locks the shards like [`Iter`] does.
+pub struct Keys<'a, K, V, S = RandomState> {
+    inner: Iter<'a, K, V, S, DashMap<K, V, S>>,
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> Keys<'a, K, V, S> {
+    pub(crate) fn new(inner: Iter<'a, K, V, S, DashMap<K, V, S>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> Iterator for Keys<'a, K, V, S> {
+    type Item = KeyRefMulti<'a, K, V, S>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(KeyRefMulti::new)
+    }
+}
+
+/// Iterator over the values of a DashMap, returned by `DashMap::values`.
+///
+/// Locks the shards like [`Iter`] does.
+pub struct Values<'a, K, V, S = RandomState> {
+    inner: Iter<'a, K, V, S, DashMap<K, V, S>>,
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> Values<'a, K, V, S> {
+    pub(crate) fn new(inner: Iter<'a, K, V, S, DashMap<K, V, S>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> Iterator for Values<'a, K, V, S> {
+    type Item = RefMulti<'a, K, V, S>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// Iterator over mutable references to the values of a DashMap, returned by `DashMap::values_mut`.
+///
+/// Locks the shards like [`IterMut`] does.
+pub struct ValuesMut<'a, K, V, S = RandomState> {
+    inner: IterMut<'a, K, V, S, DashMap<K, V, S>>,
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> ValuesMut<'a, K, V, S> {
+    pub(crate) fn new(inner: IterMut<'a, K, V, S, DashMap<K, V, S>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: 'a + BuildHasher + Clone> Iterator for ValuesMut<'a, K, V, S> {
+    type Item = RefMutMulti<'a, K, V, S>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// Iterator emptying a DashMap shard by shard, returned by `DashMap::drain`.
+///
+/// Each shard is emptied under its write lock when the iterator reaches it, and the lock is
+/// released before its entries are yielded. Entries inserted into a shard after it was emptied
+/// stay in the map, as do the shards not reached when the iterator is dropped.
+pub struct Drain<'a, K, V, S = RandomState> {
+    map: &'a DashMap<K, V, S>,
+    _layout: RwLockReadGuard<'a, ()>,
+    shard_i: usize,
+    current: Option<GuardOwningIter<K, V>>,
+    observed: Option<Observed<K, V>>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> Drain<'a, K, V, S> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub(crate) fn new(map: &'a DashMap<K, V, S>) -> Self {
+        Self {
+            map,
+            _layout: map._lock_layout(),
+            shard_i: 0,
+            current: None,
+            observed: map.observers.observe(),
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> Iterator for Drain<'a, K, V, S> {
+    type Item = (K, V);
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = self.current.as_mut() {
+                if let Some((k, v)) = current.next() {
+                    let v = v.into_inner();
+
+                    if let Some(observed) = &self.observed {
+                        observed.removed(&k, &v);
+                    }
+
+                    return Some((k, v));
+                }
+            }
+
+            if self.shard_i == self.map._shard_count() {
+                return None;
+            }
+
+            let mut shard = unsafe { self.map._yield_write_shard(self.shard_i) };
+
+            let map = mem::replace(&mut *shard, HashMap::with_hasher(self.map._hasher()));
+
+            drop(shard);
+
+            self.current = Some(map.into_iter());
+
+            self.shard_i += 1;
+        }
+    }
+}
+
+impl<'a, K, V, S> Drop for Drain<'a, K, V, S> {
+    fn drop(&mut self) {
+        // The rest of the current shard is already out of the map.
+        if let (Some(current), Some(observed)) = (self.current.take(), &self.observed) {
+            for (k, v) in current {
+                observed.removed(&k, v.get());
+            }
+        }
+    }
+}
+
+/// Iterator removing the entries of a DashMap a predicate accepts, returned by `DashMap::extract_if`.
+///
+/// The predicate runs on one shard at a time under its write lock, and the lock is released
+/// before the extracted entries of that shard are yielded. If the iterator is dropped early, the
+/// entries of the current shard that were not yielded yet are put back, and shards not reached
+/// are left untouched.
+pub struct ExtractIf<'a, K: Eq + Hash, V, S: BuildHasher + Clone, F> {
+    shards: ExtractShards<'a, K, V, S>,
+    pred: F,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone, F: FnMut(&K, &mut V) -> bool>
+    ExtractIf<'a, K, V, S, F>
+{
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub(crate) fn new(map: &'a DashMap<K, V, S>, pred: F) -> Self {
+        Self {
+            shards: ExtractShards::new(map),
+            pred,
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone, F: FnMut(&K, &mut V) -> bool> Iterator
+    for ExtractIf<'a, K, V, S, F>
+{
+    type Item = (K, V);
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.shards.next(&mut self.pred)
+    }
+}
+
+/// The shard by shard progress of an extraction, shared by the `extract_if` iterators of maps
+/// and sets, which differ in the predicate they take.
+pub(crate) struct ExtractShards<'a, K: Eq + Hash, V, S: BuildHasher + Clone> {
+    map: &'a DashMap<K, V, S>,
+    _layout: RwLockReadGuard<'a, ()>,
+    shard_i: usize,
+    current: std::vec::IntoIter<(K, V)>,
+    observed: Option<Observed<K, V>>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> ExtractShards<'a, K, V, S> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub(crate) fn new(map: &'a DashMap<K, V, S>) -> Self {
+        Self {
+            map,
+            _layout: map._lock_layout(),
+            shard_i: 0,
+            current: Vec::new().into_iter(),
+            observed: map.observers.observe(),
+        }
+    }
+
+    /// Yields the next entry `pred` accepts, extracting the next shard once the current one is done.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub(crate) fn next(&mut self, pred: &mut impl FnMut(&K, &mut V) -> bool) -> Option<(K, V)> {
+        loop {
+            if let Some((k, v)) = self.current.next() {
+                if let Some(observed) = &self.observed {
+                    observed.removed(&k, &v);
+                }
+
+                return Some((k, v));
+            }
+
+            if self.shard_i == self.map._shard_count() {
+                return None;
+            }
+
+            let mut shard = unsafe { self.map._yield_write_shard(self.shard_i) };
+
+            let extracted = extract_shard(&mut shard, pred);
+
+            drop(shard);
+
+            self.current = extracted.into_iter();
+
+            self.shard_i += 1;
+        }
+    }
+}
+
+/// Where a `DashMap::scan` resumes, serializable with the `serde` feature.
+///
+/// A cursor is a position in an order derived from the hashes of the keys, so it is only
+/// meaningful for the map that returned it: maps do not share hashers, and a map's default
+/// hasher is seeded anew when it is created. Any cursor is safe to pass to `scan`, including one
+/// deserialized from untrusted input; a foreign cursor merely skips or repeats entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cursor(u64);
+
+impl Cursor {
+    /// The cursor a scan over the whole map starts from.
+    pub const START: Cursor = Cursor(0);
+
+    pub(crate) fn new(position: u64) -> Self {
+        Cursor(position)
+    }
+
+    pub(crate) fn position(self) -> u64 {
+        self.0
+    }
+}
+
+/// Iterator cloning the entries of a DashMap out in batches, returned by `DashMap::iter_batched`.
+///
+/// Shards are only read locked while a batch is cloned, so a slow consumer does not hold up
+/// writers. Entries present for the whole iteration are yielded exactly once.
+pub struct IterBatched<'a, K, V, S = RandomState> {
+    map: &'a DashMap<K, V, S>,
+    batch_size: usize,
+    /// The scan position the next batch starts at, `None` once the end of the map was reached.
+    next: Option<u64>,
+    batch: std::vec::IntoIter<(K, V)>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone> IterBatched<'a, K, V, S> {
+    pub(crate) fn new(map: &'a DashMap<K, V, S>, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be greater than 0");
+
+        Self {
+            map,
+            batch_size,
+            next: Some(0),
+            batch: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone> Iterator
+    for IterBatched<'a, K, V, S>
+{
+    type Item = (K, V);
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.batch.next() {
+                return Some(entry);
+            }
+
+            let from = self.next?;
+            let mut batch = Vec::with_capacity(self.batch_size);
+
+            self.next = self.map.scan_from(from, self.batch_size, &mut batch);
+            self.batch = batch.into_iter();
+        }
+    }
+}
+
+/// Moves the entries `pred` accepts out of `shard`, leaving the others in place.
+#[cfg(feature = "hashbrown")]
+fn extract_shard<K: Eq + Hash, V, S: BuildHasher>(
+    shard: &mut HashMap<K, V, S>,
+    pred: &mut impl FnMut(&K, &mut V) -> bool,
+) -> Vec<(K, V)> {
+    shard
+        .extract_if(|k, v| pred(k, v.get_mut()))
+        .map(|(k, v)| (k, v.into_inner()))
+        .collect()
+}
+
+/// Moves the entries `pred` accepts out of `shard`, leaving the others in place.
+///
+/// The shard is rebuilt from its entries, since the std map can not move values out of `retain`.
+/// If `pred` panics, the entries not visited yet and the one being visited are put back.
+#[cfg(not(feature = "hashbrown"))]
+fn extract_shard<K: Eq + Hash, V, S: BuildHasher + Clone>(
+    shard: &mut HashMap<K, V, S>,
+    pred: &mut impl FnMut(&K, &mut V) -> bool,
+) -> Vec<(K, V)> {
+    struct Refill<'s, K: Eq + Hash, V, S: BuildHasher> {
+        shard: &'s mut HashMap<K, V, S>,
+        rest: GuardOwningIter<K, V>,
+        visiting: Option<(K, SharedValue<V>)>,
+    }
+
+    impl<'s, K: Eq + Hash, V, S: BuildHasher> Drop for Refill<'s, K, V, S> {
+        fn drop(&mut self) {
+            for (k, v) in self.visiting.take().into_iter().chain(&mut self.rest) {
+                self.shard.insert(k, v);
+            }
+        }
+    }
+
+    let empty = HashMap::with_capacity_and_hasher(shard.capacity(), shard.hasher().clone());
+    let taken = mem::replace(shard, empty);
+
+    let mut refill = Refill {
+        shard,
+        rest: taken.into_iter(),
+        visiting: None,
+    };
+
+    let mut extracted = Vec::new();
+
+    for entry in refill.rest.by_ref() {
+        refill.visiting = Some(entry);
+
+        let (k, v) = refill.visiting.as_mut().unwrap();
+        let extract = pred(k, v.get_mut());
+        let (k, v) = refill.visiting.take().unwrap();
+
+        if extract {
+            extracted.push((k, v.into_inner()));
+        } else {
+            refill.shard.insert(k, v);
+        }
+    }
+
+    extracted
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> Drop for ExtractShards<'a, K, V, S> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn drop(&mut self) {
+        if self.current.len() == 0 {
+            return;
+        }
+
+        // The entries not yielded go back into the shard they were extracted from, which the
+        // layout lock still pins. A key inserted again meanwhile keeps its new value, as if it
+        // had been inserted after the put back.
+        let mut shard = unsafe { self.map._yield_write_shard(self.shard_i - 1) };
+
+        for (k, v) in &mut self.current {
+            if let hash_map::Entry::Vacant(entry) = shard.entry(k) {
+                entry.insert(SharedValue::new(v));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::DashMap;
@@ -1,6 +1,8 @@
+use crate::iter::ExtractShards;
 use crate::setref::multiple::RefMulti;
 use crate::t::Map;
 use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
 
 pub struct OwningIter<K, S> {
     inner: crate::iter::OwningIter<K, (), S>,
@@ -65,7 +67,80 @@ impl<'a, K: Eq + Hash, S: 'a + BuildHasher + Clone, M: Map<'a, K, (), S>> Iterat
 {
     type Item = RefMulti<'a, K, S>;
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next().map(RefMulti::new)
     }
 }
+
+/// Iterator removing every key of a DashSet, returned by `DashSet::drain`.
+///
+/// Locks the shards like [`crate::iter::Drain`] does.
+pub struct Drain<'a, K, S = RandomState> {
+    inner: crate::iter::Drain<'a, K, (), S>,
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher + Clone> Drain<'a, K, S> {
+    pub(crate) fn new(inner: crate::iter::Drain<'a, K, (), S>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher + Clone> Iterator for Drain<'a, K, S> {
+    type Item = K;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
+
+/// Iterator removing the keys of a DashSet a predicate accepts, returned by `DashSet::extract_if`.
+///
+/// Locks the shards and puts back the keys not yielded like [`crate::iter::ExtractIf`] does.
+pub struct ExtractIf<'a, K: Eq + Hash, S: BuildHasher + Clone, F> {
+    shards: ExtractShards<'a, K, (), S>,
+    pred: F,
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher + Clone, F: FnMut(&K) -> bool> ExtractIf<'a, K, S, F> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub(crate) fn new(shards: ExtractShards<'a, K, (), S>, pred: F) -> Self {
+        Self { shards, pred }
+    }
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher + Clone, F: FnMut(&K) -> bool> Iterator
+    for ExtractIf<'a, K, S, F>
+{
+    type Item = K;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+
+        self.shards.next(&mut |k, _| pred(k)).map(|(k, _)| k)
+    }
+}
+
+/// Iterator cloning the keys of a DashSet out in batches, returned by `DashSet::iter_batched`.
+///
+/// Locks the shards like [`crate::iter::IterBatched`] does.
+pub struct IterBatched<'a, K, S = RandomState> {
+    inner: crate::iter::IterBatched<'a, K, (), S>,
+}
+
+impl<'a, K: Eq + Hash + Clone, S: BuildHasher + Clone> IterBatched<'a, K, S> {
+    pub(crate) fn new(inner: crate::iter::IterBatched<'a, K, (), S>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, S: BuildHasher + Clone> Iterator for IterBatched<'a, K, S> {
+    type Item = K;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
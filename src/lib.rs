@@ -1,15 +1,26 @@
 #![allow(clippy::type_complexity)]
 
+pub mod bounded;
+pub mod cas;
+mod deadlock;
+mod error;
 pub mod iter;
 pub mod iter_set;
 pub mod lock;
 pub mod mapref;
+mod notify;
+mod pending;
 mod read_only;
+mod read_only_set;
 #[cfg(feature = "serde")]
 mod serde;
 mod set;
 pub mod setref;
+mod shard;
 mod t;
+mod table;
+mod transaction;
+pub mod ttl;
 mod util;
 
 #[cfg(feature = "rayon")]
@@ -18,6 +29,8 @@ pub mod rayon {
     pub mod set;
 }
 
+pub use bounded::BoundedMap;
+use cas::{CompareAndSwap, InsertIfAbsent, RemoveIfEq, Replace, UpdateIf};
 use cfg_if::cfg_if;
 use core::borrow::Borrow;
 use core::fmt;
@@ -25,15 +38,34 @@ use core::future::Future;
 use core::hash::{BuildHasher, Hash, Hasher};
 use core::iter::FromIterator;
 use core::ops::{BitAnd, BitOr, Shl, Shr, Sub};
-use iter::{Iter, IterMut, OwningIter};
-use lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use mapref::entry::{Entry, OccupiedEntry, VacantEntry};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
+use deadlock::Mode;
+pub use error::TryLockError;
+use iter::{
+    Cursor, Drain, ExtractIf, Iter, IterBatched, IterMut, Keys, OwningIter, Values, ValuesMut,
+};
+use lock::{RwLock, RwLockReadGuard, RwLockUpgradeableGuard, RwLockWriteGuard};
+use mapref::entry::{Entry, OccupiedEntry, OccupiedError, VacantEntry};
 use mapref::multiple::RefMulti;
 use mapref::one::{Ref, RefMut};
+#[cfg(feature = "hashbrown")]
+use mapref::raw_entry::{RawEntryBuilder, RawEntryBuilderMut};
+use mapref::upgradeable::{UpgradeableEntry, UpgradeableOccupiedEntry, UpgradeableVacantEntry};
+use notify::{Change, Observers, Pending};
+pub use notify::{Event, SubscriptionId};
+use pending::PendingKeys;
 pub use read_only::ReadOnlyView;
-pub use set::DashSet;
+pub use read_only_set::ReadOnlySetView;
+pub use set::{DashSet, SetOperand};
+use shard::HashedShard;
 use std::collections::hash_map::RandomState;
+use std::sync::Arc;
+use std::time::Instant;
 pub use t::Map;
+use table::Table;
+pub use transaction::Transaction;
+pub use ttl::TtlMap;
 
 cfg_if! {
     if #[cfg(feature = "raw-api")] {
@@ -43,14 +75,55 @@ cfg_if! {
     }
 }
 
-pub(crate) type HashMap<K, V, S> = std::collections::HashMap<K, SharedValue<V>, S>;
+cfg_if! {
+    if #[cfg(feature = "hashbrown")] {
+        use hashbrown::hash_map;
+    } else {
+        use std::collections::hash_map;
+    }
+}
 
-fn shard_amount() -> usize {
+pub(crate) type HashMap<K, V, S> = hash_map::HashMap<K, SharedValue<V>, S>;
+
+/// How many items `Extend` and `FromIterator` insert per batch.
+const EXTEND_BATCH_SIZE: usize = 1024;
+
+fn default_shard_amount() -> usize {
     (num_cpus::get() * 4).next_power_of_two()
 }
 
-fn ncb(shard_amount: usize) -> usize {
-    shard_amount.trailing_zeros() as usize
+fn assert_shard_amount(shard_amount: usize) {
+    assert!(shard_amount > 1, "shard_amount must be greater than 1");
+    assert!(
+        shard_amount.is_power_of_two(),
+        "shard_amount must be a power of two"
+    );
+}
+
+fn new_table<K, V, S: Clone>(
+    mut capacity: usize,
+    hasher: &S,
+    shard_amount: usize,
+) -> Table<K, V, S> {
+    if capacity != 0 {
+        capacity = (capacity + (shard_amount - 1)) & !(shard_amount - 1);
+    }
+
+    let cps = capacity / shard_amount;
+
+    let shards = (0..shard_amount)
+        .map(|_| new_shard(HashMap::with_capacity_and_hasher(cps, hasher.clone())))
+        .collect();
+
+    Table::new(shards)
+}
+
+fn new_shard<K, V, S>(shard: HashMap<K, V, S>) -> RwLock<HashMap<K, V, S>> {
+    if cfg!(feature = "fair-lock") {
+        RwLock::new_fair(shard)
+    } else {
+        RwLock::new(shard)
+    }
 }
 
 /// DashMap is an implementation of a concurrent associative array/hashmap in Rust.
@@ -64,27 +137,385 @@ fn ncb(shard_amount: usize) -> usize {
 ///
 /// Documentation mentioning locking behaviour acts in the reference frame of the calling thread.
 /// This means that it is safe to ignore it across multiple threads.
+///
+/// By default every operation hashes its key twice, once to pick the shard and once more inside
+/// the std map of the shard. With the `hashbrown` feature the first hash is reused, so expensive
+/// keys such as long strings are only hashed once.
+///
+/// With the `fair-lock` feature, readers wait whenever a writer is waiting on their shard. Then
+/// even the operations documented as safe while holding a reference, such as `get` or `iter`,
+/// may deadlock if called when holding any sort of reference into the map, as soon as another
+/// thread tries to write to the same shard.
 pub struct DashMap<K, V, S = RandomState> {
-    shift: usize,
-    shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    /// The first table on the chain of tables holding entries, see the `table` module.
+    current: AtomicPtr<Table<K, V, S>>,
+    /// Every table the map has used, oldest first. Retired tables are kept until the map is
+    /// dropped since concurrent operations may still be walking through them.
+    /// Resizes hold the write lock for their whole duration.
+    /// The tables are boxed so that their addresses stay put when the list grows.
+    #[allow(clippy::vec_box)]
+    tables: RwLock<Vec<Box<Table<K, V, S>>>>,
+    /// Held for reading by operations visiting every shard, so that no entry moves between
+    /// shards under them, and for writing by every step of a resize.
+    layout: RwLock<()>,
     hasher: S,
+    pending: PendingKeys,
+    observers: Observers<K, V>,
 }
 
-impl<K: Eq + Hash + Clone, V: Clone, S: Clone> Clone for DashMap<K, V, S> {
-    fn clone(&self) -> Self {
-        let mut inner_shards = Vec::new();
+impl<K, V, S> DashMap<K, V, S> {
+    /// Creates a map from a chain of tables, the first of which becomes the current one.
+    #[allow(clippy::vec_box)]
+    fn from_tables(tables: Vec<Box<Table<K, V, S>>>, hasher: S) -> Self {
+        let current = AtomicPtr::new(&*tables[0] as *const Table<K, V, S> as *mut _);
+
+        Self {
+            current,
+            tables: RwLock::new(tables),
+            layout: RwLock::new(()),
+            hasher,
+            pending: PendingKeys::default(),
+            observers: Observers::new(),
+        }
+    }
+
+    fn table(&self) -> &Table<K, V, S> {
+        unsafe { &*self.current.load(Ordering::Acquire) }
+    }
 
-        for shard in self.shards.iter() {
-            let shard = shard.read();
+    /// The number of shards over every table on the chain.
+    ///
+    /// Only stable while the layout is locked or the map is not shared.
+    fn shard_count(&self) -> usize {
+        self.table().chain().map(|t| t.shards.len()).sum()
+    }
 
-            inner_shards.push(RwLock::new((*shard).clone()));
+    /// Finds shard `i`, counted over the shards of every table on the chain.
+    ///
+    /// Only stable while the layout is locked or the map is not shared.
+    fn shard(&self, mut i: usize) -> &RwLock<HashMap<K, V, S>> {
+        for table in self.table().chain() {
+            match table.shards.get(i) {
+                Some(shard) => return shard,
+                None => i -= table.shards.len(),
+            }
         }
 
-        Self {
-            shift: self.shift,
-            shards: inner_shards.into_boxed_slice(),
-            hasher: self.hasher.clone(),
+        panic!("shard index out of bounds");
+    }
+
+    /// The index of the shard holding `hash`, counted like in [`shard`](DashMap::shard).
+    ///
+    /// Only stable while the layout is locked or the map is not shared.
+    fn shard_index_for(&self, hash: u64) -> usize {
+        let mut offset = 0;
+
+        for table in self.table().chain() {
+            let i = table.determine_shard(hash as usize);
+
+            if !table.is_migrated(i) {
+                return offset + i;
+            }
+
+            offset += table.shards.len();
+        }
+
+        panic!("migrated shard without a next table");
+    }
+
+    /// Groups `items` by the shard holding the key `hash` returns for them, in shard order.
+    /// Each item is paired with its position in `items` and its hash, and keeps its order within the group.
+    ///
+    /// Only stable while the layout is locked.
+    fn group_by_shard<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        hash: impl Fn(&T) -> u64,
+    ) -> Vec<(usize, Vec<(usize, u64, T)>)> {
+        let mut tagged: Vec<(usize, usize, u64, T)> = items
+            .into_iter()
+            .enumerate()
+            .map(|(pos, item)| {
+                let hash = hash(&item);
+
+                (self.shard_index_for(hash), pos, hash, item)
+            })
+            .collect();
+
+        tagged.sort_by_key(|&(i, _, _, _)| i);
+
+        let mut groups: Vec<(usize, Vec<(usize, u64, T)>)> = Vec::new();
+
+        for (i, pos, hash, item) in tagged {
+            match groups.last_mut() {
+                Some((j, group)) if *j == i => group.push((pos, hash, item)),
+                _ => groups.push((i, vec![(pos, hash, item)])),
+            }
+        }
+
+        groups
+    }
+
+    /// Every shard of every table on the chain.
+    ///
+    /// Only stable while the layout is locked or the map is not shared.
+    #[cfg(feature = "rayon")]
+    fn all_shards(&self) -> Vec<&RwLock<HashMap<K, V, S>>> {
+        self.table()
+            .chain()
+            .flat_map(|table| table.shards.iter())
+            .collect()
+    }
+
+    /// Read locks shard `i`, checking for self-deadlock when `deadlock-detection` is enabled.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn read_shard(&self, i: usize) -> RwLockReadGuard<'_, HashMap<K, V, S>> {
+        let shard = self.shard(i);
+
+        deadlock::check_shard(shard.id(), i, Mode::Read);
+
+        shard.read()
+    }
+
+    /// Write locks shard `i`, checking for self-deadlock when `deadlock-detection` is enabled.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn write_shard(&self, i: usize) -> RwLockWriteGuard<'_, HashMap<K, V, S>> {
+        let shard = self.shard(i);
+
+        deadlock::check_shard(shard.id(), i, Mode::Write);
+
+        shard.write()
+    }
+
+    /// Read locks the shard holding `hash`, following the chain past migrated shards.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn read_shard_for(&self, hash: u64) -> RwLockReadGuard<'_, HashMap<K, V, S>> {
+        let mut table = self.table();
+
+        loop {
+            let i = table.determine_shard(hash as usize);
+            let shard = &table.shards[i];
+
+            deadlock::check_shard(shard.id(), i, Mode::Read);
+
+            let guard = shard.read();
+
+            if !table.is_migrated(i) {
+                return guard;
+            }
+
+            table = table.next().expect("migrated shard without a next table");
+        }
+    }
+
+    /// Write locks the shard holding `hash`, following the chain past migrated shards.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn write_shard_for(&self, hash: u64) -> RwLockWriteGuard<'_, HashMap<K, V, S>> {
+        let mut table = self.table();
+
+        loop {
+            let i = table.determine_shard(hash as usize);
+            let shard = &table.shards[i];
+
+            deadlock::check_shard(shard.id(), i, Mode::Write);
+
+            let guard = shard.write();
+
+            if !table.is_migrated(i) {
+                return guard;
+            }
+
+            table = table.next().expect("migrated shard without a next table");
+        }
+    }
+
+    /// Upgradeable read locks the shard holding `hash`, following the chain past migrated shards.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn upgradeable_shard_for(&self, hash: u64) -> RwLockUpgradeableGuard<'_, HashMap<K, V, S>> {
+        let mut table = self.table();
+
+        loop {
+            let i = table.determine_shard(hash as usize);
+            let shard = &table.shards[i];
+
+            // Checked as a write, since callers upgrade the guard when they go on to modify the shard.
+            deadlock::check_shard(shard.id(), i, Mode::Write);
+
+            let guard = shard.upgradeable_read();
+
+            if !table.is_migrated(i) {
+                return guard;
+            }
+
+            table = table.next().expect("migrated shard without a next table");
+        }
+    }
+
+    /// Like `read_shard_for` and `write_shard_for`, but gives up as soon as `lock` fails.
+    fn try_lock_shard_for<'s, G>(
+        &'s self,
+        hash: u64,
+        mut lock: impl FnMut(&'s RwLock<HashMap<K, V, S>>) -> Option<G>,
+    ) -> Option<G> {
+        let mut table = self.table();
+
+        loop {
+            let i = table.determine_shard(hash as usize);
+            let guard = lock(&table.shards[i])?;
+
+            if !table.is_migrated(i) {
+                return Some(guard);
+            }
+
+            drop(guard);
+
+            table = table.next().expect("migrated shard without a next table");
+        }
+    }
+
+    /// Tries to read lock the shard holding `hash`, waiting at most `timeout` or not at all if `None`.
+    fn try_read_shard_for(
+        &self,
+        hash: u64,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockReadGuard<'_, HashMap<K, V, S>>> {
+        self.try_lock_shard_for(hash, |shard| match timeout {
+            Some(timeout) => shard.try_read_for(timeout),
+            None => shard.try_read(),
+        })
+    }
+
+    /// Tries to write lock the shard holding `hash`, waiting at most `timeout` or not at all if `None`.
+    fn try_write_shard_for(
+        &self,
+        hash: u64,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockWriteGuard<'_, HashMap<K, V, S>>> {
+        self.try_lock_shard_for(hash, |shard| match timeout {
+            Some(timeout) => shard.try_write_for(timeout),
+            None => shard.try_write(),
+        })
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> DashMap<K, V, S> {
+    /// Copies the chain of tables, taking the contents of shard `i` from `copy_shard(i)`.
+    ///
+    /// The caller has to hold the layout lock.
+    fn copy_tables(&self, mut copy_shard: impl FnMut(usize) -> HashMap<K, V, S>) -> Self {
+        let mut tables = Vec::new();
+        let mut i = 0;
+
+        // A resize in progress is copied as is and finished by the next resize of the copy.
+        for table in self.table().chain() {
+            let mut inner_shards = Vec::with_capacity(table.shards.len());
+
+            for _ in 0..table.shards.len() {
+                inner_shards.push(new_shard(copy_shard(i)));
+
+                i += 1;
+            }
+
+            let cloned = Table::new(inner_shards.into_boxed_slice());
+
+            for j in 0..table.shards.len() {
+                if table.is_migrated(j) {
+                    cloned.set_migrated(j);
+                }
+            }
+
+            tables.push(Box::new(cloned));
+        }
+
+        for pair in tables.windows(2) {
+            pair[0].set_next(&pair[1]);
+        }
+
+        Self::from_tables(tables, self.hasher.clone())
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher + Clone> DashMap<K, V, S> {
+    /// Clones the entries from scan position `from` on into `batch` until it holds `limit` of
+    /// them, read locking one shard at a time. Entries sharing a position are never split up, so
+    /// `batch` may end up slightly longer than `limit`.
+    ///
+    /// Returns the position to resume from, or `None` if the scan reached the end of the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn scan_from(&self, mut from: u64, limit: usize, batch: &mut Vec<(K, V)>) -> Option<u64> {
+        let _layout = self.layout.read();
+
+        loop {
+            let (i, end) = self.scan_shard_for(from);
+            let shard = self.read_shard(i);
+
+            let mut found: Vec<(u64, &K, &V)> = shard
+                .iter()
+                .map(|(k, v)| (table::scan_position(self.hash_u64(k)), k, v.get()))
+                .filter(|&(pos, _, _)| pos >= from && !matches!(end, Some(end) if pos >= end))
+                .collect();
+
+            let room = limit - batch.len();
+
+            if found.len() > room {
+                // Finds the position of the last entry that fits without sorting the rest.
+                let (_, &mut (last, _, _), _) =
+                    found.select_nth_unstable_by_key(room - 1, |&(pos, _, _)| pos);
+
+                batch.extend(
+                    found
+                        .iter()
+                        .filter(|&&(pos, _, _)| pos <= last)
+                        .map(|&(_, k, v)| (k.clone(), v.clone())),
+                );
+
+                return last.checked_add(1);
+            }
+
+            batch.extend(found.into_iter().map(|(_, k, v)| (k.clone(), v.clone())));
+
+            from = end?;
+
+            if batch.len() == limit {
+                return Some(from);
+            }
+        }
+    }
+
+    /// The index of the shard holding scan position `pos`, counted like in
+    /// [`shard`](DashMap::shard), and the position where the entries it holds from `pos` on end.
+    ///
+    /// Only stable while the layout is locked.
+    fn scan_shard_for(&self, pos: u64) -> (usize, Option<u64>) {
+        let mut offset = 0;
+        let mut end: Option<u64> = None;
+
+        for table in self.table().chain() {
+            let (i, table_end) = table.scan_shard(pos);
+
+            // Earlier tables may have split the range further; their shards are migrated.
+            end = match (end, table_end) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+            if !table.is_migrated(i) {
+                return (offset + i, end);
+            }
+
+            offset += table.shards.len();
         }
+
+        panic!("migrated shard without a next table");
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: Clone> Clone for DashMap<K, V, S> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn clone(&self) -> Self {
+        let _layout = self.layout.read();
+
+        self.copy_tables(|i| (*self.read_shard(i)).clone())
     }
 }
 
@@ -98,7 +529,7 @@ where
     }
 }
 
-impl<'a, K: 'a + Eq + Hash, V: 'a> DashMap<K, V, RandomState> {
+impl<K: Eq + Hash, V> DashMap<K, V, RandomState> {
     /// Creates a new DashMap with a capacity of 0.
     ///
     /// # Examples
@@ -127,14 +558,87 @@ impl<'a, K: 'a + Eq + Hash, V: 'a> DashMap<K, V, RandomState> {
     pub fn with_capacity(capacity: usize) -> Self {
         DashMap::with_capacity_and_hasher(capacity, RandomState::default())
     }
+
+    /// Creates a new DashMap with a capacity of 0 split into `shard_amount` shards.
+    ///
+    /// By default the number of shards depends on the number of CPUs. Fewer shards save memory
+    /// for small maps, more shards reduce contention on heavily shared ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let mappings = DashMap::with_shard_amount(4);
+    /// mappings.insert(2, 4);
+    /// mappings.insert(8, 16);
+    /// ```
+    pub fn with_shard_amount(shard_amount: usize) -> Self {
+        DashMap::with_capacity_and_hasher_and_shard_amount(0, RandomState::default(), shard_amount)
+    }
 }
 
 impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// Wraps this `DashMap` into a read-only view. This view allows to obtain raw references to the stored values.
     pub fn into_read_only(self) -> ReadOnlyView<K, V, S> {
+        // The view indexes shards by hash, so a resize left pending by `clone` has to be finished.
+        self.complete_migration(None);
+
         ReadOnlyView::new(self)
     }
 
+    /// Copies the map as it was at a single instant, unlike `clone` and `iter`,
+    /// which visit one shard at a time and may observe some writes that happen meanwhile but not others.
+    ///
+    /// Every shard is read locked for the duration of the copy, so writers on all shards wait for it.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let balances = DashMap::new();
+    /// balances.insert("alice", 30);
+    /// balances.insert("bob", 70);
+    ///
+    /// let snapshot = balances.snapshot();
+    /// balances.insert("carol", 10);
+    ///
+    /// assert_eq!(snapshot.len(), 2);
+    /// assert_eq!(snapshot.values().sum::<i32>(), 100);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn snapshot(&self) -> ReadOnlyView<K, V, S>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let copy = {
+            let _layout = self.layout.read();
+
+            // No operation waits for a shard while holding another, so this can not deadlock
+            // against the map itself.
+            let shards: Vec<_> = (0..self.shard_count())
+                .map(|i| self.read_shard(i))
+                .collect();
+
+            // A concurrent `reshard` may link another table meanwhile. It stays empty, since
+            // moving entries over needs the layout lock.
+            self.copy_tables(|i| match shards.get(i) {
+                Some(shard) => (**shard).clone(),
+                None => HashMap::with_hasher(self.hasher.clone()),
+            })
+        };
+
+        copy.into_read_only()
+    }
+
     /// Creates a new DashMap with a capacity of 0 and the provided hasher.
     ///
     /// # Examples
@@ -164,29 +668,42 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// mappings.insert(2, 4);
     /// mappings.insert(8, 16);
     /// ```
-    pub fn with_capacity_and_hasher(mut capacity: usize, hasher: S) -> Self {
-        let shard_amount = shard_amount();
-        let shift = util::ptr_size_bits() - ncb(shard_amount);
-
-        if capacity != 0 {
-            capacity = (capacity + (shard_amount - 1)) & !(shard_amount - 1);
-        }
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self::with_capacity_and_hasher_and_shard_amount(capacity, hasher, default_shard_amount())
+    }
 
-        let cps = capacity / shard_amount;
+    /// Creates a new DashMap with a specified starting capacity and hasher, split into `shard_amount` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let s = RandomState::new();
+    /// let mappings = DashMap::with_capacity_and_hasher_and_shard_amount(2, s, 32);
+    /// mappings.insert(2, 4);
+    /// mappings.insert(8, 16);
+    /// ```
+    pub fn with_capacity_and_hasher_and_shard_amount(
+        capacity: usize,
+        hasher: S,
+        shard_amount: usize,
+    ) -> Self {
+        assert_shard_amount(shard_amount);
 
-        let shards = (0..shard_amount)
-            .map(|_| RwLock::new(HashMap::with_capacity_and_hasher(cps, hasher.clone())))
-            .collect();
+        let table = new_table(capacity, &hasher, shard_amount);
 
-        Self {
-            shift,
-            shards,
-            hasher,
-        }
+        Self::from_tables(vec![Box::new(table)], hasher)
     }
 
     /// Hash a given item to produce a usize.
     /// Uses the provided or default HashBuilder.
+    #[allow(clippy::manual_hash_one)]
     pub fn hash_usize<T: Hash>(&self, item: &T) -> usize {
         let mut hasher = self.hasher.build_hasher();
 
@@ -195,11 +712,25 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         hasher.finish() as usize
     }
 
+    /// Hash a given item to produce the full 64 bit hash, as taken by the raw entry API.
+    /// Uses the provided or default HashBuilder.
+    #[allow(clippy::manual_hash_one)]
+    pub fn hash_u64<T: Hash>(&self, item: &T) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+
+        item.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
     cfg_if! {
         if #[cfg(feature = "raw-api")] {
             /// Allows you to peek at the inner shards that store your data.
             /// You should probably not use this unless you know what you are doing.
             ///
+            /// While a [`reshard`](DashMap::reshard) is in progress, these are the shards of the
+            /// table entries are moved out of. The entries moved already are not in any of them.
+            ///
             /// Requires the `raw-api` feature to be enabled.
             ///
             /// # Examples
@@ -211,12 +742,12 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
             /// println!("Amount of shards: {}", map.shards().len());
             /// ```
             pub fn shards(&self) -> &[RwLock<HashMap<K, V, S>>] {
-                &self.shards
+                &self.table().shards
             }
         } else {
             #[allow(dead_code)]
             pub(crate) fn shards(&self) -> &[RwLock<HashMap<K, V, S>>] {
-                &self.shards
+                &self.table().shards
             }
         }
     }
@@ -227,6 +758,9 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
             /// You should probably not use this unless you know what you are doing.
             /// Note that shard selection is dependent on the default or provided HashBuilder.
             ///
+            /// The index is into [`shards`](DashMap::shards), so while a `reshard` is in progress
+            /// the key may have been moved out of that shard already.
+            ///
             /// Requires the `raw-api` feature to be enabled.
             ///
             /// # Examples
@@ -253,6 +787,9 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         if #[cfg(feature = "raw-api")] {
             /// Finds which shard a certain hash is stored in.
             ///
+            /// Like [`determine_map`](DashMap::determine_map), this indexes into
+            /// [`shards`](DashMap::shards) and is only accurate while no `reshard` is in progress.
+            ///
             /// Requires the `raw-api` feature to be enabled.
             ///
             /// # Examples
@@ -266,14 +803,12 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
             /// println!("hash is stored in shard: {}", map.determine_shard(hash));
             /// ```
             pub fn determine_shard(&self, hash: usize) -> usize {
-                // Leave the high 7 bits for the HashBrown SIMD tag.
-                (hash << 7) >> self.shift
+                self.table().determine_shard(hash)
             }
         } else {
 
             pub(crate) fn determine_shard(&self, hash: usize) -> usize {
-                // Leave the high 7 bits for the HashBrown SIMD tag.
-                (hash << 7) >> self.shift
+                self.table().determine_shard(hash)
             }
         }
     }
@@ -308,14 +843,87 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// let map = DashMap::new();
     /// map.insert("I am the key!", "And I am the value!");
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn insert(&self, key: K, value: V) -> Option<V> {
         self._insert(key, value)
     }
 
+    /// Inserts a key and a value into the map if its shard can be locked right away.
+    /// On failure the key and value are handed back inside the error.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::{DashMap, TryLockError};
+    ///
+    /// let map = DashMap::new();
+    /// assert_eq!(map.try_insert_now("apple", 1).unwrap(), None);
+    ///
+    /// let guard = map.get("apple").unwrap();
+    /// match map.try_insert_now("apple", 2) {
+    ///     Err(TryLockError::WouldBlock((key, value))) => assert_eq!((key, value), ("apple", 2)),
+    ///     _ => unreachable!(),
+    /// }
+    /// drop(guard);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_insert_now(&'a self, key: K, value: V) -> Result<Option<V>, TryLockError<(K, V)>> {
+        self._try_insert_now(key, value, None)
+    }
+
+    /// Inserts a key and a value into the map, waiting at most `timeout` for its shard.
+    /// On failure the key and value are handed back inside the error.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_timeout(
+        &'a self,
+        key: K,
+        value: V,
+        timeout: Duration,
+    ) -> Result<Option<V>, TryLockError<(K, V)>> {
+        self._try_insert_now(key, value, Some(timeout))
+    }
+
+    /// Inserts a key and a value into the map unless the key is already present, in which case
+    /// nothing is changed and the error holds the entry of the present element and the value.
+    ///
+    /// Unlike [`try_insert_now`](DashMap::try_insert_now) this waits for the shard like [`insert`](DashMap::insert)
+    /// does; the only failure is an occupied key.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let ids = DashMap::new();
+    /// assert_eq!(*ids.try_insert("alice", 1).unwrap(), 1);
+    ///
+    /// let err = ids.try_insert("alice", 2).err().unwrap();
+    /// assert_eq!(*err.entry.get(), 1);
+    /// assert_eq!(err.value, 2);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_insert(
+        &'a self,
+        key: K,
+        value: V,
+    ) -> Result<RefMut<'a, K, V, S>, OccupiedError<'a, K, V, S>> {
+        match self._entry(key) {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
     /// Inserts a key and a value into the map. After insert the value, f will execute before return.
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn insert_with<T, E>(
         &self,
         key: K,
@@ -330,6 +938,7 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn insert_and_post_process<T1, E1, T2, E2, T3, E3>(
         &self,
         key: K,
@@ -351,7 +960,9 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
-    /// It's the same as insert_and_post_process besides the function are async
+    /// It's the same as insert_and_post_process besides the function are async.
+    /// The shard lock is held while the futures are awaited, see
+    /// `insert_and_post_process_async_fn_unlocked` for a variant that releases it.
     pub async fn insert_and_post_process_async_fn<T1, E1, T2, E2, T3, E3, Fut1, Fut2, Fut3>(
         &self,
         key: K,
@@ -370,14 +981,14 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         Fut2: Future<Output = Result<T2, E2>>,
         Fut3: Future<Output = Result<T3, E3>>,
     {
-        let hash = self.hash_usize(&key);
+        let insertion = self.observers.insertion(&key, &value);
 
-        let idx = self.determine_shard(hash);
+        let hash = self.hash_u64(&key);
 
-        let mut shard = unsafe { self._yield_write_shard(idx) };
+        let mut shard = self.write_shard_for(hash);
 
         let retv = shard
-            .insert(key, SharedValue::new(value))
+            .insert_hashed(hash, key, SharedValue::new(value))
             .map(|v| v.into_inner());
 
         let (key_exists_ret, not_exists_ret) = if let Some(ref prev_v) = retv {
@@ -393,22 +1004,95 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         } else {
             None
         };
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(retv.as_ref());
+        }
+
         (retv, key_exists_ret, not_exists_ret, post_ret)
     }
 
-    /// Removes an entry from the map, returning the key and value if they existed in the map.
+    /// Inserts a key and a value into the map. After insert the value, execute key_exists_func if
+    /// key already present before this insert or not_exists_func if key is newly insert.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    /// Unlike `insert_and_post_process_async_fn` the shard lock is released before any of the
+    /// futures are awaited, so other tasks hashing to the same shard are not left spinning.
+    /// Instead the key is marked as pending until `post_func` has completed. Other `*_unlocked`
+    /// async methods called on the same key wait for the mark to clear, so they observe the insert
+    /// and its post-processing as a single step.
     ///
-    /// # Examples
+    /// **Only `*_unlocked` methods respect the mark.** Every other method, such as `get`, `insert`,
+    /// `entry` or the locked async variants, sees the new value as soon as it is inserted, which
+    /// may be before `post_func` has run or while it is still being awaited. Callers that need the
+    /// post-processing to be atomic with the insert must access the key through `*_unlocked`
+    /// methods only.
     ///
-    /// ```
-    /// use dashmap::DashMap;
+    /// Marks are kept by the hash of the key truncated to `usize` rather than by the key itself,
+    /// so an unrelated key whose hash collides with it waits for the mark as well.
     ///
-    /// let soccer_team = DashMap::new();
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    pub async fn insert_and_post_process_async_fn_unlocked<
+        T1,
+        E1,
+        T2,
+        E2,
+        T3,
+        E3,
+        Fut1,
+        Fut2,
+        Fut3,
+    >(
+        &self,
+        key: K,
+        value: V,
+        key_exists_func: impl FnOnce(&V) -> Fut1,
+        not_exists_func: impl FnOnce() -> Fut2,
+        post_func: Option<impl FnOnce() -> Fut3>,
+    ) -> (
+        Option<V>,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+        Option<Result<T3, E3>>,
+    )
+    where
+        Fut1: Future<Output = Result<T1, E1>>,
+        Fut2: Future<Output = Result<T2, E2>>,
+        Fut3: Future<Output = Result<T3, E3>>,
+    {
+        let hash = self.hash_u64(&key);
+
+        let _pending = self.pending.acquire(hash as usize).await;
+
+        let retv = self._insert(key, value);
+
+        let (key_exists_ret, not_exists_ret) = if let Some(ref prev_v) = retv {
+            (Some(key_exists_func(prev_v).await), None)
+        } else {
+            (None, Some(not_exists_func().await))
+        };
+        let post_ret = match post_func {
+            Some(post_func) => Some(post_func().await),
+            None => None,
+        };
+        (retv, key_exists_ret, not_exists_ret, post_ret)
+    }
+
+    /// Removes an entry from the map, returning the key and value if they existed in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let soccer_team = DashMap::new();
     /// soccer_team.insert("Jack", "Goalie");
     /// assert_eq!(soccer_team.remove("Jack").unwrap().1, "Goalie");
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn remove<Q>(&self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
@@ -417,6 +1101,44 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         self._remove(key)
     }
 
+    /// Removes an entry from the map if its shard can be locked right away.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let soccer_team = DashMap::new();
+    /// soccer_team.insert("Jack", "Goalie");
+    /// assert_eq!(soccer_team.try_remove("Jack").unwrap().unwrap().1, "Goalie");
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_remove<Q>(&'a self, key: &Q) -> Result<Option<(K, V)>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_remove(key, None)
+    }
+
+    /// Removes an entry from the map, waiting at most `timeout` for its shard.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_timeout<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<(K, V)>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_remove(key, Some(timeout))
+    }
+
     /// Removes an entry from the map, returning the key and value
     /// if the entry existed and the provided conditional function returned true.
     ///
@@ -438,6 +1160,7 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// soccer_team.remove_if("Sam", |_, position| position == &"Forward");
     /// assert!(!soccer_team.contains_key("Sam"));
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)>
     where
         K: Borrow<Q>,
@@ -446,6 +1169,7 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         self._remove_if(key, f)
     }
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn remove_and_post_process<Q, T1, E1, T2, E2>(
         &self,
         key: &Q,
@@ -463,246 +1187,411 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
         self._remove_and_post_process(key, key_exists_func, not_exists_func)
     }
 
-    /// Creates an iterator over a DashMap yielding immutable references.
+    /// Replaces the value of `key` with `new` if it is equal to `expected`.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    /// The comparison runs under an upgradeable read lock, which is only upgraded to a write lock
+    /// once the value matched. It does not wait for the readers of the shard, and with the
+    /// `parking_lot` backend new readers are not held up by checks that fail either. The spinning
+    /// and `std-lock` backends hold back new readers while an upgradeable lock is held, so there a
+    /// failing check blocks them until it is done.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::cas::CompareAndSwap;
     /// use dashmap::DashMap;
     ///
-    /// let words = DashMap::new();
-    /// words.insert("hello", "world");
-    /// assert_eq!(words.iter().count(), 1);
+    /// let versions = DashMap::new();
+    /// versions.insert("config", 1);
+    ///
+    /// assert_eq!(versions.compare_and_swap("config", &1, 2), CompareAndSwap::Swapped(1));
+    /// assert_eq!(versions.compare_and_swap("config", &1, 3), CompareAndSwap::Mismatch(3));
+    /// assert_eq!(versions.compare_and_swap("schema", &1, 2), CompareAndSwap::Absent(2));
     /// ```
-    pub fn iter(&'a self) -> Iter<'a, K, V, S, DashMap<K, V, S>> {
-        self._iter()
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn compare_and_swap<Q>(&self, key: &Q, expected: &V, new: V) -> CompareAndSwap<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        self._compare_and_swap(key, expected, new)
     }
 
-    /// Iterator over a DashMap yielding mutable references.
+    /// Inserts `key` and `value` only if `key` is not present yet,
+    /// handing both back otherwise.
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::cas::InsertIfAbsent;
     /// use dashmap::DashMap;
     ///
-    /// let map = DashMap::new();
-    /// map.insert("Johnny", 21);
-    /// map.iter_mut().for_each(|mut r| *r += 1);
-    /// assert_eq!(*map.get("Johnny").unwrap(), 22);
+    /// let owners = DashMap::new();
+    ///
+    /// assert!(owners.insert_if_absent("lock", "alice").is_inserted());
+    /// assert_eq!(owners.insert_if_absent("lock", "bob"), InsertIfAbsent::Present("lock", "bob"));
+    /// assert_eq!(*owners.get("lock").unwrap(), "alice");
     /// ```
-    pub fn iter_mut(&'a self) -> IterMut<'a, K, V, S, DashMap<K, V, S>> {
-        self._iter_mut()
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_if_absent(&self, key: K, value: V) -> InsertIfAbsent<K, V> {
+        self._insert_if_absent(key, value)
     }
 
-    /// Get a immutable reference to an entry in the map
+    /// Replaces the value of `key` only if `key` is present.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::cas::Replace;
     /// use dashmap::DashMap;
     ///
-    /// let youtubers = DashMap::new();
-    /// youtubers.insert("Bosnian Bill", 457000);
-    /// assert_eq!(*youtubers.get("Bosnian Bill").unwrap(), 457000);
+    /// let sessions = DashMap::new();
+    /// sessions.insert(7, "token-a");
+    ///
+    /// assert_eq!(sessions.replace(&7, "token-b"), Replace::Replaced("token-a"));
+    /// assert_eq!(sessions.replace(&8, "token-c"), Replace::Absent("token-c"));
+    /// assert!(!sessions.contains_key(&8));
     /// ```
-    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn replace<Q>(&self, key: &Q, value: V) -> Replace<V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self._get(key)
+        self._replace(key, value)
     }
 
-    /// Get a mutable reference to an entry in the map
+    /// Calls `f` on the value of `key` if `predicate` accepts the entry,
+    /// without anything changing in between.
+    ///
+    /// `predicate` runs under an upgradeable read lock, which is only upgraded to a write lock once
+    /// it accepted the entry. As for [`compare_and_swap`](DashMap::compare_and_swap), only the
+    /// `parking_lot` backend lets new readers in meanwhile.
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::cas::UpdateIf;
     /// use dashmap::DashMap;
     ///
-    /// let class = DashMap::new();
-    /// class.insert("Albin", 15);
-    /// *class.get_mut("Albin").unwrap() -= 1;
-    /// assert_eq!(*class.get("Albin").unwrap(), 14);
+    /// let stock = DashMap::new();
+    /// stock.insert("apples", 3);
+    ///
+    /// let take = |n| stock.update_if("apples", |_, &left| left >= n, |_, left| *left -= n);
+    ///
+    /// assert_eq!(take(2), UpdateIf::Updated(()));
+    /// assert_eq!(take(2), UpdateIf::Rejected);
+    /// assert_eq!(*stock.get("apples").unwrap(), 1);
     /// ```
-    pub fn get_mut<Q>(&'a self, key: &Q) -> Option<RefMut<'a, K, V, S>>
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn update_if<Q, R>(
+        &self,
+        key: &Q,
+        predicate: impl FnOnce(&K, &V) -> bool,
+        f: impl FnOnce(&K, &mut V) -> R,
+    ) -> UpdateIf<R>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self._get_mut(key)
+        self._update_if(key, predicate, f)
     }
 
-    /// Get a immutable reference to an entry in the map. Before return execute `post_func`
+    /// Removes `key` if its value is equal to `expected`.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
-    pub fn get_with<Q, T, E>(
-        &'a self,
-        key: &Q,
-        post_func: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::cas::RemoveIfEq;
+    /// use dashmap::DashMap;
+    ///
+    /// let leases = DashMap::new();
+    /// leases.insert("db", "worker-1");
+    ///
+    /// assert_eq!(leases.remove_if_eq("db", &"worker-2"), RemoveIfEq::Mismatch);
+    /// assert_eq!(leases.remove_if_eq("db", &"worker-1"), RemoveIfEq::Removed("db", "worker-1"));
+    /// assert_eq!(leases.remove_if_eq("db", &"worker-1"), RemoveIfEq::Absent);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_if_eq<Q>(&self, key: &Q, expected: &V) -> RemoveIfEq<K, V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
+        V: PartialEq,
     {
-        self._get_with(key, post_func)
+        self._remove_if_eq(key, expected)
     }
 
-    /// Get a immutable reference to an entry in the map. Before return execute `key_exists_func`
-    /// if key exists or execute not_exists_func if key doesn't exists.
-    pub fn get_and_post_process<Q, T, E>(
-        &'a self,
-        key: &Q,
-        key_exists_func: impl FnOnce(&V) -> Result<T, E>,
-        not_exists_func: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self._get_and_post_process(key, key_exists_func, not_exists_func)
-    }
+    /// Inserts all of `items`, locking each shard once for all the items that belong to it.
+    /// Returns the previous value of each key, in the order of `items`.
+    ///
+    /// Items with the same key are inserted in order, so the last one wins.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let scores = DashMap::new();
+    /// scores.insert("a", 1);
+    ///
+    /// assert_eq!(scores.insert_many(vec![("a", 2), ("b", 3)]), [Some(1), None]);
+    /// assert_eq!(*scores.get("a").unwrap(), 2);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_many(&self, items: impl IntoIterator<Item = (K, V)>) -> Vec<Option<V>> {
+        let _layout = self.layout.read();
 
-    pub async fn get_and_post_process_ke_async<Q, T, E, Fut1>(
-        &'a self,
-        key: &Q,
-        key_exists_func: impl FnOnce(&V) -> Fut1,
-        not_exists_func: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-        Fut1: Future<Output = Result<T, E>>,
-    {
-        let hash = self.hash_usize(&key);
+        let groups = self.group_by_shard(items, |(key, _)| self.hash_u64(key));
+        let len = groups.iter().map(|(_, group)| group.len()).sum();
+        let mut previous: Vec<Option<V>> = (0..len).map(|_| None).collect();
 
-        let idx = self.determine_shard(hash);
+        let observed = self.observers.observe();
 
-        let shard = unsafe { self._yield_read_shard(idx) };
+        for (i, group) in groups {
+            let mut pending = Pending::new(observed.clone());
 
-        let val = if let Some((kptr, vptr)) = shard.get_key_value(key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+            let mut shard = self.write_shard(i);
 
-                let vptr = util::change_lifetime_const(vptr);
+            for (pos, hash, (key, value)) in group {
+                let copy = pending.observed().map(|o| o.clone_pair(&key, &value));
 
-                Some(Ref::new(shard, kptr, vptr.get()))
-            }
-        } else {
-            None
-        };
-        // # Safety
-        //
-        // If the closure panics, we must abort otherwise we could double drop `T`
-        let ret = {
-            let _promote_panic_to_abort = util::AbortOnPanic;
-            if let Some(ref kv) = val {
-                key_exists_func(kv.value()).await
-            } else {
-                not_exists_func()
+                let old = shard
+                    .insert_hashed(hash, key, SharedValue::new(value))
+                    .map(|v| v.into_inner());
+
+                if let Some((key, value)) = copy {
+                    pending.record(|o| match &old {
+                        Some(old) => Change::Updated(key, o.clone_value(old), value),
+                        None => Change::Inserted(key, value),
+                    });
+                }
+
+                previous[pos] = old;
             }
-        };
-        (val, ret)
-    }
+        }
 
-    /// Remove excess capacity to reduce memory usage.
-    ///
-    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
-    pub fn shrink_to_fit(&self) {
-        self._shrink_to_fit();
+        previous
     }
 
-    /// Retain elements that whose predicates return true
-    /// and discard elements whose predicates return false.
+    /// Looks up all of `keys`, locking each shard once for all the keys that belong to it.
+    /// Returns a copy of the value of each key, in the order of `keys`.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let people = DashMap::new();
-    /// people.insert("Albin", 15);
-    /// people.insert("Jones", 22);
-    /// people.insert("Charlie", 27);
-    /// people.retain(|_, v| *v > 20);
-    /// assert_eq!(people.len(), 2);
+    /// let ages = DashMap::new();
+    /// ages.insert("ann", 31);
+    /// ages.insert("bo", 42);
+    ///
+    /// assert_eq!(ages.get_many(vec!["bo", "cy", "ann"]), [Some(42), None, Some(31)]);
     /// ```
-    pub fn retain(&self, f: impl FnMut(&K, &mut V) -> bool) {
-        self._retain(f);
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_many<'k, Q>(&self, keys: impl IntoIterator<Item = &'k Q>) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'k,
+        V: Clone,
+    {
+        let _layout = self.layout.read();
+
+        let groups = self.group_by_shard(keys, |key| self.hash_u64(key));
+        let len = groups.iter().map(|(_, group)| group.len()).sum();
+        let mut values: Vec<Option<V>> = (0..len).map(|_| None).collect();
+
+        for (i, group) in groups {
+            let shard = self.read_shard(i);
+
+            for (pos, hash, key) in group {
+                values[pos] = shard
+                    .get_key_value_hashed(hash, key)
+                    .map(|(_, v)| v.get().clone());
+            }
+        }
+
+        values
     }
 
-    /// Fetches the total number of key-value pairs stored in the map.
+    /// Removes all of `keys`, locking each shard once for all the keys that belong to it.
+    /// Returns the removed entries, in the order of `keys`.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let people = DashMap::new();
-    /// people.insert("Albin", 15);
-    /// people.insert("Jones", 22);
-    /// people.insert("Charlie", 27);
-    /// assert_eq!(people.len(), 3);
+    /// let jobs = DashMap::new();
+    /// jobs.insert(1, "build");
+    /// jobs.insert(2, "test");
+    ///
+    /// assert_eq!(jobs.remove_many(&[2, 3]), [Some((2, "test")), None]);
+    /// assert_eq!(jobs.len(), 1);
     /// ```
-    pub fn len(&self) -> usize {
-        self._len()
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_many<'k, Q>(&self, keys: impl IntoIterator<Item = &'k Q>) -> Vec<Option<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'k,
+    {
+        let _layout = self.layout.read();
+
+        let groups = self.group_by_shard(keys, |key| self.hash_u64(key));
+        let len = groups.iter().map(|(_, group)| group.len()).sum();
+        let mut removed: Vec<Option<(K, V)>> = (0..len).map(|_| None).collect();
+
+        let observed = self.observers.observe();
+
+        for (i, group) in groups {
+            let mut pending = Pending::new(observed.clone());
+
+            let mut shard = self.write_shard(i);
+
+            for (pos, hash, key) in group {
+                let kv = shard
+                    .remove_entry_hashed(hash, key)
+                    .map(|(k, v)| (k, v.into_inner()));
+
+                if let Some((k, v)) = &kv {
+                    pending.record(|o| Change::Removed(o.clone_key(k), o.clone_value(v)));
+                }
+
+                removed[pos] = kv;
+            }
+        }
+
+        removed
     }
 
-    /// Checks if the map is empty or not.
+    /// Inserts the items of `iter` in batches of up to `batch_size`, like [`insert_many`](DashMap::insert_many)
+    /// does, without collecting all of them first.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let map = DashMap::<(), ()>::new();
-    /// assert!(map.is_empty());
+    /// let squares = DashMap::new();
+    /// squares.extend_batched((0..1000).map(|i| (i, i * i)), 64);
+    /// assert_eq!(*squares.get(&12).unwrap(), 144);
     /// ```
-    pub fn is_empty(&self) -> bool {
-        self._is_empty()
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn extend_batched(&self, iter: impl IntoIterator<Item = (K, V)>, batch_size: usize) {
+        assert!(batch_size > 0, "batch size must be at least 1");
+
+        let mut iter = iter.into_iter();
+
+        loop {
+            let batch: Vec<(K, V)> = iter.by_ref().take(batch_size).collect();
+
+            if batch.is_empty() {
+                return;
+            }
+
+            self.insert_many(batch);
+        }
     }
 
-    /// Removes all key-value pairs in the map.
+    /// Creates an iterator over a DashMap yielding immutable references.
     ///
-    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let stats = DashMap::new();
-    /// stats.insert("Goals", 4);
-    /// assert!(!stats.is_empty());
-    /// stats.clear();
-    /// assert!(stats.is_empty());
+    /// let words = DashMap::new();
+    /// words.insert("hello", "world");
+    /// assert_eq!(words.iter().count(), 1);
     /// ```
-    pub fn clear(&self) {
-        self._clear();
+    pub fn iter(&'a self) -> Iter<'a, K, V, S, DashMap<K, V, S>> {
+        self._iter()
     }
 
-    /// Returns how many key-value pairs the map can store without reallocating.
+    /// Iterator over a DashMap yielding mutable references.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map = DashMap::new();
+    /// map.insert("Johnny", 21);
+    /// map.iter_mut().for_each(|mut r| *r += 1);
+    /// assert_eq!(*map.get("Johnny").unwrap(), 22);
+    /// ```
+    pub fn iter_mut(&'a self) -> IterMut<'a, K, V, S, DashMap<K, V, S>> {
+        self._iter_mut()
+    }
+
+    /// Iterator over the keys of a DashMap, locking the shards like [`iter`](DashMap::iter).
     ///
     /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
-    pub fn capacity(&self) -> usize {
-        self._capacity()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map = DashMap::new();
+    /// map.insert("apple", 3);
+    /// map.insert("pear", 5);
+    ///
+    /// let mut keys: Vec<&str> = map.keys().map(|k| *k).collect();
+    /// keys.sort_unstable();
+    /// assert_eq!(keys, ["apple", "pear"]);
+    /// ```
+    pub fn keys(&'a self) -> Keys<'a, K, V, S> {
+        Keys::new(self._iter())
     }
 
-    /// Modify a specific value according to a function.
+    /// Iterator over the values of a DashMap, locking the shards like [`iter`](DashMap::iter).
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map = DashMap::new();
+    /// map.insert("apple", 3);
+    /// map.insert("pear", 5);
+    /// assert_eq!(map.values().map(|v| *v).sum::<i32>(), 8);
+    /// ```
+    pub fn values(&'a self) -> Values<'a, K, V, S> {
+        Values::new(self._iter())
+    }
+
+    /// Iterator over mutable references to the values of a DashMap, locking the shards like
+    /// [`iter_mut`](DashMap::iter_mut).
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
@@ -711,24 +1600,47 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let stats = DashMap::new();
-    /// stats.insert("Goals", 4);
-    /// stats.alter("Goals", |_, v| v * 2);
-    /// assert_eq!(*stats.get("Goals").unwrap(), 8);
+    /// let map = DashMap::new();
+    /// map.insert("apple", 3);
+    /// map.values_mut().for_each(|mut v| *v *= 10);
+    /// assert_eq!(*map.get("apple").unwrap(), 30);
     /// ```
+    pub fn values_mut(&'a self) -> ValuesMut<'a, K, V, S> {
+        ValuesMut::new(self._iter_mut())
+    }
+
+    /// Empties the map shard by shard, yielding the removed key-value pairs.
     ///
-    /// # Panics
+    /// Each shard is only locked while it is being emptied, so the map stays usable while the
+    /// pairs are consumed. Entries inserted into a shard after the iterator emptied it stay in
+    /// the map, as do the shards not reached yet if the iterator is dropped.
     ///
-    /// If the given closure panics, then `alter` will abort the process
-    pub fn alter<Q>(&self, key: &Q, f: impl FnOnce(&K, V) -> V)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self._alter(key, f);
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let queue = DashMap::new();
+    /// queue.insert(1, "build");
+    /// queue.insert(2, "test");
+    ///
+    /// let mut jobs: Vec<_> = queue.drain().collect();
+    /// jobs.sort_unstable();
+    /// assert_eq!(jobs, [(1, "build"), (2, "test")]);
+    /// assert!(queue.is_empty());
+    /// ```
+    pub fn drain(&'a self) -> Drain<'a, K, V, S> {
+        Drain::new(self)
     }
 
-    /// Modify every value in the map according to a function.
+    /// Removes the entries `pred` returns `true` for, yielding them as key-value pairs.
+    ///
+    /// `pred` runs on one shard at a time under its write lock, and the lock is released before the
+    /// entries extracted from that shard are yielded. Until they are, those entries are out of the
+    /// map. If the iterator is dropped early, the ones not yielded are put back unless their key
+    /// was inserted again meanwhile, and shards not reached yet are left untouched.
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
     ///
@@ -737,596 +1649,3215 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> DashMap<K, V, S> {
     /// ```
     /// use dashmap::DashMap;
     ///
-    /// let stats = DashMap::new();
-    /// stats.insert("Wins", 4);
-    /// stats.insert("Losses", 2);
-    /// stats.alter_all(|_, v| v + 1);
-    /// assert_eq!(*stats.get("Wins").unwrap(), 5);
-    /// assert_eq!(*stats.get("Losses").unwrap(), 3);
+    /// let numbers: DashMap<u32, u32> = (0..10).map(|i| (i, i)).collect();
+    ///
+    /// let mut odd: Vec<u32> = numbers.extract_if(|_, v| *v % 2 == 1).map(|(k, _)| k).collect();
+    /// odd.sort_unstable();
+    /// assert_eq!(odd, [1, 3, 5, 7, 9]);
+    /// assert_eq!(numbers.len(), 5);
     /// ```
+    pub fn extract_if<F: FnMut(&K, &mut V) -> bool>(
+        &'a self,
+        pred: F,
+    ) -> ExtractIf<'a, K, V, S, F> {
+        ExtractIf::new(self, pred)
+    }
+
+    /// Iterator cloning the entries out of the map in batches of about `batch_size`.
+    ///
+    /// Unlike [`iter`](DashMap::iter), no lock is held while entries are consumed: each batch is
+    /// cloned under the read lock of one shard at a time, and the next batch resumes from a
+    /// position derived from the hash of the last key, similar to Redis `SCAN`. Entries present
+    /// for the whole iteration are yielded exactly once, even if the map is resharded meanwhile.
+    /// Entries inserted or removed during the iteration may or may not be yielded.
+    ///
+    /// Every batch hashes every key of each shard it is taken from under its read lock, since
+    /// no scan position is stored in the map. Taking a shard of `n` entries in batches of `b`
+    /// thus costs about `n * n / b` hashes instead of `n`, so batches much smaller than a shard,
+    /// which holds about `len() / shard_amount()` entries, make the iteration quadratic.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
     ///
     /// # Panics
     ///
-    /// If the given closure panics, then `alter_all` will abort the process
-    pub fn alter_all(&self, f: impl FnMut(&K, V) -> V) {
-        self._alter_all(f);
+    /// Panics if `batch_size` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let scores: DashMap<u32, u32> = (0..1000).map(|i| (i, i % 7)).collect();
+    ///
+    /// let mut total = 0;
+    ///
+    /// for (_, score) in scores.iter_batched(64) {
+    ///     // Writers are not blocked while this runs.
+    ///     scores.insert(1000, 0);
+    ///     total += score;
+    /// }
+    ///
+    /// assert_eq!(total, (0..1000).map(|i| i % 7).sum());
+    /// ```
+    pub fn iter_batched(&'a self, batch_size: usize) -> IterBatched<'a, K, V, S>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        IterBatched::new(self, batch_size)
     }
 
-    /// Checks if the map contains a specific key.
+    /// Clones up to `limit` entries out of the map, starting at `cursor`, and returns them with
+    /// the cursor to pass to the next call, or `None` once the whole map was scanned.
+    ///
+    /// A scan starts from [`Cursor::START`] and may be spread over any amount of time, for example
+    /// one call per request of a paginated endpoint, without holding any lock in between. Like
+    /// [`iter_batched`](DashMap::iter_batched), which it resumes like, it returns every entry
+    /// present for the whole scan exactly once, even if the map is resharded meanwhile. Entries
+    /// inserted or removed during the scan may or may not be returned.
+    ///
+    /// Fewer than `limit` entries are only returned by the last call. More are only returned if
+    /// keys share a hash, since those are never split between calls.
+    ///
+    /// Every call hashes every key of each shard it takes entries from, which costs
+    /// `O(len() / shard_amount())` per shard no matter how small `limit` is. Scanning a whole map
+    /// with a `limit` much smaller than a shard is therefore quadratic in the size of a shard.
     ///
     /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
     ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
+    ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::iter::Cursor;
     /// use dashmap::DashMap;
     ///
-    /// let team_sizes = DashMap::new();
-    /// team_sizes.insert("Dakota Cherries", 23);
-    /// assert!(team_sizes.contains_key("Dakota Cherries"));
+    /// let users: DashMap<u32, String> = (0..250).map(|i| (i, format!("user{}", i))).collect();
+    ///
+    /// let mut pages = 0;
+    /// let mut seen = 0;
+    /// let mut cursor = Some(Cursor::START);
+    ///
+    /// while let Some(current) = cursor {
+    ///     let (page, next) = users.scan(current, 100);
+    ///
+    ///     pages += 1;
+    ///     seen += page.len();
+    ///     cursor = next;
+    /// }
+    ///
+    /// assert_eq!(pages, 3);
+    /// assert_eq!(seen, 250);
     /// ```
-    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    pub fn scan(&'a self, cursor: Cursor, limit: usize) -> (Vec<(K, V)>, Option<Cursor>)
+    where
+        K: Clone,
+        V: Clone,
+    {
+        assert!(limit > 0, "scan limit must be greater than 0");
+
+        let mut batch = Vec::with_capacity(limit);
+
+        let next = self.scan_from(cursor.position(), limit, &mut batch);
+
+        (batch, next.map(Cursor::new))
+    }
+
+    /// Get a immutable reference to an entry in the map
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let youtubers = DashMap::new();
+    /// youtubers.insert("Bosnian Bill", 457000);
+    /// assert_eq!(*youtubers.get("Bosnian Bill").unwrap(), 457000);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self._contains_key(key)
+        self._get(key)
     }
 
-    /// Advanced entry API that tries to mimic `std::collections::HashMap`.
-    /// See the documentation on `dashmap::mapref::entry` for more details.
+    /// Get a mutable reference to an entry in the map
     ///
     /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
-    pub fn entry(&'a self, key: K) -> Entry<'a, K, V, S> {
-        self._entry(key)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let class = DashMap::new();
+    /// class.insert("Albin", 15);
+    /// *class.get_mut("Albin").unwrap() -= 1;
+    /// assert_eq!(*class.get("Albin").unwrap(), 14);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_mut<Q>(&'a self, key: &Q) -> Option<RefMut<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._get_mut(key)
     }
-}
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + BuildHasher + Clone> Map<'a, K, V, S>
-    for DashMap<K, V, S>
-{
-    fn _shard_count(&self) -> usize {
-        self.shards.len()
+    /// Get a immutable reference to an entry in the map if its shard can be locked right away.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is write locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::{DashMap, TryLockError};
+    ///
+    /// let youtubers = DashMap::new();
+    /// youtubers.insert("Bosnian Bill", 457000);
+    /// assert_eq!(*youtubers.try_get("Bosnian Bill").unwrap().unwrap(), 457000);
+    ///
+    /// let guard = youtubers.get_mut("Bosnian Bill").unwrap();
+    /// assert!(matches!(youtubers.try_get("Bosnian Bill"), Err(TryLockError::WouldBlock(()))));
+    /// drop(guard);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_get<Q>(&'a self, key: &Q) -> Result<Option<Ref<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_get(key, None)
     }
 
-    unsafe fn _get_read_shard(&'a self, i: usize) -> &'a HashMap<K, V, S> {
-        debug_assert!(i < self.shards.len());
-
-        self.shards.get_unchecked(i).get()
+    /// Get a immutable reference to an entry in the map, waiting at most `timeout` for its shard.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    /// use std::time::Duration;
+    ///
+    /// let youtubers = DashMap::new();
+    /// youtubers.insert("Bosnian Bill", 457000);
+    ///
+    /// let guard = youtubers.get_mut("Bosnian Bill").unwrap();
+    /// let err = youtubers.get_timeout("Bosnian Bill", Duration::from_millis(10)).err().unwrap();
+    /// assert!(err.is_timeout());
+    /// drop(guard);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_timeout<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<Ref<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_get(key, Some(timeout))
     }
 
-    unsafe fn _yield_read_shard(&'a self, i: usize) -> RwLockReadGuard<'a, HashMap<K, V, S>> {
-        debug_assert!(i < self.shards.len());
-
-        self.shards.get_unchecked(i).read()
+    /// Get a mutable reference to an entry in the map if its shard can be locked right away.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let class = DashMap::new();
+    /// class.insert("Albin", 15);
+    /// *class.try_get_mut("Albin").unwrap().unwrap() -= 1;
+    ///
+    /// let guard = class.get("Albin").unwrap();
+    /// assert!(class.try_get_mut("Albin").is_err());
+    /// assert_eq!(*guard, 14);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_get_mut<Q>(&'a self, key: &Q) -> Result<Option<RefMut<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_get_mut(key, None)
     }
 
-    unsafe fn _yield_write_shard(&'a self, i: usize) -> RwLockWriteGuard<'a, HashMap<K, V, S>> {
-        debug_assert!(i < self.shards.len());
-
-        self.shards.get_unchecked(i).write()
+    /// Get a mutable reference to an entry in the map, waiting at most `timeout` for its shard.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_mut_timeout<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<RefMut<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._try_get_mut(key, Some(timeout))
     }
 
-    fn _insert(&self, key: K, value: V) -> Option<V> {
-        let hash = self.hash_usize(&key);
-
-        let idx = self.determine_shard(hash);
-
-        let mut shard = unsafe { self._yield_write_shard(idx) };
-
-        shard
-            .insert(key, SharedValue::new(value))
-            .map(|v| v.into_inner())
+    /// Get a immutable reference to an entry in the map. Before return execute `post_func`
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_with<Q, T, E>(
+        &'a self,
+        key: &Q,
+        post_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._get_with(key, post_func)
     }
 
-    fn _insert_with<T, E>(
-        &self,
-        key: K,
-        value: V,
-        f: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<V>, Result<T, E>) {
-        let hash = self.hash_usize(&key);
-
-        let idx = self.determine_shard(hash);
-
-        let mut shard = unsafe { self._yield_write_shard(idx) };
-
-        let retv = shard
-            .insert(key, SharedValue::new(value))
-            .map(|v| v.into_inner());
-        let ret = util::run_fnonce_with_result(f);
-        (retv, ret)
+    /// Get a immutable reference to an entry in the map. Before return execute `key_exists_func`
+    /// if key exists or execute not_exists_func if key doesn't exists.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_and_post_process<Q, T, E>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&V) -> Result<T, E>,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._get_and_post_process(key, key_exists_func, not_exists_func)
     }
 
-    fn _insert_and_post_process<T1, E1, T2, E2, T3, E3>(
-        &self,
-        key: K,
-        value: V,
-        key_exists_func: impl FnOnce(&V) -> Result<T1, E1>,
-        not_exists_func: impl FnOnce() -> Result<T2, E2>,
-        post_func: Option<impl FnOnce() -> Result<T3, E3>>,
-    ) -> (
-        Option<V>,
-        Option<Result<T1, E1>>,
-        Option<Result<T2, E2>>,
-        Option<Result<T3, E3>>,
-    ) {
-        let hash = self.hash_usize(&key);
+    pub async fn get_and_post_process_ke_async<Q, T, E, Fut1>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&V) -> Fut1,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        Fut1: Future<Output = Result<T, E>>,
+    {
+        let hash = self.hash_u64(&key);
 
-        let idx = self.determine_shard(hash);
+        let shard = self.read_shard_for(hash);
 
-        let mut shard = unsafe { self._yield_write_shard(idx) };
+        let val = if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
 
-        let retv = shard
-            .insert(key, SharedValue::new(value))
-            .map(|v| v.into_inner());
+                let vptr = util::change_lifetime_const(vptr);
 
-        let (key_exists_ret, not_exists_ret) = if let Some(ref prev_v) = retv {
-            (
-                Some(util::run_fnonce_with_val(prev_v, key_exists_func)),
-                None,
-            )
-        } else {
-            (None, Some(util::run_fnonce_with_result(not_exists_func)))
-        };
-        let post_ret = if let Some(post_func) = post_func {
-            Some(util::run_fnonce_with_result(post_func))
+                Some(Ref::new(shard, kptr, vptr.get()))
+            }
         } else {
             None
         };
-        (retv, key_exists_ret, not_exists_ret, post_ret)
+        // # Safety
+        //
+        // If the closure panics, we must abort otherwise we could double drop `T`
+        let ret = {
+            let _promote_panic_to_abort = util::AbortOnPanic;
+            if let Some(ref kv) = val {
+                key_exists_func(kv.value()).await
+            } else {
+                not_exists_func()
+            }
+        };
+        (val, ret)
     }
 
-    fn _remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    /// Get a immutable reference to an entry in the map. Before return execute `key_exists_func`
+    /// if key exists or execute not_exists_func if key doesn't exists.
+    ///
+    /// Unlike `get_and_post_process_ke_async` the shard lock is only held while `key_exists_func`
+    /// builds its future and is released before that future is awaited. The key is marked as
+    /// pending for the whole call instead, see `insert_and_post_process_async_fn_unlocked`.
+    /// Like there, calls on keys whose hashes collide wait for each other.
+    /// The returned reference is taken after the future has completed.
+    ///
+    /// **Only `*_unlocked` methods respect the mark.** Every other method may change or remove
+    /// the value while `key_exists_func` is being awaited, and an insert made by
+    /// `insert_and_post_process_async_fn_unlocked` is only waited for by this method, not by `get`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    pub async fn get_and_post_process_ke_async_unlocked<Q, T, E, Fut1>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&V) -> Fut1,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
+        Fut1: Future<Output = Result<T, E>>,
     {
-        let hash = self.hash_usize(&key);
+        let hash = self.hash_u64(&key);
+
+        let _pending = self.pending.acquire(hash as usize).await;
 
-        let idx = self.determine_shard(hash);
+        let fut = match self._get(key) {
+            Some(kv) => key_exists_func(kv.value()),
+            None => return (None, not_exists_func()),
+        };
 
-        let mut shard = unsafe { self._yield_write_shard(idx) };
+        let ret = fut.await;
 
-        shard.remove_entry(key).map(|(k, v)| (k, v.into_inner()))
+        (self._get(key), ret)
     }
 
-    fn _remove_and_post_process<Q, T1, E1, T2, E2>(
-        &self,
-        key: &Q,
-        key_exists_func: impl FnOnce(&Q, &V) -> Result<T1, E1>,
-        not_exists_func: Option<impl FnOnce() -> Result<T2, E2>>,
-    ) -> (
-        Option<(K, V)>,
-        Option<Result<T1, E1>>,
-        Option<Result<T2, E2>>,
-    )
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    /// Returns the number of shards the map is split into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map: DashMap<i32, i32> = DashMap::with_shard_amount(8);
+    /// assert_eq!(map.shard_amount(), 8);
+    /// ```
+    pub fn shard_amount(&self) -> usize {
+        self.table().shards.len()
+    }
 
-        let idx = self.determine_shard(hash);
+    /// Splits the map into `shard_amount` shards while it stays in use.
+    ///
+    /// Entries are moved to the new shards one old shard at a time. Lookups, inserts and removals
+    /// on other threads keep working throughout and only wait for the shard being moved.
+    /// Iterators and other operations visiting the whole map hold back the move until they are done,
+    /// so a steady stream of overlapping iterators can hold it back indefinitely. Use
+    /// [`try_reshard_for`](DashMap::try_reshard_for) to give up instead. Concurrent calls are serialized.
+    ///
+    /// The replaced shards are emptied and shrunk but not freed until the map is dropped, since
+    /// lookups on other threads may still be passing through them. Every call that changes the
+    /// shard amount thus keeps one empty shard per old shard alive, a few dozen bytes each, so
+    /// resharding over and over grows the memory of the map without bound.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference or iterator into the map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map = DashMap::with_shard_amount(4);
+    /// map.insert("apple", 3);
+    /// map.reshard(64);
+    /// assert_eq!(map.shard_amount(), 64);
+    /// assert_eq!(*map.get("apple").unwrap(), 3);
+    /// ```
+    pub fn reshard(&self, shard_amount: usize) {
+        assert_shard_amount(shard_amount);
 
-        let mut shard = unsafe { self._yield_write_shard(idx) };
+        let mut tables = self.tables.write();
 
-        let kv = shard.remove_entry(key).map(|(k, v)| (k, v.into_inner()));
-        let (key_exists_ret, not_exists_ret) = match kv {
-            Some((ref k, ref v)) => {
-                let _promote_panic_to_abort = util::AbortOnPanic;
-                (Some(key_exists_func(k.borrow(), v)), None)
-            }
-            None => match not_exists_func {
-                Some(not_exists_func) => {
-                    (None, Some(util::run_fnonce_with_result(not_exists_func)))
-                }
-                None => (None, None),
-            },
-        };
-        (kv, key_exists_ret, not_exists_ret)
+        self.reshard_locked(&mut tables, shard_amount, None);
     }
 
-    fn _remove_if<Q>(&self, key: &Q, f: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    /// Like [`reshard`](DashMap::reshard), but gives up once the move was held back for `timeout`.
+    ///
+    /// Entries moved already stay where they are, and operations keep working on the map as usual.
+    /// The next call to `reshard` or `try_reshard_for` finishes the move first.
+    ///
+    /// Like with `reshard`, the replaced shards are kept, emptied, until the map is dropped.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    /// use std::time::Duration;
+    ///
+    /// let map = DashMap::with_shard_amount(4);
+    /// map.insert("apple", 3);
+    ///
+    /// let iter = map.iter();
+    /// assert!(map.try_reshard_for(64, Duration::from_millis(10)).unwrap_err().is_timeout());
+    /// drop(iter);
+    ///
+    /// map.try_reshard_for(64, Duration::from_secs(1)).unwrap();
+    /// assert_eq!(map.shard_amount(), 64);
+    /// assert_eq!(*map.get("apple").unwrap(), 3);
+    /// ```
+    pub fn try_reshard_for(
+        &self,
+        shard_amount: usize,
+        timeout: Duration,
+    ) -> Result<(), TryLockError> {
+        assert_shard_amount(shard_amount);
 
-        let idx = self.determine_shard(hash);
+        let deadline = Instant::now().checked_add(timeout);
 
-        let mut shard = unsafe { self._yield_write_shard(idx) };
+        let mut tables = self
+            .tables
+            .try_write_for(timeout)
+            .ok_or(TryLockError::TimedOut(()))?;
 
-        if let Some((k, v)) = shard.get_key_value(key) {
-            if f(k, v.get()) {
-                shard.remove_entry(key).map(|(k, v)| (k, v.into_inner()))
-            } else {
-                None
-            }
+        if self.reshard_locked(&mut tables, shard_amount, deadline) {
+            Ok(())
         } else {
-            None
+            Err(TryLockError::TimedOut(()))
         }
     }
 
-    fn _iter(&'a self) -> Iter<'a, K, V, S, DashMap<K, V, S>> {
-        Iter::new(self)
+    /// Resizes to `shard_amount` shards while holding the `tables` lock.
+    ///
+    /// Returns `false` if `deadline` passed before the move was done.
+    #[allow(clippy::vec_box)]
+    fn reshard_locked(
+        &self,
+        tables: &mut Vec<Box<Table<K, V, S>>>,
+        shard_amount: usize,
+        deadline: Option<Instant>,
+    ) -> bool {
+        if !self.complete_migration(deadline) {
+            return false;
+        }
+
+        if self.shard_amount() == shard_amount {
+            return true;
+        }
+
+        let table = new_table(self.len(), &self.hasher, shard_amount);
+
+        tables.push(Box::new(table));
+
+        // The new table starts out empty, so linking it does not change what whole-map operations see.
+        self.table().set_next(tables.last().unwrap());
+
+        self.complete_migration(deadline)
     }
 
-    fn _iter_mut(&'a self) -> IterMut<'a, K, V, S, DashMap<K, V, S>> {
-        IterMut::new(self)
+    /// Moves every entry of the current table to the next one, if there is one, and makes the next
+    /// table the current one. The caller has to hold the `tables` lock or own the map.
+    ///
+    /// Returns `false` if `deadline` passed first, leaving the migration to be finished later.
+    fn complete_migration(&self, deadline: Option<Instant>) -> bool {
+        let table = self.table();
+
+        let next = match table.next() {
+            Some(next) => next,
+            None => return true,
+        };
+
+        let expired = || matches!(deadline, Some(deadline) if Instant::now() >= deadline);
+
+        for i in 0..table.shards.len() {
+            while !self.migrate_shard(table, next, i) {
+                if expired() {
+                    return false;
+                }
+
+                std::thread::yield_now();
+            }
+        }
+
+        // Whole-map operations index shards across the chain, so the chain may only shrink while
+        // none of them run. Like `migrate_shard` this never blocks on the layout lock, which lets
+        // operations visiting the whole map nest without ever deadlocking on it.
+        loop {
+            if let Some(_layout) = self.layout.try_write() {
+                self.current
+                    .store(next as *const Table<K, V, S> as *mut _, Ordering::Release);
+
+                return true;
+            }
+
+            if expired() {
+                return false;
+            }
+
+            std::thread::yield_now();
+        }
     }
 
-    fn _get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    /// Moves the entries of shard `i` of `table` to `next`.
+    ///
+    /// Returns `false` without waiting if any of the locks involved is taken.
+    fn migrate_shard(&self, table: &Table<K, V, S>, next: &Table<K, V, S>, i: usize) -> bool {
+        let _layout = match self.layout.try_write() {
+            Some(layout) => layout,
+            None => return false,
+        };
+
+        let mut shard = match table.shards[i].try_write() {
+            Some(shard) => shard,
+            None => return false,
+        };
 
-        let idx = self.determine_shard(hash);
+        if table.is_migrated(i) {
+            return true;
+        }
 
-        let shard = unsafe { self._yield_read_shard(idx) };
+        let mut targets: Vec<usize> = shard
+            .keys()
+            .map(|k| next.determine_shard(self.hash_u64(k) as usize))
+            .collect();
 
-        if let Some((kptr, vptr)) = shard.get_key_value(key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+        targets.sort_unstable();
+        targets.dedup();
 
-                let vptr = util::change_lifetime_const(vptr);
+        let mut target_shards = Vec::with_capacity(targets.len());
 
-                Some(Ref::new(shard, kptr, vptr.get()))
+        for &j in &targets {
+            match next.shards[j].try_write() {
+                Some(target) => target_shards.push(target),
+                None => return false,
             }
-        } else {
-            None
         }
+
+        for (k, v) in shard.drain() {
+            let hash = self.hash_u64(&k);
+            let j = next.determine_shard(hash as usize);
+            let pos = targets.binary_search(&j).unwrap();
+
+            target_shards[pos].insert_unique_hashed(hash, k, v);
+        }
+
+        shard.shrink_to_fit();
+
+        table.set_migrated(i);
+
+        true
     }
 
-    fn _get_with<Q, T, E>(
-        &'a self,
-        key: &Q,
-        post_func: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    /// Remove excess capacity to reduce memory usage.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn shrink_to_fit(&self) {
+        self._shrink_to_fit();
+    }
+
+    /// Retain elements that whose predicates return true
+    /// and discard elements whose predicates return false.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let people = DashMap::new();
+    /// people.insert("Albin", 15);
+    /// people.insert("Jones", 22);
+    /// people.insert("Charlie", 27);
+    /// people.retain(|_, v| *v > 20);
+    /// assert_eq!(people.len(), 2);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn retain(&self, f: impl FnMut(&K, &mut V) -> bool) {
+        self._retain(f);
+    }
+
+    /// Fetches the total number of key-value pairs stored in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let people = DashMap::new();
+    /// people.insert("Albin", 15);
+    /// people.insert("Jones", 22);
+    /// people.insert("Charlie", 27);
+    /// assert_eq!(people.len(), 3);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn len(&self) -> usize {
+        self._len()
+    }
+
+    /// Checks if the map is empty or not.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let map = DashMap::<(), ()>::new();
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_empty(&self) -> bool {
+        self._is_empty()
+    }
+
+    /// Removes all key-value pairs in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let stats = DashMap::new();
+    /// stats.insert("Goals", 4);
+    /// assert!(!stats.is_empty());
+    /// stats.clear();
+    /// assert!(stats.is_empty());
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn clear(&self) {
+        self._clear();
+    }
+
+    /// Returns how many key-value pairs the map can store without reallocating.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn capacity(&self) -> usize {
+        self._capacity()
+    }
+
+    /// Modify a specific value according to a function.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let stats = DashMap::new();
+    /// stats.insert("Goals", 4);
+    /// stats.alter("Goals", |_, v| v * 2);
+    /// assert_eq!(*stats.get("Goals").unwrap(), 8);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the given closure panics, then `alter` will abort the process
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn alter<Q>(&self, key: &Q, f: impl FnOnce(&K, V) -> V)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._alter(key, f);
+    }
+
+    /// Modify every value in the map according to a function.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let stats = DashMap::new();
+    /// stats.insert("Wins", 4);
+    /// stats.insert("Losses", 2);
+    /// stats.alter_all(|_, v| v + 1);
+    /// assert_eq!(*stats.get("Wins").unwrap(), 5);
+    /// assert_eq!(*stats.get("Losses").unwrap(), 3);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the given closure panics, then `alter_all` will abort the process
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn alter_all(&self, f: impl FnMut(&K, V) -> V) {
+        self._alter_all(f);
+    }
+
+    /// Runs `f` with exclusive access to all of `keys` at once, and writes its changes back only
+    /// if it returns `Ok`. Nothing is written if it returns `Err` or panics.
+    ///
+    /// The shards holding `keys` are write locked in shard order for the whole call, so concurrent
+    /// transactions never deadlock each other. `f` works on copies of the values and may only
+    /// access the keys passed in.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let accounts = DashMap::new();
+    /// accounts.insert("alice", 50);
+    /// accounts.insert("bob", 20);
+    ///
+    /// let transfer = |from, to, amount| {
+    ///     accounts.transaction(vec![from, to], |tx| {
+    ///         *tx.get_mut(&to).unwrap() += amount;
+    ///
+    ///         let balance = tx.get_mut(&from).unwrap();
+    ///
+    ///         if *balance < amount {
+    ///             return Err("insufficient funds");
+    ///         }
+    ///
+    ///         *balance -= amount;
+    ///         Ok(())
+    ///     })
+    /// };
+    ///
+    /// assert_eq!(transfer("alice", "bob", 30), Ok(()));
+    /// assert_eq!(transfer("alice", "bob", 30), Err("insufficient funds"));
+    /// assert_eq!(*accounts.get("alice").unwrap(), 20);
+    /// assert_eq!(*accounts.get("bob").unwrap(), 50);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn transaction<R, E>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        f: impl FnOnce(&mut Transaction<K, V>) -> Result<R, E>,
+    ) -> Result<R, E>
+    where
+        V: Clone,
+    {
+        let _layout = self.layout.read();
+
+        let keys: Vec<(K, u64, usize)> = keys
+            .into_iter()
+            .map(|key| {
+                let hash = self.hash_u64(&key);
+
+                (key, hash, self.shard_index_for(hash))
+            })
+            .collect();
+
+        let mut indices: Vec<usize> = keys.iter().map(|&(_, _, i)| i).collect();
+
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut pending = Pending::new(self.observers.observe());
+
+        let mut shards: Vec<_> = indices.iter().map(|&i| self.write_shard(i)).collect();
+
+        let mut transaction = Transaction::new();
+
+        for (key, hash, i) in keys {
+            let pos = indices.binary_search(&i).unwrap();
+            let shard = &shards[pos];
+
+            transaction.add(key, hash, pos, |key| {
+                shard
+                    .get_key_value_hashed(hash, key)
+                    .map(|(_, v)| v.get().clone())
+            });
+        }
+
+        let result = f(&mut transaction);
+
+        if result.is_ok() {
+            for (key, hash, pos, value) in transaction.into_changes() {
+                let shard = &mut shards[pos];
+
+                match value {
+                    Some(value) => {
+                        let new = pending.observed().map(|o| o.clone_pair(&key, &value));
+
+                        let old = shard
+                            .insert_hashed(hash, key, SharedValue::new(value))
+                            .map(|v| v.into_inner());
+
+                        if let Some((key, new)) = new {
+                            pending.record(|_| match old {
+                                Some(old) => Change::Updated(key, old, new),
+                                None => Change::Inserted(key, new),
+                            });
+                        }
+                    }
+                    None => {
+                        if let Some((key, old)) = shard.remove_entry_hashed(hash, &key) {
+                            pending.record(|_| Change::Removed(key, old.into_inner()));
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Checks if the map contains a specific key.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let team_sizes = DashMap::new();
+    /// team_sizes.insert("Dakota Cherries", 23);
+    /// assert!(team_sizes.contains_key("Dakota Cherries"));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._contains_key(key)
+    }
+
+    /// Advanced entry API that tries to mimic `std::collections::HashMap`.
+    /// See the documentation on `dashmap::mapref::entry` for more details.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn entry(&'a self, key: K) -> Entry<'a, K, V, S> {
+        self._entry(key)
+    }
+
+    /// Like [`entry`](DashMap::entry), but only if the shard can be locked right away.
+    /// On failure the key is handed back inside the error.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let counters = DashMap::new();
+    /// *counters.try_entry("hits").unwrap().or_insert(0) += 1;
+    /// assert_eq!(*counters.get("hits").unwrap(), 1);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_entry(&'a self, key: K) -> Result<Entry<'a, K, V, S>, TryLockError<K>> {
+        self._try_entry(key, None)
+    }
+
+    /// Like [`entry`](DashMap::entry), but waits at most `timeout` for the shard.
+    /// On failure the key is handed back inside the error.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn entry_timeout(
+        &'a self,
+        key: K,
+        timeout: Duration,
+    ) -> Result<Entry<'a, K, V, S>, TryLockError<K>> {
+        self._try_entry(key, Some(timeout))
+    }
+
+    /// Like [`entry`](DashMap::entry), but only takes an upgradeable read lock on the shard.
+    /// The lock is upgraded to a write lock only to insert, or when the entry is
+    /// [`upgrade`](UpgradeableEntry::upgrade)d, and the references it returns hold a read lock.
+    ///
+    /// With the `parking_lot` feature readers of the shard can proceed while the entry is held.
+    /// The other locks keep new readers out to avoid starving the upgrade.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let names = DashMap::new();
+    /// assert_eq!(*names.entry_upgradeable(1).or_insert("one"), "one");
+    /// assert_eq!(*names.entry_upgradeable(1).or_insert("uno"), "one");
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn entry_upgradeable(&'a self, key: K) -> UpgradeableEntry<'a, K, V, S> {
+        self._entry_upgradeable(key)
+    }
+
+    /// Returns a reference to the value of `key`, inserting the result of `f` first if it is absent.
+    ///
+    /// Meant for read-mostly maps such as caches: the lookup only takes a read lock, and on a miss
+    /// the shard is locked like [`entry_upgradeable`](DashMap::entry_upgradeable) does. Either way
+    /// the returned reference holds a read lock, so hits never wait for each other.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashMap;
+    ///
+    /// let squares = DashMap::new();
+    /// assert_eq!(*squares.get_or_insert_with(12, || 12 * 12), 144);
+    /// assert_eq!(*squares.get_or_insert_with(12, || unreachable!()), 144);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_or_insert_with(&'a self, key: K, f: impl FnOnce() -> V) -> Ref<'a, K, V, S> {
+        if let Some(r) = self._get(&key) {
+            return r;
+        }
+
+        self._entry_upgradeable(key).or_insert_with(f)
+    }
+
+    cfg_if! {
+        if #[cfg(feature = "hashbrown")] {
+            /// Looks up elements by a precomputed hash and a closure matching the key, so that keys
+            /// can be found without constructing a `K`. The hash has to be the one
+            /// [`hash_u64`](DashMap::hash_u64) returns for the key.
+            ///
+            /// Requires the `hashbrown` feature to be enabled.
+            ///
+            /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use dashmap::DashMap;
+            ///
+            /// let interned: DashMap<Box<str>, u32> = DashMap::new();
+            /// interned.insert("apple".into(), 1);
+            ///
+            /// let hash = interned.hash_u64(&"apple");
+            /// let found = interned.raw_entry().from_hash(hash, |k| &**k == "apple");
+            /// assert_eq!(*found.unwrap().value(), 1);
+            /// ```
+            pub fn raw_entry(&'a self) -> RawEntryBuilder<'a, K, V, S> {
+                RawEntryBuilder::new(self)
+            }
+
+            /// Like [`raw_entry`](DashMap::raw_entry), but write locks the shard and returns an
+            /// entry that can insert, modify or remove the element.
+            ///
+            /// Requires the `hashbrown` feature to be enabled.
+            ///
+            /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use dashmap::mapref::raw_entry::RawEntryMut;
+            /// use dashmap::DashMap;
+            ///
+            /// let interned: DashMap<Box<str>, u32> = DashMap::new();
+            ///
+            /// for word in ["apple", "pear", "apple"].iter() {
+            ///     let hash = interned.hash_u64(word);
+            ///     let next = interned.len() as u32;
+            ///
+            ///     if let RawEntryMut::Vacant(entry) = interned.raw_entry_mut().from_hash(hash, |k| &**k == *word) {
+            ///         entry.insert((*word).into(), next);
+            ///     }
+            /// }
+            ///
+            /// assert_eq!(*interned.get("pear").unwrap(), 1);
+            /// ```
+            pub fn raw_entry_mut(&'a self) -> RawEntryBuilderMut<'a, K, V, S> {
+                RawEntryBuilderMut::new(self)
+            }
+        }
+    }
+
+    /// Registers `listener` to be called with an [`Event`] for every insert, update and removal
+    /// made through `insert`, `remove`, `alter`, `retain`, the entry API and their variants.
+    /// Writes through the references handed out by `get_mut` and `iter_mut` are not reported.
+    ///
+    /// Events are delivered on the mutating thread once the shard lock has been released, so the
+    /// listener may use the map itself. Keys and values are cloned for that, but only while
+    /// anyone is subscribed. Clones of the map start out without listeners.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::{DashMap, Event};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let map = DashMap::new();
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let sink = log.clone();
+    /// let id = map.subscribe(move |event: &Event<'_, &str, i32>| {
+    ///     sink.lock().unwrap().push(format!("{:?}", event));
+    /// });
+    ///
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.remove("a");
+    /// assert!(map.unsubscribe(id));
+    /// map.insert("b", 3);
+    ///
+    /// assert_eq!(
+    ///     *log.lock().unwrap(),
+    ///     [
+    ///         r#"Inserted { key: "a", value: 1 }"#,
+    ///         r#"Updated { key: "a", old: 1, new: 2 }"#,
+    ///         r#"Removed { key: "a", value: 2 }"#,
+    ///     ]
+    /// );
+    /// ```
+    pub fn subscribe(
+        &self,
+        listener: impl Fn(&Event<'_, K, V>) + Send + Sync + 'static,
+    ) -> SubscriptionId
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.observers
+            .subscribe(Arc::new(listener), (K::clone, V::clone))
+    }
+
+    /// Removes a listener registered with [`subscribe`](DashMap::subscribe).
+    /// Returns `false` if it was already removed.
+    ///
+    /// Operations that started before the call may still deliver events to it.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.observers.unsubscribe(id)
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + BuildHasher + Clone> Map<'a, K, V, S>
+    for DashMap<K, V, S>
+{
+    fn _shard_count(&self) -> usize {
+        self.shard_count()
+    }
+
+    unsafe fn _get_read_shard(&'a self, i: usize) -> &'a HashMap<K, V, S> {
+        debug_assert!(i < self.shard_count());
+
+        self.shard(i).get()
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    unsafe fn _yield_read_shard(&'a self, i: usize) -> RwLockReadGuard<'a, HashMap<K, V, S>> {
+        debug_assert!(i < self.shard_count());
+
+        self.read_shard(i)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    unsafe fn _yield_write_shard(&'a self, i: usize) -> RwLockWriteGuard<'a, HashMap<K, V, S>> {
+        debug_assert!(i < self.shard_count());
+
+        self.write_shard(i)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    unsafe fn _try_yield_read_shard(
+        &'a self,
+        i: usize,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockReadGuard<'a, HashMap<K, V, S>>> {
+        debug_assert!(i < self.shard_count());
+
+        let shard = self.shard(i);
+
+        match timeout {
+            Some(timeout) => shard.try_read_for(timeout),
+            None => shard.try_read(),
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    unsafe fn _try_yield_write_shard(
+        &'a self,
+        i: usize,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockWriteGuard<'a, HashMap<K, V, S>>> {
+        debug_assert!(i < self.shard_count());
+
+        let shard = self.shard(i);
+
+        match timeout {
+            Some(timeout) => shard.try_write_for(timeout),
+            None => shard.try_write(),
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _insert(&self, key: K, value: V) -> Option<V> {
+        let insertion = self.observers.insertion(&key, &value);
+
+        let hash = self.hash_u64(&key);
+
+        let retv = self
+            .write_shard_for(hash)
+            .insert_hashed(hash, key, SharedValue::new(value))
+            .map(|v| v.into_inner());
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(retv.as_ref());
+        }
+
+        retv
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _try_insert_now(
+        &'a self,
+        key: K,
+        value: V,
+        timeout: Option<Duration>,
+    ) -> Result<Option<V>, TryLockError<(K, V)>> {
+        let hash = self.hash_u64(&key);
+
+        let mut shard = match self.try_write_shard_for(hash, timeout) {
+            Some(shard) => shard,
+            None => return Err(TryLockError::new(timeout, (key, value))),
+        };
+
+        let insertion = self.observers.insertion(&key, &value);
+
+        let retv = shard
+            .insert_hashed(hash, key, SharedValue::new(value))
+            .map(|v| v.into_inner());
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(retv.as_ref());
+        }
+
+        Ok(retv)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _insert_with<T, E>(
+        &self,
+        key: K,
+        value: V,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<V>, Result<T, E>) {
+        let insertion = self.observers.insertion(&key, &value);
+
+        let hash = self.hash_u64(&key);
+
+        let mut shard = self.write_shard_for(hash);
+
+        let retv = shard
+            .insert_hashed(hash, key, SharedValue::new(value))
+            .map(|v| v.into_inner());
+        let ret = util::run_fnonce_with_result(f);
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(retv.as_ref());
+        }
+
+        (retv, ret)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _insert_and_post_process<T1, E1, T2, E2, T3, E3>(
+        &self,
+        key: K,
+        value: V,
+        key_exists_func: impl FnOnce(&V) -> Result<T1, E1>,
+        not_exists_func: impl FnOnce() -> Result<T2, E2>,
+        post_func: Option<impl FnOnce() -> Result<T3, E3>>,
+    ) -> (
+        Option<V>,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+        Option<Result<T3, E3>>,
+    ) {
+        let insertion = self.observers.insertion(&key, &value);
+
+        let hash = self.hash_u64(&key);
+
+        let mut shard = self.write_shard_for(hash);
+
+        let retv = shard
+            .insert_hashed(hash, key, SharedValue::new(value))
+            .map(|v| v.into_inner());
+
+        let (key_exists_ret, not_exists_ret) = if let Some(ref prev_v) = retv {
+            (
+                Some(util::run_fnonce_with_val(prev_v, key_exists_func)),
+                None,
+            )
+        } else {
+            (None, Some(util::run_fnonce_with_result(not_exists_func)))
+        };
+        let post_ret = post_func.map(util::run_fnonce_with_result);
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(retv.as_ref());
+        }
+
+        (retv, key_exists_ret, not_exists_ret, post_ret)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let observed = self.observers.observe();
+
+        let hash = self.hash_u64(&key);
+
+        let kv = self
+            .write_shard_for(hash)
+            .remove_entry_hashed(hash, key)
+            .map(|(k, v)| (k, v.into_inner()));
+
+        if let (Some(observed), Some((k, v))) = (&observed, &kv) {
+            observed.removed(k, v);
+        }
+
+        kv
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _try_remove<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<(K, V)>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let mut shard = match self.try_write_shard_for(hash, timeout) {
+            Some(shard) => shard,
+            None => return Err(TryLockError::new(timeout, ())),
+        };
+
+        let observed = self.observers.observe();
+
+        let kv = shard
+            .remove_entry_hashed(hash, key)
+            .map(|(k, v)| (k, v.into_inner()));
+
+        drop(shard);
+
+        if let (Some(observed), Some((k, v))) = (&observed, &kv) {
+            observed.removed(k, v);
+        }
+
+        Ok(kv)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _remove_and_post_process<Q, T1, E1, T2, E2>(
+        &self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&Q, &V) -> Result<T1, E1>,
+        not_exists_func: Option<impl FnOnce() -> Result<T2, E2>>,
+    ) -> (
+        Option<(K, V)>,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+    )
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let observed = self.observers.observe();
+
+        let hash = self.hash_u64(&key);
+
+        let mut shard = self.write_shard_for(hash);
+
+        let kv = shard
+            .remove_entry_hashed(hash, key)
+            .map(|(k, v)| (k, v.into_inner()));
+        let (key_exists_ret, not_exists_ret) = match kv {
+            Some((ref k, ref v)) => {
+                let _promote_panic_to_abort = util::AbortOnPanic;
+                (Some(key_exists_func(k.borrow(), v)), None)
+            }
+            None => match not_exists_func {
+                Some(not_exists_func) => {
+                    (None, Some(util::run_fnonce_with_result(not_exists_func)))
+                }
+                None => (None, None),
+            },
+        };
+
+        drop(shard);
+
+        if let (Some(observed), Some((k, v))) = (&observed, &kv) {
+            observed.removed(k, v);
+        }
+
+        (kv, key_exists_ret, not_exists_ret)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _compare_and_swap<Q>(&self, key: &Q, expected: &V, new: V) -> CompareAndSwap<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        let insertion = match shard.get_key_value_hashed(hash, key) {
+            None => return CompareAndSwap::Absent(new),
+            Some((_, v)) if v.get() != expected => return CompareAndSwap::Mismatch(new),
+            Some((k, _)) => self.observers.insertion(k, &new),
+        };
+
+        let mut shard = shard.upgrade();
+
+        let old = core::mem::replace(shard.get_mut_hashed(hash, key).unwrap().get_mut(), new);
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(Some(&old));
+        }
+
+        CompareAndSwap::Swapped(old)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _insert_if_absent(&self, key: K, value: V) -> InsertIfAbsent<K, V> {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        if shard.get_key_value_hashed(hash, &key).is_some() {
+            return InsertIfAbsent::Present(key, value);
+        }
+
+        let insertion = self.observers.insertion(&key, &value);
+
+        shard
+            .upgrade()
+            .insert_unique_hashed(hash, key, SharedValue::new(value));
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(None);
+        }
+
+        InsertIfAbsent::Inserted
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _replace<Q>(&self, key: &Q, value: V) -> Replace<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        let insertion = match shard.get_key_value_hashed(hash, key) {
+            None => return Replace::Absent(value),
+            Some((k, _)) => self.observers.insertion(k, &value),
+        };
+
+        let mut shard = shard.upgrade();
+
+        let old = core::mem::replace(shard.get_mut_hashed(hash, key).unwrap().get_mut(), value);
+
+        drop(shard);
+
+        if let Some(insertion) = insertion {
+            insertion.deliver(Some(&old));
+        }
+
+        Replace::Replaced(old)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _update_if<Q, R>(
+        &self,
+        key: &Q,
+        predicate: impl FnOnce(&K, &V) -> bool,
+        f: impl FnOnce(&K, &mut V) -> R,
+    ) -> UpdateIf<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut pending = Pending::new(self.observers.observe());
+
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        match shard.get_key_value_hashed(hash, key) {
+            None => return UpdateIf::Absent,
+            Some((k, v)) if !predicate(k, v.get()) => return UpdateIf::Rejected,
+            Some(_) => {}
+        }
+
+        let shard = shard.upgrade();
+
+        let (k, v) = shard.get_key_value_hashed(hash, key).unwrap();
+
+        // SAFETY: The shard is write locked and `shard` is not used to access it meanwhile.
+        let v = unsafe { &mut *v.as_ptr() };
+
+        let old = pending.observed().map(|o| o.clone_value(v));
+
+        let result = f(k, v);
+
+        if let Some(old) = old {
+            pending.record(|o| Change::Updated(o.clone_key(k), old, o.clone_value(v)));
+        }
+
+        UpdateIf::Updated(result)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _remove_if_eq<Q>(&self, key: &Q, expected: &V) -> RemoveIfEq<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        let observed = self.observers.observe();
+
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        match shard.get_key_value_hashed(hash, key) {
+            None => return RemoveIfEq::Absent,
+            Some((_, v)) if v.get() != expected => return RemoveIfEq::Mismatch,
+            Some(_) => {}
+        }
+
+        let (k, v) = shard.upgrade().remove_entry_hashed(hash, key).unwrap();
+        let v = v.into_inner();
+
+        if let Some(observed) = &observed {
+            observed.removed(&k, &v);
+        }
+
+        RemoveIfEq::Removed(k, v)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _remove_if<Q>(&self, key: &Q, f: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let observed = self.observers.observe();
+
+        let hash = self.hash_u64(&key);
+
+        let mut shard = self.write_shard_for(hash);
+
+        let kv = if let Some((k, v)) = shard.get_key_value_hashed(hash, key) {
+            if f(k, v.get()) {
+                shard
+                    .remove_entry_hashed(hash, key)
+                    .map(|(k, v)| (k, v.into_inner()))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        drop(shard);
+
+        if let (Some(observed), Some((k, v))) = (&observed, &kv) {
+            observed.removed(k, v);
+        }
+
+        kv
+    }
+
+    fn _lock_layout(&'a self) -> RwLockReadGuard<'a, ()> {
+        self.layout.read()
+    }
+
+    fn _iter(&'a self) -> Iter<'a, K, V, S, DashMap<K, V, S>> {
+        Iter::new(self)
+    }
+
+    fn _iter_mut(&'a self) -> IterMut<'a, K, V, S, DashMap<K, V, S>> {
+        IterMut::new(self)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.read_shard_for(hash);
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = util::change_lifetime_const(vptr);
+
+                Some(Ref::new(shard, kptr, vptr.get()))
+            }
+        } else {
+            None
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _try_get<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Ref<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = match self.try_read_shard_for(hash, timeout) {
+            Some(shard) => shard,
+            None => return Err(TryLockError::new(timeout, ())),
+        };
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = util::change_lifetime_const(vptr);
+
+                Ok(Some(Ref::new(shard, kptr, vptr.get())))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _get_with<Q, T, E>(
+        &'a self,
+        key: &Q,
+        post_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.read_shard_for(hash);
+
+        let val = if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = util::change_lifetime_const(vptr);
+
+                Some(Ref::new(shard, kptr, vptr.get()))
+            }
+        } else {
+            None
+        };
+        let ret = post_func();
+        (val, ret)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _get_and_post_process<Q, T, E>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&V) -> Result<T, E>,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.read_shard_for(hash);
+
+        let val = if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = util::change_lifetime_const(vptr);
+
+                Some(Ref::new(shard, kptr, vptr.get()))
+            }
+        } else {
+            None
+        };
+        // # Safety
+        //
+        // If the closure panics, we must abort otherwise we could double drop `T`
+        let ret = {
+            let _promote_panic_to_abort = util::AbortOnPanic;
+            if let Some(ref kv) = val {
+                key_exists_func(kv.value())
+            } else {
+                not_exists_func()
+            }
+        };
+        (val, ret)
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _get_mut<Q>(&'a self, key: &Q) -> Option<RefMut<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = self.write_shard_for(hash);
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = &mut *vptr.as_ptr();
+
+                Some(RefMut::new(shard, kptr, vptr))
+            }
+        } else {
+            None
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _try_get_mut<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<RefMut<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_u64(&key);
+
+        let shard = match self.try_write_shard_for(hash, timeout) {
+            Some(shard) => shard,
+            None => return Err(TryLockError::new(timeout, ())),
+        };
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = &mut *vptr.as_ptr();
+
+                Ok(Some(RefMut::new(shard, kptr, vptr)))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _shrink_to_fit(&self) {
+        let _layout = self.layout.read();
+
+        for i in 0..self.shard_count() {
+            self.write_shard(i).shrink_to_fit();
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _retain(&self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        let _layout = self.layout.read();
+
+        let observed = self.observers.observe();
+
+        for i in 0..self.shard_count() {
+            let mut pending = Pending::new(observed.clone());
+
+            self.write_shard(i).retain(|k, v| {
+                let keep = f(k, v.get_mut());
+
+                if !keep {
+                    pending.record(|o| Change::Removed(o.clone_key(k), o.clone_value(v.get())));
+                }
+
+                keep
+            });
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _len(&self) -> usize {
+        let _layout = self.layout.read();
+
+        let mut len = 0;
+
+        for i in 0..self.shard_count() {
+            len += self.read_shard(i).len();
+        }
+
+        len
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _capacity(&self) -> usize {
+        let _layout = self.layout.read();
+
+        let mut capacity = 0;
+
+        for i in 0..self.shard_count() {
+            capacity += self.read_shard(i).capacity();
+        }
+
+        capacity
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _alter<Q>(&self, key: &Q, f: impl FnOnce(&K, V) -> V)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut pending = Pending::new(self.observers.observe());
+
+        if let Some(mut r) = self.get_mut(key) {
+            let old = pending.observed().map(|o| o.clone_value(r.value()));
+
+            util::map_in_place_2(r.pair_mut(), f);
+
+            if let Some(old) = old {
+                pending.record(|o| {
+                    Change::Updated(o.clone_key(r.key()), old, o.clone_value(r.value()))
+                });
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _alter_all(&self, mut f: impl FnMut(&K, V) -> V) {
+        let _layout = self.layout.read();
+
+        let observed = self.observers.observe();
+
+        for i in 0..self.shard_count() {
+            let mut pending = Pending::new(observed.clone());
+
+            self.write_shard(i).iter_mut().for_each(|(k, v)| {
+                let old = pending.observed().map(|o| o.clone_value(v.get()));
+
+                util::map_in_place_2((k, v.get_mut()), &mut f);
+
+                if let Some(old) = old {
+                    pending
+                        .record(|o| Change::Updated(o.clone_key(k), old, o.clone_value(v.get())));
+                }
+            });
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _entry(&'a self, key: K) -> Entry<'a, K, V, S> {
+        let pending = Pending::new(self.observers.observe());
+
+        let hash = self.hash_u64(&key);
+
+        let shard = self.write_shard_for(hash);
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, &key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = &mut *vptr.as_ptr();
+
+                Entry::Occupied(OccupiedEntry::new(shard, key, hash, (kptr, vptr), pending))
+            }
+        } else {
+            Entry::Vacant(VacantEntry::new(shard, key, hash, pending))
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _try_entry(
+        &'a self,
+        key: K,
+        timeout: Option<Duration>,
+    ) -> Result<Entry<'a, K, V, S>, TryLockError<K>> {
+        let pending = Pending::new(self.observers.observe());
+
+        let hash = self.hash_u64(&key);
+
+        let shard = match self.try_write_shard_for(hash, timeout) {
+            Some(shard) => shard,
+            None => return Err(TryLockError::new(timeout, key)),
+        };
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, &key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = &mut *vptr.as_ptr();
+
+                Ok(Entry::Occupied(OccupiedEntry::new(
+                    shard,
+                    key,
+                    hash,
+                    (kptr, vptr),
+                    pending,
+                )))
+            }
+        } else {
+            Ok(Entry::Vacant(VacantEntry::new(shard, key, hash, pending)))
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn _entry_upgradeable(&'a self, key: K) -> UpgradeableEntry<'a, K, V, S> {
+        let pending = Pending::new(self.observers.observe());
+
+        let hash = self.hash_u64(&key);
+
+        let shard = self.upgradeable_shard_for(hash);
+
+        if let Some((kptr, vptr)) = shard.get_key_value_hashed(hash, &key) {
+            unsafe {
+                let kptr = util::change_lifetime_const(kptr);
+
+                let vptr = util::change_lifetime_const(vptr);
+
+                UpgradeableEntry::Occupied(UpgradeableOccupiedEntry::new(
+                    shard,
+                    key,
+                    hash,
+                    (kptr, vptr),
+                    pending,
+                ))
+            }
+        } else {
+            UpgradeableEntry::Vacant(UpgradeableVacantEntry::new(shard, key, hash, pending))
+        }
+    }
+
+    fn _hasher(&self) -> S {
+        self.hasher.clone()
+    }
+}
+
+impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher + Clone> fmt::Debug
+    for DashMap<K, V, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pmap = f.debug_map();
+
+        for r in self {
+            let (k, v) = r.pair();
+
+            pmap.entry(k, v);
+        }
+
+        pmap.finish()
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> Shl<(K, V)> for &'a DashMap<K, V, S> {
+    type Output = Option<V>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn shl(self, pair: (K, V)) -> Self::Output {
+        self.insert(pair.0, pair.1)
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> Shr<&Q> for &'a DashMap<K, V, S>
+where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = Ref<'a, K, V, S>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn shr(self, key: &Q) -> Self::Output {
+        self.get(key).unwrap()
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> BitOr<&Q> for &'a DashMap<K, V, S>
+where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = RefMut<'a, K, V, S>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn bitor(self, key: &Q) -> Self::Output {
+        self.get_mut(key).unwrap()
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> Sub<&Q> for &'a DashMap<K, V, S>
+where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = Option<(K, V)>;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn sub(self, key: &Q) -> Self::Output {
+        self.remove(key)
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> BitAnd<&Q> for &'a DashMap<K, V, S>
+where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = bool;
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn bitand(self, key: &Q) -> Self::Output {
+        self.contains_key(key)
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher + Clone> IntoIterator for DashMap<K, V, S> {
+    type Item = (K, V);
+
+    type IntoIter = OwningIter<K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        OwningIter::new(self)
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> IntoIterator for &'a DashMap<K, V, S> {
+    type Item = RefMulti<'a, K, V, S>;
+
+    type IntoIter = Iter<'a, K, V, S, DashMap<K, V, S>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher + Clone> Extend<(K, V)> for DashMap<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, intoiter: I) {
+        self.extend_batched(intoiter, EXTEND_BATCH_SIZE);
+    }
+}
+
+impl<K: Eq + Hash, V> FromIterator<(K, V)> for DashMap<K, V, RandomState> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(intoiter: I) -> Self {
+        let mut map = DashMap::new();
+
+        map.extend(intoiter);
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DashMap, Map, TryLockError};
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::{Context, Poll, Waker};
+    use std::collections::hash_map::RandomState;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::{Duration, Instant};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn poll_once<F: Future>(fut: Pin<&mut F>) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+
+        fut.poll(&mut Context::from_waker(&waker))
+    }
+
+    struct Gate<'a>(&'a AtomicBool);
+
+    impl<'a> Future for Gate<'a> {
+        type Output = Result<(), ()>;
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.0.load(Ordering::SeqCst) {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn test_basic() {
+        let dm = DashMap::new();
+
+        dm.insert(0, 0);
+
+        assert_eq!(dm.get(&0).unwrap().value(), &0);
+    }
+
+    #[test]
+    fn test_default() {
+        let dm: DashMap<u32, u32> = DashMap::default();
+
+        dm.insert(0, 0);
+
+        assert_eq!(dm.get(&0).unwrap().value(), &0);
+    }
+
+    #[test]
+    fn test_multiple_hashes() {
+        let dm: DashMap<u32, u32> = DashMap::default();
+
+        for i in 0..100 {
+            dm.insert(0, i);
+
+            dm.insert(i, i);
+        }
+
+        for i in 1..100 {
+            let r = dm.get(&i).unwrap();
+
+            assert_eq!(i, *r.value());
+
+            assert_eq!(i, *r.key());
+        }
+
+        let r = dm.get(&0).unwrap();
+
+        assert_eq!(99, *r.value());
+    }
+
+    #[test]
+    fn test_more_complex_values() {
+        #[derive(Hash, PartialEq, Debug, Clone)]
+
+        struct T0 {
+            s: String,
+            u: u8,
+        }
+
+        let dm = DashMap::new();
+
+        let range = 0..10;
+
+        for i in range {
+            let t = T0 {
+                s: i.to_string(),
+                u: i as u8,
+            };
+
+            dm.insert(i, t.clone());
+
+            assert_eq!(&t, dm.get(&i).unwrap().value());
+        }
+    }
+
+    #[test]
+    fn test_different_hashers_randomstate() {
+        let dm_hm_default: DashMap<u32, u32, RandomState> =
+            DashMap::with_hasher(RandomState::new());
+
+        for i in 0..10 {
+            dm_hm_default.insert(i, i);
+
+            assert_eq!(i, *dm_hm_default.get(&i).unwrap().value());
+        }
+    }
+
+    #[test]
+    fn test_async_unlocked_does_not_hold_shard() {
+        let dm = DashMap::new();
+        let open = AtomicBool::new(false);
+
+        let insert = dm.insert_and_post_process_async_fn_unlocked(
+            1,
+            1,
+            |_| core::future::ready(Ok::<(), ()>(())),
+            || Gate(&open),
+            None::<fn() -> core::future::Ready<Result<(), ()>>>,
+        );
+        let mut insert = Box::pin(insert);
+        assert!(poll_once(insert.as_mut()).is_pending());
+
+        let idx = dm.determine_shard(dm.hash_usize(&1));
+        assert!(dm.shards()[idx].try_write().is_some());
+        assert_eq!(*dm.get(&1).unwrap(), 1);
+
+        let get = dm.get_and_post_process_ke_async_unlocked(
+            &1,
+            |v| core::future::ready(Ok::<i32, ()>(*v)),
+            || Err(()),
+        );
+        let mut get = Box::pin(get);
+        assert!(poll_once(get.as_mut()).is_pending());
+
+        open.store(true, Ordering::SeqCst);
+
+        match poll_once(insert.as_mut()) {
+            Poll::Ready((prev, exists, not_exists, post)) => {
+                assert_eq!(prev, None);
+                assert!(exists.is_none());
+                assert_eq!(not_exists, Some(Ok(())));
+                assert!(post.is_none());
+            }
+            Poll::Pending => panic!("insert should have completed"),
+        }
+
+        match poll_once(get.as_mut()) {
+            Poll::Ready((r, ret)) => {
+                assert_eq!(*r.unwrap(), 1);
+                assert_eq!(ret, Ok(1));
+            }
+            Poll::Pending => panic!("get should have completed"),
+        };
+    }
+
+    #[test]
+    fn test_try_methods_do_not_block() {
+        let dm = DashMap::new();
+
+        dm.insert(1, 1);
+
+        let guard = dm.get_mut(&1).unwrap();
+
+        assert!(matches!(dm.try_get(&1), Err(TryLockError::WouldBlock(()))));
+        assert!(matches!(
+            dm.try_get_mut(&1),
+            Err(TryLockError::WouldBlock(()))
+        ));
+        assert!(matches!(
+            dm.try_remove(&1),
+            Err(TryLockError::WouldBlock(()))
+        ));
+        assert_eq!(dm.try_insert_now(1, 2).unwrap_err().into_inner(), (1, 2));
+        assert_eq!(dm.try_entry(1).err().unwrap().into_inner(), 1);
+
+        let start = Instant::now();
+        let err = dm.get_timeout(&1, Duration::from_millis(20)).err().unwrap();
+        assert!(err.is_timeout());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(dm
+            .insert_timeout(1, 2, Duration::from_millis(1))
+            .unwrap_err()
+            .is_timeout());
+
+        drop(guard);
+
+        assert_eq!(*dm.try_get(&1).unwrap().unwrap(), 1);
+        assert_eq!(dm.try_insert_now(1, 2).unwrap(), Some(1));
+        *dm.get_mut_timeout(&1, Duration::from_millis(20))
+            .unwrap()
+            .unwrap() += 1;
+        assert_eq!(
+            *dm.entry_timeout(1, Duration::from_millis(20))
+                .unwrap()
+                .or_insert(0),
+            3
+        );
+        assert_eq!(
+            dm.remove_timeout(&1, Duration::from_millis(20)).unwrap(),
+            Some((1, 3))
+        );
+        assert!(dm.try_get(&1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_shard_amount() {
+        let dm = DashMap::with_shard_amount(2);
+
+        assert_eq!(dm._shard_count(), 2);
+
+        for i in 0..100 {
+            dm.insert(i, i);
+        }
+
+        assert_eq!(dm.len(), 100);
+        assert!(dm.shards().iter().all(|s| !s.read().is_empty()));
+
+        let dm: DashMap<i32, i32> =
+            DashMap::with_capacity_and_hasher_and_shard_amount(64, RandomState::new(), 256);
+
+        assert_eq!(dm._shard_count(), 256);
+        assert!(dm.capacity() >= 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn test_shard_amount_not_power_of_two() {
+        DashMap::<i32, i32>::with_shard_amount(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "greater than 1")]
+    fn test_shard_amount_one() {
+        DashMap::<i32, i32>::with_shard_amount(1);
+    }
+
+    #[test]
+    fn test_try_reshard_for_gives_up() {
+        let dm = DashMap::with_shard_amount(4);
+
+        for i in 0..1000 {
+            dm.insert(i, i * 2);
+        }
+
+        let iter = dm.iter();
+        let err = dm
+            .try_reshard_for(64, Duration::from_millis(20))
+            .unwrap_err();
+        drop(iter);
+
+        assert!(err.is_timeout());
+
+        // The move stays half done, with every entry still reachable.
+        assert_eq!(dm.shard_amount(), 4);
+        assert_eq!(dm.len(), 1000);
+        assert_eq!(dm.iter().count(), 1000);
+
+        for i in 0..1000 {
+            assert_eq!(*dm.get(&i).unwrap(), i * 2);
+        }
+
+        dm.reshard(16);
+
+        assert_eq!(dm.shard_amount(), 16);
+        assert_eq!(dm._shard_count(), 16);
+        assert_eq!(dm.len(), 1000);
+    }
+
+    #[test]
+    fn test_reshard() {
+        let dm = DashMap::with_shard_amount(4);
+
+        for i in 0..1000 {
+            dm.insert(i, i * 2);
+        }
+
+        for &amount in &[64, 2, 4, 4, 256] {
+            dm.reshard(amount);
+
+            assert_eq!(dm.shard_amount(), amount);
+            assert_eq!(dm._shard_count(), amount);
+            assert_eq!(dm.len(), 1000);
+            assert_eq!(dm.iter().count(), 1000);
+
+            for i in 0..1000 {
+                assert_eq!(*dm.get(&i).unwrap(), i * 2);
+            }
+        }
+
+        assert_eq!(dm.tables.read().len(), 5);
+    }
+
+    #[test]
+    fn test_partially_migrated() {
+        let dm = DashMap::with_shard_amount(4);
+
+        for i in 0..1000 {
+            dm.insert(i, i);
+        }
+
+        {
+            let mut tables = dm.tables.write();
+
+            tables.push(Box::new(crate::new_table(0, &dm.hasher, 16)));
+            dm.table().set_next(tables.last().unwrap());
+
+            assert!(dm.migrate_shard(dm.table(), tables.last().unwrap(), 0));
+            assert!(dm.shards()[0].read().is_empty());
+        }
+
+        assert_eq!(dm._shard_count(), 20);
+        assert_eq!(dm.len(), 1000);
+
+        let mut seen = std::collections::HashSet::new();
+
+        for r in dm.iter() {
+            assert!(seen.insert(*r.key()));
+        }
+
+        assert_eq!(seen.len(), 1000);
+
+        for i in 0..1000 {
+            assert_eq!(dm.remove(&i), Some((i, i)));
+            assert!(dm.insert(i, i + 1).is_none());
+        }
+
+        let clone = dm.clone();
+        let view = clone.clone().into_read_only();
+
+        assert_eq!(view.len(), 1000);
+
+        for i in 0..1000 {
+            assert_eq!(view.get(&i), Some(&(i + 1)));
+        }
+
+        assert_eq!(clone.into_iter().count(), 1000);
+
+        dm.reshard(8);
+
+        assert_eq!(dm._shard_count(), 8);
+
+        for i in 0..1000 {
+            assert_eq!(*dm.get(&i).unwrap(), i + 1);
+        }
+    }
+
+    #[test]
+    fn test_scan_position() {
+        let mut hash: u64 = 1;
+
+        for &amount in &[2, 4, 64, 1024] {
+            let table: crate::Table<u32, u32, RandomState> =
+                crate::new_table(0, &RandomState::new(), amount);
+
+            for _ in 0..1000 {
+                hash = hash.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+
+                let pos = crate::table::scan_position(hash);
+                let (i, end) = table.scan_shard(pos);
+
+                assert_eq!(i, table.determine_shard(hash as usize));
+                assert!(!matches!(end, Some(end) if pos >= end));
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_batched_partially_migrated() {
+        for &(from, to) in &[(4, 16), (16, 4)] {
+            let dm = DashMap::with_shard_amount(from);
+
+            for i in 0..1000 {
+                dm.insert(i, i);
+            }
+
+            {
+                let mut tables = dm.tables.write();
+
+                tables.push(Box::new(crate::new_table(0, &dm.hasher, to)));
+                dm.table().set_next(tables.last().unwrap());
+
+                for i in (0..from).step_by(3) {
+                    assert!(dm.migrate_shard(dm.table(), tables.last().unwrap(), i));
+                }
+            }
+
+            for &batch_size in &[1, 7, 1000, 5000] {
+                let mut seen: Vec<u32> = dm.iter_batched(batch_size).map(|(k, _)| k).collect();
+
+                seen.sort_unstable();
+
+                assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_batched_equal_hashes() {
+        #[derive(Clone, Default)]
+        struct Constant;
+
+        impl core::hash::Hasher for Constant {
+            fn finish(&self) -> u64 {
+                42
+            }
+
+            fn write(&mut self, _: &[u8]) {}
+        }
+
+        let dm: DashMap<u32, u32, core::hash::BuildHasherDefault<Constant>> =
+            DashMap::with_hasher(Default::default());
+
+        for i in 0..10 {
+            dm.insert(i, i);
+        }
+
+        // Entries sharing a position are never split between batches.
+        let mut seen: Vec<u32> = dm.iter_batched(3).map(|(k, _)| k).collect();
+
+        seen.sort_unstable();
+
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_batched_concurrently() {
+        const STABLE: u32 = 2000;
+
+        let dm = Arc::new(DashMap::with_shard_amount(4));
+        let done = Arc::new(AtomicBool::new(false));
+
+        for i in 0..STABLE {
+            dm.insert(i, i);
+        }
+
+        let writer = {
+            let dm = dm.clone();
+            let done = done.clone();
+
+            std::thread::spawn(move || {
+                let mut round = 0;
+
+                while !done.load(Ordering::Relaxed) {
+                    for k in STABLE..STABLE + 100 {
+                        dm.insert(k, round);
+                    }
+
+                    for k in STABLE..STABLE + 100 {
+                        dm.remove(&k);
+                    }
+
+                    dm.reshard(if round % 2 == 0 { 64 } else { 2 });
+
+                    round += 1;
+                }
+            })
+        };
+
+        for _ in 0..20 {
+            let mut seen = std::collections::HashSet::new();
+
+            for (k, v) in dm.iter_batched(16) {
+                assert!(seen.insert(k), "{} seen twice", k);
+
+                if k < STABLE {
+                    assert_eq!(k, v);
+                }
+            }
+
+            assert!((0..STABLE).all(|i| seen.contains(&i)));
+        }
+
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_scan() {
+        let dm = DashMap::with_shard_amount(8);
+
+        for i in 0..1000 {
+            dm.insert(i, i);
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = Some(crate::Cursor::START);
+        let mut round = 0;
+
+        while let Some(current) = cursor {
+            let (page, next) = dm.scan(current, 64);
+
+            assert!(page.len() >= 64 || next.is_none());
+
+            for (k, _) in page {
+                assert!(seen.insert(k), "{} seen twice", k);
+            }
+
+            // Keys below 500 stay put, the rest come and go between pages.
+            dm.remove(&(500 + round));
+            dm.insert(1000 + round, 0);
+
+            if round == 3 {
+                dm.reshard(64);
+            }
+
+            cursor = next;
+            round += 1;
+        }
+
+        assert!((0..500).all(|i| seen.contains(&i)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_scan_cursor_serde() {
+        let dm: DashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+
+        let (first, next) = dm.scan(crate::Cursor::START, 40);
+
+        let json = serde_json::to_string(&next.unwrap()).unwrap();
+        let cursor: crate::Cursor = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(cursor, next.unwrap());
+
+        let (rest, next) = dm.scan(cursor, 100);
+
+        assert!(next.is_none());
+        assert_eq!(first.len() + rest.len(), 100);
+    }
+
+    #[test]
+    fn test_reshard_concurrently() {
+        const WRITERS: usize = 4;
+        const KEYS: usize = 500;
+        const STABLE: usize = 1000;
+
+        let dm = Arc::new(DashMap::with_shard_amount(2));
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Keys below `STABLE` are never touched again, so every scan has to see each of them once.
+        for i in 0..STABLE {
+            dm.insert(i, i);
+        }
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|t| {
+                let dm = dm.clone();
+                let done = done.clone();
+
+                std::thread::spawn(move || {
+                    let keys = STABLE + t * KEYS..STABLE + (t + 1) * KEYS;
+                    let mut round = 0;
+
+                    while !done.load(Ordering::Relaxed) || round < 2 {
+                        for k in keys.clone() {
+                            assert!(dm.insert(k, round).is_none());
+                        }
+
+                        for k in keys.clone() {
+                            assert_eq!(*dm.get(&k).unwrap(), round);
+
+                            *dm.get_mut(&k).unwrap() += 1;
+                        }
+
+                        for k in keys.clone() {
+                            assert_eq!(dm.remove(&k), Some((k, round + 1)));
+                        }
+
+                        round += 1;
+                    }
+
+                    for k in keys {
+                        dm.insert(k, k);
+                    }
+                })
+            })
+            .collect();
+
+        let scanner = {
+            let dm = dm.clone();
+            let done = done.clone();
+
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let mut seen = std::collections::HashSet::new();
+
+                    for r in dm.iter() {
+                        assert!(seen.insert(*r.key()), "{} seen twice", r.key());
+                    }
+
+                    assert!((0..STABLE).all(|i| seen.contains(&i)));
+                    assert!(dm.len() >= STABLE);
+
+                    // Scans hold back migration steps, give the resize a chance to make progress.
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        for _ in 0..3 {
+            for &amount in &[64, 4, 256, 8, 2] {
+                dm.reshard(amount);
+
+                for i in 0..STABLE {
+                    assert_eq!(*dm.get(&i).unwrap(), i);
+                }
+            }
+        }
+
+        done.store(true, Ordering::Relaxed);
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        scanner.join().unwrap();
+
+        assert_eq!(dm.len(), STABLE + WRITERS * KEYS);
+
+        for i in 0..STABLE + WRITERS * KEYS {
+            assert_eq!(*dm.get(&i).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_is_consistent() {
+        const KEYS: u64 = 64;
+
+        let dm = Arc::new(DashMap::with_shard_amount(16));
+        let done = Arc::new(AtomicBool::new(false));
+
+        for i in 0..KEYS {
+            dm.insert(i, 0);
+        }
+
+        // Bumps every key in order, so at any instant earlier keys are at most one round ahead.
+        let writer = {
+            let dm = dm.clone();
+            let done = done.clone();
+
+            std::thread::spawn(move || {
+                let mut round = 0;
+
+                while !done.load(Ordering::Relaxed) {
+                    round += 1;
+
+                    for i in 0..KEYS {
+                        dm.insert(i, round);
+                    }
+                }
+            })
+        };
+
+        for _ in 0..200 {
+            let snapshot = dm.snapshot();
+            let values: Vec<u64> = (0..KEYS).map(|i| *snapshot.get(&i).unwrap()).collect();
+
+            assert!(values.windows(2).all(|w| w[0] >= w[1]), "{:?}", values);
+            assert!(values[0] - values[KEYS as usize - 1] <= 1, "{:?}", values);
+        }
+
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_transaction_rolls_back() {
+        let dm = DashMap::new();
+
+        dm.insert(1, 10);
+        dm.insert(2, 20);
+
+        let result: Result<(), ()> = dm.transaction(vec![1, 2, 3], |tx| {
+            tx.remove(&1);
+            tx.insert(&3, 30);
+            *tx.get_mut(&2).unwrap() += 1;
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(dm.len(), 2);
+        assert_eq!(*dm.get(&1).unwrap(), 10);
+        assert_eq!(*dm.get(&2).unwrap(), 20);
+
+        let result: Result<_, ()> = dm.transaction(vec![1, 2, 3, 1], |tx| {
+            let moved = tx.remove(&1).unwrap();
+
+            tx.insert(&3, moved);
+
+            Ok(tx.contains_key(&1))
+        });
+
+        assert_eq!(result, Ok(false));
+        assert!(!dm.contains_key(&1));
+        assert_eq!(*dm.get(&3).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_transaction_concurrent_transfers() {
+        const ACCOUNTS: u64 = 32;
+        const THREADS: u64 = 4;
+
+        let dm = Arc::new(DashMap::with_shard_amount(8));
+
+        for i in 0..ACCOUNTS {
+            dm.insert(i, 100);
+        }
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let dm = dm.clone();
+
+                std::thread::spawn(move || {
+                    for n in 0..2000 {
+                        let from = (n * 7 + t) % ACCOUNTS;
+                        let to = (n * 13 + t * 5 + 1) % ACCOUNTS;
+
+                        let _ = dm.transaction(vec![from, to], |tx| {
+                            if from == to || *tx.get(&from).unwrap() < 3 {
+                                return Err(());
+                            }
+
+                            *tx.get_mut(&from).unwrap() -= 3;
+                            *tx.get_mut(&to).unwrap() += 3;
+
+                            Ok(())
+                        });
+
+                        if n % 100 == 0 {
+                            dm.reshard(if n % 200 == 0 { 4 } else { 16 });
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..50 {
+            assert_eq!(dm.snapshot().values().sum::<u64>(), ACCOUNTS * 100);
+        }
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(dm.iter().map(|r| *r.value()).sum::<u64>(), ACCOUNTS * 100);
+    }
+
+    #[test]
+    fn test_compare_and_swap_counts_exactly() {
+        const THREADS: u64 = 4;
+        const INCREMENTS: u64 = 1000;
+
+        let dm = Arc::new(DashMap::with_shard_amount(2));
+
+        dm.insert("counter", 0);
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let dm = dm.clone();
+
+                std::thread::spawn(move || {
+                    for _ in 0..INCREMENTS {
+                        loop {
+                            let current = *dm.get("counter").unwrap();
+
+                            if dm
+                                .compare_and_swap("counter", &current, current + 1)
+                                .is_swapped()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*dm.get("counter").unwrap(), THREADS * INCREMENTS);
+    }
+
+    #[test]
+    fn test_entry_upgradeable() {
+        use crate::mapref::entry::Entry;
+        use crate::mapref::upgradeable::UpgradeableEntry;
+
+        let dm = DashMap::new();
+
+        dm.insert(1, "a");
+
+        {
+            let entry = dm.entry_upgradeable(1);
 
-        let idx = self.determine_shard(hash);
+            // Readers only get through next to an upgradeable lock with `parking_lot`.
+            assert_eq!(dm.try_get(&1).is_ok(), cfg!(feature = "parking_lot"));
+            assert!(dm.try_get_mut(&1).is_err());
+            assert!(matches!(entry, UpgradeableEntry::Occupied(_)));
+        }
 
-        let shard = unsafe { self._yield_read_shard(idx) };
+        match dm.entry_upgradeable(1).upgrade() {
+            Entry::Occupied(mut entry) => assert_eq!(entry.insert("b"), "a"),
+            Entry::Vacant(_) => unreachable!(),
+        }
 
-        let val = if let Some((kptr, vptr)) = shard.get_key_value(key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+        {
+            let r = dm.entry_upgradeable(2).or_insert("c");
 
-                let vptr = util::change_lifetime_const(vptr);
+            // The reference returned after inserting only holds a read lock.
+            assert_eq!(*dm.try_get(&2).unwrap().unwrap(), "c");
+            assert_eq!(*r, "c");
+        }
 
-                Some(Ref::new(shard, kptr, vptr.get()))
-            }
-        } else {
-            None
-        };
-        let ret = post_func();
-        (val, ret)
+        assert_eq!(*dm.get_or_insert_with(1, || "d"), "b");
+        assert_eq!(*dm.get_or_insert_with(3, || "d"), "d");
+        assert_eq!(dm.len(), 3);
     }
 
-    fn _get_and_post_process<Q, T, E>(
-        &'a self,
-        key: &Q,
-        key_exists_func: impl FnOnce(&V) -> Result<T, E>,
-        not_exists_func: impl FnOnce() -> Result<T, E>,
-    ) -> (Option<Ref<'a, K, V, S>>, Result<T, E>)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    #[test]
+    fn test_fallible_entry() {
+        use crate::mapref::entry::Entry;
 
-        let idx = self.determine_shard(hash);
+        let dm = DashMap::new();
 
-        let shard = unsafe { self._yield_read_shard(idx) };
+        *dm.try_insert(1, 10).unwrap() += 1;
 
-        let val = if let Some((kptr, vptr)) = shard.get_key_value(key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+        let err = dm.try_insert(1, 20).err().unwrap();
 
-                let vptr = util::change_lifetime_const(vptr);
+        assert_eq!(
+            err.to_string(),
+            "failed to insert 20, key 1 already exists with value 11"
+        );
+        assert_eq!(err.entry.remove(), 11);
 
-                Some(Ref::new(shard, kptr, vptr.get()))
-            }
-        } else {
-            None
+        let mut entry = match dm.entry(2) {
+            Entry::Vacant(entry) => entry.insert_entry(5),
+            Entry::Occupied(_) => unreachable!(),
         };
-        // # Safety
-        //
-        // If the closure panics, we must abort otherwise we could double drop `T`
-        let ret = {
-            let _promote_panic_to_abort = util::AbortOnPanic;
-            if let Some(ref kv) = val {
-                key_exists_func(kv.value())
-            } else {
-                not_exists_func()
-            }
-        };
-        (val, ret)
+
+        assert_eq!(entry.insert(6), 5);
+        assert_eq!(entry.into_key(), 2);
+
+        assert_eq!(*dm.entry(3).or_insert_with_key(|k| k * 100), 300);
+        assert_eq!(*dm.entry(3).or_insert_with_key(|_| unreachable!()), 300);
+        assert_eq!(*dm.get(&2).unwrap(), 6);
+        assert!(!dm.contains_key(&1));
     }
 
-    fn _get_mut<Q>(&'a self, key: &Q) -> Option<RefMut<'a, K, V, S>>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.hash_usize(&key);
+    #[test]
+    fn test_batches() {
+        let dm = DashMap::with_shard_amount(4);
 
-        let idx = self.determine_shard(hash);
+        let previous = dm.insert_many((0..100).map(|i| (i % 50, i)));
 
-        let shard = unsafe { self._yield_write_shard(idx) };
+        assert!(previous[..50].iter().all(Option::is_none));
+        assert_eq!(previous[50..], (0..50).map(Some).collect::<Vec<_>>()[..]);
+        assert_eq!(dm.len(), 50);
 
-        if let Some((kptr, vptr)) = shard.get_key_value(key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+        dm.reshard(16);
 
-                let vptr = &mut *vptr.as_ptr();
+        let keys: Vec<u32> = (0..60).rev().collect();
+        let values = dm.get_many(&keys);
 
-                Some(RefMut::new(shard, kptr, vptr))
-            }
-        } else {
-            None
+        for (key, value) in keys.iter().zip(values) {
+            assert_eq!(value, if *key < 50 { Some(key + 50) } else { None });
         }
-    }
 
-    fn _shrink_to_fit(&self) {
-        self.shards.iter().for_each(|s| s.write().shrink_to_fit());
-    }
-
-    fn _retain(&self, mut f: impl FnMut(&K, &mut V) -> bool) {
-        self.shards
-            .iter()
-            .for_each(|s| s.write().retain(|k, v| f(k, v.get_mut())));
-    }
+        let removed = dm.remove_many(&[3, 70, 3]);
 
-    fn _len(&self) -> usize {
-        self.shards.iter().map(|s| s.read().len()).sum()
-    }
+        assert_eq!(removed, [Some((3, 53)), None, None]);
 
-    fn _capacity(&self) -> usize {
-        self.shards.iter().map(|s| s.read().capacity()).sum()
-    }
+        let mut dm: DashMap<u32, u32> = (0..5000).map(|i| (i, i)).collect();
 
-    fn _alter<Q>(&self, key: &Q, f: impl FnOnce(&K, V) -> V)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(mut r) = self.get_mut(key) {
-            util::map_in_place_2(r.pair_mut(), f);
-        }
-    }
+        dm.extend((5000..6000).map(|i| (i, i)));
 
-    fn _alter_all(&self, mut f: impl FnMut(&K, V) -> V) {
-        self.shards.iter().for_each(|s| {
-            s.write()
-                .iter_mut()
-                .for_each(|(k, v)| util::map_in_place_2((k, v.get_mut()), &mut f));
-        });
+        assert_eq!(dm.len(), 6000);
+        assert!((0..6000).all(|i| *dm.get(&i).unwrap() == i));
     }
 
-    fn _entry(&'a self, key: K) -> Entry<'a, K, V, S> {
-        let hash = self.hash_usize(&key);
-
-        let idx = self.determine_shard(hash);
+    #[test]
+    fn test_keys_values() {
+        let dm: DashMap<u32, u32> = (0..100).map(|i| (i, i * 2)).collect();
 
-        let shard = unsafe { self._yield_write_shard(idx) };
+        let mut keys: Vec<u32> = dm.keys().map(|k| *k).collect();
 
-        if let Some((kptr, vptr)) = shard.get_key_value(&key) {
-            unsafe {
-                let kptr = util::change_lifetime_const(kptr);
+        keys.sort_unstable();
 
-                let vptr = &mut *vptr.as_ptr();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
 
-                Entry::Occupied(OccupiedEntry::new(shard, key, (kptr, vptr)))
-            }
-        } else {
-            Entry::Vacant(VacantEntry::new(shard, key))
-        }
-    }
+        dm.values_mut().for_each(|mut v| *v += 1);
 
-    fn _hasher(&self) -> S {
-        self.hasher.clone()
+        assert_eq!(dm.values().map(|v| *v).sum::<u32>(), 9_900 + 100);
     }
-}
-
-impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher + Clone> fmt::Debug
-    for DashMap<K, V, S>
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut pmap = f.debug_map();
 
-        for r in self {
-            let (k, v) = r.pair();
+    #[test]
+    fn test_drain_concurrent_inserts() {
+        let dm: Arc<DashMap<u32, u32>> = Arc::new(DashMap::with_shard_amount(8));
 
-            pmap.entry(k, v);
+        for i in 0..10_000 {
+            dm.insert(i, i);
         }
 
-        pmap.finish()
-    }
-}
+        let writer = {
+            let dm = dm.clone();
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> Shl<(K, V)> for &'a DashMap<K, V, S> {
-    type Output = Option<V>;
+            std::thread::spawn(move || {
+                for i in 10_000..20_000 {
+                    dm.insert(i, i);
+                }
+            })
+        };
 
-    fn shl(self, pair: (K, V)) -> Self::Output {
-        self.insert(pair.0, pair.1)
-    }
-}
+        let mut drained: Vec<u32> = dm
+            .drain()
+            .map(|(k, v)| {
+                assert_eq!(k, v);
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> Shr<&Q> for &'a DashMap<K, V, S>
-where
-    K: Borrow<Q>,
-    Q: Hash + Eq + ?Sized,
-{
-    type Output = Ref<'a, K, V, S>;
+                k
+            })
+            .collect();
 
-    fn shr(self, key: &Q) -> Self::Output {
-        self.get(key).unwrap()
-    }
-}
+        writer.join().unwrap();
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> BitOr<&Q> for &'a DashMap<K, V, S>
-where
-    K: Borrow<Q>,
-    Q: Hash + Eq + ?Sized,
-{
-    type Output = RefMut<'a, K, V, S>;
+        // Every key was either drained or inserted into a shard after it was emptied.
+        drained.extend(dm.drain().map(|(k, _)| k));
+        drained.sort_unstable();
 
-    fn bitor(self, key: &Q) -> Self::Output {
-        self.get_mut(key).unwrap()
+        assert_eq!(drained, (0..20_000).collect::<Vec<_>>());
+        assert!(dm.is_empty());
     }
-}
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> Sub<&Q> for &'a DashMap<K, V, S>
-where
-    K: Borrow<Q>,
-    Q: Hash + Eq + ?Sized,
-{
-    type Output = Option<(K, V)>;
+    #[test]
+    fn test_extract_if_concurrent_inserts() {
+        let dm: Arc<DashMap<u32, u32>> = Arc::new(DashMap::with_shard_amount(8));
 
-    fn sub(self, key: &Q) -> Self::Output {
-        self.remove(key)
-    }
-}
+        for i in 0..10_000 {
+            dm.insert(i, i);
+        }
 
-impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, Q> BitAnd<&Q> for &'a DashMap<K, V, S>
-where
-    K: Borrow<Q>,
-    Q: Hash + Eq + ?Sized,
-{
-    type Output = bool;
+        let writer = {
+            let dm = dm.clone();
 
-    fn bitand(self, key: &Q) -> Self::Output {
-        self.contains_key(key)
-    }
-}
+            std::thread::spawn(move || {
+                for i in 10_000..20_000 {
+                    dm.insert(i, i);
+                }
+            })
+        };
 
-impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> IntoIterator for DashMap<K, V, S> {
-    type Item = (K, V);
+        let extracted: Vec<u32> = dm.extract_if(|k, _| k % 2 == 0).map(|(k, _)| k).collect();
 
-    type IntoIter = OwningIter<K, V, S>;
+        writer.join().unwrap();
 
-    fn into_iter(self) -> Self::IntoIter {
-        OwningIter::new(self)
-    }
-}
+        assert!(extracted.iter().all(|k| k % 2 == 0));
+        assert!((0..10_000)
+            .filter(|k| k % 2 == 0)
+            .all(|k| extracted.contains(&k)));
 
-impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> IntoIterator for &'a DashMap<K, V, S> {
-    type Item = RefMulti<'a, K, V, S>;
+        // Odd keys are never extracted, and each even key is either extracted or still present.
+        for i in 0..20_000 {
+            assert_eq!(dm.contains_key(&i), i % 2 == 1 || !extracted.contains(&i));
+        }
 
-    type IntoIter = Iter<'a, K, V, S, DashMap<K, V, S>>;
+        let before = dm.len();
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
+        let mut it = dm.extract_if(|_, _| true);
+        assert!(it.next().is_some());
+        drop(it);
+
+        // Only the yielded entry is gone, the rest of its shard was put back.
+        assert_eq!(dm.len(), before - 1);
     }
-}
 
-impl<K: Eq + Hash, V, S: BuildHasher + Clone> Extend<(K, V)> for DashMap<K, V, S> {
-    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, intoiter: I) {
-        for pair in intoiter.into_iter() {
-            self.insert(pair.0, pair.1);
+    #[test]
+    fn test_extract_if_early_drop() {
+        let dm: DashMap<u32, u32> = DashMap::with_shard_amount(2);
+
+        for i in 0..100 {
+            dm.insert(i, i);
         }
-    }
-}
 
-impl<K: Eq + Hash, V> FromIterator<(K, V)> for DashMap<K, V, RandomState> {
-    fn from_iter<I: IntoIterator<Item = (K, V)>>(intoiter: I) -> Self {
-        let mut map = DashMap::new();
+        let first = dm.extract_if(|_, v| *v % 2 == 0).next().unwrap();
+        assert_eq!(first.0 % 2, 0);
+        assert_eq!(dm.len(), 99);
 
-        map.extend(intoiter);
+        assert_eq!(dm.extract_if(|_, v| *v % 2 == 0).take(10).count(), 10);
+        assert_eq!(dm.len(), 89);
 
-        map
-    }
-}
+        assert!(dm.extract_if(|k, _| *k == 51).any(|(k, _)| k == 51));
+        assert_eq!(dm.len(), 88);
 
-#[cfg(test)]
-mod tests {
-    use crate::DashMap;
-    use std::collections::hash_map::RandomState;
+        // A key inserted again before the put back keeps its new value.
+        let dm: DashMap<u32, u32> = DashMap::with_shard_amount(2);
 
-    #[test]
-    fn test_basic() {
-        let dm = DashMap::new();
+        for i in 0..100 {
+            dm.insert(i, i);
+        }
 
-        dm.insert(0, 0);
+        let mut it = dm.extract_if(|_, _| true);
+        let (yielded, _) = it.next().unwrap();
+        let pending = (0..100)
+            .find(|&i| i != yielded && !dm.contains_key(&i))
+            .unwrap();
 
-        assert_eq!(dm.get(&0).unwrap().value(), &0);
+        dm.insert(pending, 1000);
+        drop(it);
+
+        assert_eq!(dm.len(), 99);
+        assert_eq!(*dm.get(&pending).unwrap(), 1000);
+        assert!(!dm.contains_key(&yielded));
     }
 
     #[test]
-    fn test_default() {
-        let dm: DashMap<u32, u32> = DashMap::default();
+    fn test_extract_if_panicking_predicate() {
+        let dm: DashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
 
-        dm.insert(0, 0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            dm.extract_if(|_, _| panic!("predicate failed")).count()
+        }));
 
-        assert_eq!(dm.get(&0).unwrap().value(), &0);
+        assert!(result.is_err());
+        assert_eq!(dm.len(), 100);
+        assert!((0..100).all(|i| *dm.get(&i).unwrap() == i));
     }
 
+    #[cfg(feature = "rayon")]
     #[test]
-    fn test_multiple_hashes() {
-        let dm: DashMap<u32, u32> = DashMap::default();
+    fn test_par_iter() {
+        use rayon::iter::{
+            IntoParallelIterator, IntoParallelRefIterator, ParallelExtend, ParallelIterator,
+        };
 
-        for i in 0..100 {
-            dm.insert(0, i);
+        // Few shards with many elements each, so the shards themselves get split up with hashbrown.
+        let mut dm: DashMap<u64, u64> = DashMap::with_shard_amount(2);
 
-            dm.insert(i, i);
-        }
+        dm.par_extend((0..10_000u64).into_par_iter().map(|i| (i, i)));
 
-        for i in 1..100 {
-            let r = dm.get(&i).unwrap();
+        dm.par_iter_mut().for_each(|mut r| *r *= 2);
 
-            assert_eq!(i, *r.value());
+        assert_eq!(
+            dm.par_iter().map(|r| *r.value()).sum::<u64>(),
+            9_999 * 10_000
+        );
 
-            assert_eq!(i, *r.key());
-        }
+        let mut pairs: Vec<_> = dm.into_par_iter().collect();
 
-        let r = dm.get(&0).unwrap();
+        pairs.sort_unstable();
 
-        assert_eq!(99, *r.value());
+        assert!(pairs
+            .iter()
+            .zip(0..)
+            .all(|(&(k, v), i)| k == i && v == 2 * i));
     }
 
+    #[cfg(feature = "hashbrown")]
     #[test]
-    fn test_more_complex_values() {
-        #[derive(Hash, PartialEq, Debug, Clone)]
+    fn test_raw_entry() {
+        use crate::mapref::raw_entry::RawEntryMut;
 
-        struct T0 {
-            s: String,
-            u: u8,
+        let dm: DashMap<String, u32> = DashMap::with_shard_amount(4);
+
+        for i in 0..100 {
+            let key = i.to_string();
+            let hash = dm.hash_u64(&key.as_str());
+
+            match dm.raw_entry_mut().from_hash(hash, |k| *k == key) {
+                RawEntryMut::Vacant(entry) => entry.insert(key, i),
+                RawEntryMut::Occupied(_) => unreachable!(),
+            };
         }
 
-        let dm = DashMap::new();
+        dm.reshard(16);
 
-        let range = 0..10;
+        // Keys are found after resharding without building a `String`.
+        for i in 0..100 {
+            let key = i.to_string();
+            let hash = dm.hash_u64(&key.as_str());
 
-        for i in range {
-            let t = T0 {
-                s: i.to_string(),
-                u: i as u8,
-            };
+            assert_eq!(*dm.get(&key).unwrap(), i);
+            assert_eq!(*dm.raw_entry().from_hash(hash, |k| *k == key).unwrap(), i);
+        }
 
-            dm.insert(i, t.clone());
+        let hash = dm.hash_u64(&"7");
 
-            assert_eq!(&t, dm.get(&i).unwrap().value());
+        match dm.raw_entry_mut().from_key_hashed_nocheck(hash, "7") {
+            RawEntryMut::Occupied(mut entry) => assert_eq!(entry.insert(70), 7),
+            RawEntryMut::Vacant(_) => unreachable!(),
+        }
+
+        match dm.raw_entry_mut().from_key("8") {
+            RawEntryMut::Occupied(entry) => assert_eq!(entry.remove_entry(), ("8".to_string(), 8)),
+            RawEntryMut::Vacant(_) => unreachable!(),
         }
+
+        assert_eq!(*dm.raw_entry().from_key("7").unwrap(), 70);
+        assert!(dm.raw_entry().from_key("8").is_none());
+        assert_eq!(dm.len(), 99);
     }
 
     #[test]
-    fn test_different_hashers_randomstate() {
-        let dm_hm_default: DashMap<u32, u32, RandomState> =
-            DashMap::with_hasher(RandomState::new());
+    fn test_subscribe() {
+        use crate::Event;
+        use std::sync::Mutex;
 
-        for i in 0..10 {
-            dm_hm_default.insert(i, i);
+        let dm = Arc::new(DashMap::with_shard_amount(4));
+        let log = Arc::new(Mutex::new(Vec::new()));
 
-            assert_eq!(i, *dm_hm_default.get(&i).unwrap().value());
-        }
+        let id = {
+            let log = log.clone();
+            let weak = Arc::downgrade(&dm);
+
+            dm.subscribe(move |event: &Event<'_, u32, u32>| {
+                // Delivered after the shard is unlocked, so reading the map must not deadlock.
+                let now = weak.upgrade().unwrap().get(event.key()).map(|v| *v);
+
+                let entry = match *event {
+                    Event::Inserted { key, value } => (*key, None, Some(*value)),
+                    Event::Updated { key, old, new } => (*key, Some(*old), Some(*new)),
+                    Event::Removed { key, value } => (*key, Some(*value), None),
+                };
+
+                log.lock().unwrap().push((entry, now));
+            })
+        };
+
+        let take = || std::mem::take(&mut *log.lock().unwrap());
+
+        dm.insert(1, 10);
+        dm.insert(1, 11);
+        assert_eq!(
+            take(),
+            [
+                ((1, None, Some(10)), Some(10)),
+                ((1, Some(10), Some(11)), Some(11))
+            ]
+        );
+
+        dm.alter(&1, |_, v| v + 1);
+        *dm.entry(2).or_insert(20) += 1;
+        dm.entry(2).and_modify(|v| *v *= 2);
+        assert_eq!(
+            take(),
+            [
+                ((1, Some(11), Some(12)), Some(12)),
+                ((2, None, Some(20)), Some(21)),
+                ((2, Some(21), Some(42)), Some(42)),
+            ]
+        );
+
+        dm.insert(3, 30);
+        take();
+        dm.retain(|k, _| *k != 3);
+        dm.remove(&1);
+        assert_eq!(
+            take(),
+            [((3, Some(30), None), None), ((1, Some(12), None), None)]
+        );
+
+        // Not reported: the shard is still locked while the reference is alive.
+        *dm.get_mut(&2).unwrap() = 0;
+        assert_eq!(take(), []);
+
+        assert!(dm.unsubscribe(id));
+        assert!(!dm.unsubscribe(id));
+
+        dm.insert(4, 40);
+        dm.clear();
+        assert_eq!(take(), []);
     }
 }
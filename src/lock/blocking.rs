@@ -0,0 +1,278 @@
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+#[derive(Debug)]
+struct State {
+    readers: usize,
+    writer: bool,
+    upgradable: bool,
+    waiting_writers: usize,
+}
+
+/// Reader-writer lock word built on `std::sync::{Mutex, Condvar}`.
+/// Waiting threads are parked by the OS instead of spinning.
+///
+/// In fair mode new readers are held back while any writer is waiting.
+#[derive(Debug)]
+pub struct RawRwLock {
+    state: Mutex<State>,
+    released: Condvar,
+    fair: bool,
+}
+
+impl RawRwLock {
+    pub const fn new() -> Self {
+        Self::with_fairness(false)
+    }
+
+    pub const fn new_fair() -> Self {
+        Self::with_fairness(true)
+    }
+
+    const fn with_fairness(fair: bool) -> Self {
+        Self {
+            state: Mutex::new(State {
+                readers: 0,
+                writer: false,
+                upgradable: false,
+                waiting_writers: 0,
+            }),
+            released: Condvar::new(),
+            fair,
+        }
+    }
+
+    /// Whether a new reader or upgradeable reader has to wait.
+    fn blocks_readers(&self, state: &State) -> bool {
+        state.writer || state.upgradable || (self.fair && state.waiting_writers != 0)
+    }
+
+    fn blocks_writers(state: &State) -> bool {
+        state.writer || state.upgradable || state.readers != 0
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // The state is never left inconsistent by a panic, so poisoning can be ignored.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait_while<'a>(
+        &self,
+        state: MutexGuard<'a, State>,
+        condition: impl FnMut(&mut State) -> bool,
+    ) -> MutexGuard<'a, State> {
+        self.released
+            .wait_while(state, condition)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait_until<'a>(
+        &self,
+        state: MutexGuard<'a, State>,
+        deadline: Instant,
+        condition: impl FnMut(&mut State) -> bool,
+    ) -> MutexGuard<'a, State> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+
+        match self.released.wait_timeout_while(state, timeout, condition) {
+            Ok((state, _)) => state,
+            Err(poisoned) => poisoned.into_inner().0,
+        }
+    }
+
+    pub fn lock_shared(&self) {
+        // Like the spinning lock, a held upgradeable lock prevents new readers
+        // so that the upgrade is not starved.
+        let mut state = self.wait_while(self.state(), |s| self.blocks_readers(s));
+
+        state.readers += 1;
+    }
+
+    pub fn try_lock_shared(&self) -> bool {
+        let mut state = self.state();
+
+        if self.blocks_readers(&state) {
+            false
+        } else {
+            state.readers += 1;
+
+            true
+        }
+    }
+
+    pub fn try_lock_shared_until(&self, deadline: Instant) -> bool {
+        let mut state = self.wait_until(self.state(), deadline, |s| self.blocks_readers(s));
+
+        if self.blocks_readers(&state) {
+            false
+        } else {
+            state.readers += 1;
+
+            true
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in read mode.
+    pub unsafe fn unlock_shared(&self) {
+        let mut state = self.state();
+
+        debug_assert!(state.readers > 0);
+
+        state.readers -= 1;
+
+        if state.readers == 0 {
+            self.released.notify_all();
+        }
+    }
+
+    pub fn lock_exclusive(&self) {
+        let mut state = self.state();
+
+        if Self::blocks_writers(&state) {
+            state.waiting_writers += 1;
+
+            state = self.wait_while(state, |s| Self::blocks_writers(s));
+
+            state.waiting_writers -= 1;
+        }
+
+        state.writer = true;
+    }
+
+    pub fn try_lock_exclusive_until(&self, deadline: Instant) -> bool {
+        let mut state = self.state();
+
+        if Self::blocks_writers(&state) {
+            state.waiting_writers += 1;
+
+            state = self.wait_until(state, deadline, |s| Self::blocks_writers(s));
+
+            state.waiting_writers -= 1;
+
+            if Self::blocks_writers(&state) {
+                // Readers held back by us have to re-check now that we gave up.
+                if self.fair {
+                    self.released.notify_all();
+                }
+
+                return false;
+            }
+        }
+
+        state.writer = true;
+
+        true
+    }
+
+    pub fn try_lock_exclusive(&self) -> bool {
+        let mut state = self.state();
+
+        if Self::blocks_writers(&state) {
+            false
+        } else {
+            state.writer = true;
+
+            true
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn unlock_exclusive(&self) {
+        let mut state = self.state();
+
+        debug_assert!(state.writer);
+
+        state.writer = false;
+
+        self.released.notify_all();
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn downgrade(&self) {
+        let mut state = self.state();
+
+        debug_assert!(state.writer);
+
+        state.writer = false;
+        state.readers += 1;
+
+        self.released.notify_all();
+    }
+
+    pub fn lock_upgradable(&self) {
+        let mut state = self.wait_while(self.state(), |s| self.blocks_readers(s));
+
+        state.upgradable = true;
+    }
+
+    pub fn try_lock_upgradable(&self) -> bool {
+        let mut state = self.state();
+
+        if self.blocks_readers(&state) {
+            false
+        } else {
+            state.upgradable = true;
+
+            true
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn unlock_upgradable(&self) {
+        let mut state = self.state();
+
+        debug_assert!(state.upgradable);
+
+        state.upgradable = false;
+
+        self.released.notify_all();
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn upgrade(&self) {
+        let mut state = self.wait_while(self.state(), |s| s.readers != 0);
+
+        state.upgradable = false;
+        state.writer = true;
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn try_upgrade(&self) -> bool {
+        let mut state = self.state();
+
+        if state.readers != 0 {
+            false
+        } else {
+            state.upgradable = false;
+            state.writer = true;
+
+            true
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn downgrade_upgradable(&self) {
+        let mut state = self.state();
+
+        debug_assert!(state.upgradable);
+
+        state.upgradable = false;
+        state.readers += 1;
+
+        self.released.notify_all();
+    }
+}
@@ -0,0 +1,763 @@
+//! The reader-writer lock guarding each shard.
+//!
+//! The waiting strategy is chosen with cargo features and applies to every map and set in the
+//! crate, as well as to standalone uses of [`RwLock`]:
+//!
+//! - By default waiting threads spin.
+//! - `std-lock` parks waiting threads using `std::sync::{Mutex, Condvar}`.
+//! - `parking_lot` uses the `parking_lot` crate. It takes precedence over `std-lock`.
+//!
+//! With the `parking_lot` backend a held upgradeable lock does not prevent new readers.
+//!
+//! Locks created with [`RwLock::new_fair`] prefer writers. Enabling the `fair-lock` feature
+//! creates the shard locks of every map and set this way. A thread read locking a fair lock it
+//! already holds then deadlocks as soon as another thread waits to write, so with `fair-lock`
+//! calling `get` or `iter` while holding a reference into the same map is no longer safe.
+//!
+//! The `cache-padded` feature aligns every [`RwLock`] to the cache line size, so that the lock
+//! words of neighbouring shards do not share a cache line and readers of one shard stop
+//! invalidating the line of the next. It costs up to a cache line of memory per shard.
+
+use crate::deadlock::{self, Held, Mode};
+use cfg_if::cfg_if;
+use core::cell::UnsafeCell;
+use core::default::Default;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::time::Duration;
+use std::time::Instant;
+
+cfg_if! {
+    if #[cfg(feature = "parking_lot")] {
+        mod parking;
+        use parking::RawRwLock;
+    } else if #[cfg(feature = "std-lock")] {
+        mod blocking;
+        use blocking::RawRwLock;
+    } else {
+        mod spin;
+        use spin::RawRwLock;
+    }
+}
+
+// Cores prefetch cache lines in pairs on x86_64 and fetch 128 bytes at once on aarch64 and
+// powerpc64, so those need twice the usual 64 bytes.
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64"
+        )
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        not(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64"
+        ))
+    ),
+    repr(align(64))
+)]
+pub struct RwLock<T: ?Sized> {
+    lock: RawRwLock,
+    data: UnsafeCell<T>,
+}
+
+#[derive(Debug)]
+pub struct RwLockReadGuard<'a, T: 'a + ?Sized> {
+    lock: &'a RawRwLock,
+    data: NonNull<T>,
+    held: Held,
+}
+
+unsafe impl<'a, T: Send> Send for RwLockReadGuard<'a, T> {}
+
+unsafe impl<'a, T: Sync> Sync for RwLockReadGuard<'a, T> {}
+
+#[derive(Debug)]
+pub struct RwLockWriteGuard<'a, T: 'a + ?Sized> {
+    lock: &'a RawRwLock,
+    data: NonNull<T>,
+    held: Held,
+    #[doc(hidden)]
+    _invariant: PhantomData<&'a mut T>,
+}
+
+unsafe impl<'a, T: Send> Send for RwLockWriteGuard<'a, T> {}
+
+unsafe impl<'a, T: Sync> Sync for RwLockWriteGuard<'a, T> {}
+
+#[derive(Debug)]
+pub struct RwLockUpgradeableGuard<'a, T: 'a + ?Sized> {
+    lock: &'a RawRwLock,
+    data: NonNull<T>,
+    held: Held,
+    #[doc(hidden)]
+    _invariant: PhantomData<&'a mut T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(user_data: T) -> RwLock<T> {
+        RwLock {
+            lock: RawRwLock::new(),
+            data: UnsafeCell::new(user_data),
+        }
+    }
+
+    /// Creates a writer-preferring lock.
+    ///
+    /// Once a writer is waiting, new readers and upgradeable readers wait until a writer got the lock,
+    /// so a steady stream of readers can not starve writers. As a consequence taking a read lock
+    /// while already holding one on the same lock may deadlock.
+    pub const fn new_fair(user_data: T) -> RwLock<T> {
+        RwLock {
+            lock: RawRwLock::new_fair(),
+            data: UnsafeCell::new(user_data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        let RwLock { data, .. } = self;
+
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.lock.lock_shared();
+
+        self.read_guard()
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        if self.lock.try_lock_shared() {
+            Some(self.read_guard())
+        } else {
+            None
+        }
+    }
+
+    /// Tries to acquire a read lock, waiting at most `timeout` for it.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_read_for(&self, timeout: Duration) -> Option<RwLockReadGuard<'_, T>> {
+        let acquired = match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.lock.try_lock_shared_until(deadline),
+            None => {
+                self.lock.lock_shared();
+
+                true
+            }
+        };
+
+        if acquired {
+            Some(self.read_guard())
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    ///
+    /// This is only safe if the lock is currently locked in read mode and the number of readers is not 0.
+    pub unsafe fn force_read_decrement(&self) {
+        self.lock.unlock_shared();
+
+        deadlock::release_forgotten(self.id(), Mode::Read);
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn force_write_unlock(&self) {
+        self.lock.unlock_exclusive();
+
+        deadlock::release_forgotten(self.id(), Mode::Write);
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.lock.lock_exclusive();
+
+        self.write_guard()
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        if self.lock.try_lock_exclusive() {
+            Some(self.write_guard())
+        } else {
+            None
+        }
+    }
+
+    /// Tries to acquire a write lock, waiting at most `timeout` for it.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_write_for(&self, timeout: Duration) -> Option<RwLockWriteGuard<'_, T>> {
+        let acquired = match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.lock.try_lock_exclusive_until(deadline),
+            None => {
+                self.lock.lock_exclusive();
+
+                true
+            }
+        };
+
+        if acquired {
+            Some(self.write_guard())
+        } else {
+            None
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn upgradeable_read(&self) -> RwLockUpgradeableGuard<'_, T> {
+        self.lock.lock_upgradable();
+
+        self.upgradeable_guard()
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_upgradeable_read(&self) -> Option<RwLockUpgradeableGuard<'_, T>> {
+        if self.lock.try_lock_upgradable() {
+            Some(self.upgradeable_guard())
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    /// Write locks may not be used in combination with this method.
+    pub unsafe fn get(&self) -> &T {
+        &*self.data.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Identifies this lock in the records of held locks.
+    pub(crate) fn id(&self) -> *const () {
+        &self.lock as *const RawRwLock as *const ()
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn read_guard(&self) -> RwLockReadGuard<'_, T> {
+        RwLockReadGuard {
+            lock: &self.lock,
+            data: unsafe { NonNull::new_unchecked(self.data.get()) },
+            held: Held::new(self.id(), Mode::Read),
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn write_guard(&self) -> RwLockWriteGuard<'_, T> {
+        RwLockWriteGuard {
+            lock: &self.lock,
+            data: unsafe { NonNull::new_unchecked(self.data.get()) },
+            held: Held::new(self.id(), Mode::Write),
+            _invariant: PhantomData,
+        }
+    }
+
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn upgradeable_guard(&self) -> RwLockUpgradeableGuard<'_, T> {
+        RwLockUpgradeableGuard {
+            lock: &self.lock,
+            data: unsafe { NonNull::new_unchecked(self.data.get()) },
+            held: Held::new(self.id(), Mode::Upgradeable),
+            _invariant: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> RwLock<T> {
+        RwLock::new(Default::default())
+    }
+}
+
+impl<'rwlock, T: ?Sized> RwLockUpgradeableGuard<'rwlock, T> {
+    pub fn upgrade(self) -> RwLockWriteGuard<'rwlock, T> {
+        unsafe { self.lock.upgrade() };
+
+        self.into_write_guard()
+    }
+
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'rwlock, T>, Self> {
+        if unsafe { self.lock.try_upgrade() } {
+            Ok(self.into_write_guard())
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn downgrade(self) -> RwLockReadGuard<'rwlock, T> {
+        unsafe { self.lock.downgrade_upgradable() };
+
+        let out = RwLockReadGuard {
+            lock: self.lock,
+            data: self.data,
+            held: unsafe { ptr::read(&self.held) },
+        };
+
+        out.held.set_mode(Mode::Read);
+
+        mem::forget(self);
+
+        out
+    }
+
+    fn into_write_guard(self) -> RwLockWriteGuard<'rwlock, T> {
+        let out = RwLockWriteGuard {
+            lock: self.lock,
+            data: self.data,
+            held: unsafe { ptr::read(&self.held) },
+            _invariant: PhantomData,
+        };
+
+        out.held.set_mode(Mode::Write);
+
+        mem::forget(self);
+
+        out
+    }
+}
+
+impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
+    pub fn downgrade(self) -> RwLockReadGuard<'rwlock, T> {
+        unsafe { self.lock.downgrade() };
+
+        let out = RwLockReadGuard {
+            lock: self.lock,
+            data: self.data,
+            held: unsafe { ptr::read(&self.held) },
+        };
+
+        out.held.set_mode(Mode::Read);
+
+        mem::forget(self);
+
+        out
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for RwLockReadGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for RwLockUpgradeableGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for RwLockWriteGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<'rwlock, T: ?Sized> DerefMut for RwLockWriteGuard<'rwlock, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.data.as_mut() }
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for RwLockReadGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_shared() };
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for RwLockUpgradeableGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_upgradable() };
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for RwLockWriteGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_exclusive() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[derive(Eq, PartialEq, Debug)]
+    struct NonCopy(i32);
+
+    #[cfg(feature = "cache-padded")]
+    #[test]
+    fn test_cache_padded() {
+        let shards: Vec<RwLock<u8>> = (0..4).map(RwLock::new).collect();
+
+        assert!(mem::align_of::<RwLock<u8>>() >= 64);
+
+        for pair in shards.windows(2) {
+            let (a, b) = (&pair[0] as *const _ as usize, &pair[1] as *const _ as usize);
+
+            assert!(b - a >= 64);
+            assert_eq!(a % 64, 0);
+        }
+    }
+
+    #[test]
+    fn smoke() {
+        let l = RwLock::new(());
+        drop(l.read());
+        drop(l.write());
+        drop((l.read(), l.read()));
+        drop(l.write());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_rw_arc() {
+        let arc = Arc::new(RwLock::new(0));
+        let arc2 = arc.clone();
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut lock = arc2.write();
+
+            for _ in 0..10 {
+                let tmp = *lock;
+                *lock = -1;
+                thread::yield_now();
+                *lock = tmp + 1;
+            }
+
+            tx.send(()).unwrap();
+        });
+
+        let mut children = Vec::new();
+
+        for _ in 0..5 {
+            let arc3 = arc.clone();
+
+            children.push(thread::spawn(move || {
+                let lock = arc3.read();
+                assert!(*lock >= 0);
+            }));
+        }
+
+        for r in children {
+            assert!(r.join().is_ok());
+        }
+
+        rx.recv().unwrap();
+        let lock = arc.read();
+        assert_eq!(*lock, 10);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_rw_access_in_unwind() {
+        let arc = Arc::new(RwLock::new(1));
+        let arc2 = arc.clone();
+
+        let _ = thread::spawn(move || {
+            struct Unwinder {
+                i: Arc<RwLock<isize>>,
+            }
+
+            impl Drop for Unwinder {
+                fn drop(&mut self) {
+                    let mut lock = self.i.write();
+
+                    *lock += 1;
+                }
+            }
+
+            let _u = Unwinder { i: arc2 };
+
+            panic!();
+        })
+        .join();
+
+        let lock = arc.read();
+        assert_eq!(*lock, 2);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_nested_read_while_writer_waits() {
+        let lock = Arc::new(RwLock::new(0));
+        let outer = lock.read();
+
+        let writer = {
+            let lock = lock.clone();
+
+            thread::spawn(move || *lock.write() += 1)
+        };
+
+        thread::sleep(Duration::from_millis(50));
+
+        // Not held back by the waiting writer, which would wait on `outer` in turn.
+        let inner = lock.read();
+        assert_eq!(*inner, 0);
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_read_for(Duration::from_millis(10)).is_some());
+
+        drop(inner);
+        drop(outer);
+
+        writer.join().unwrap();
+        assert_eq!(*lock.read(), 1);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_fair_writer_not_starved() {
+        let lock = Arc::new(RwLock::new_fair(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+
+        // Overlapping readers that would keep the lock read-locked forever without fairness.
+        // They give up after a while so a starved writer fails the test instead of hanging it.
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let lock = lock.clone();
+                let stop = stop.clone();
+
+                thread::spawn(move || {
+                    while !stop.load(Ordering::SeqCst) && start.elapsed() < Duration::from_secs(5) {
+                        let _r = lock.read();
+
+                        for _ in 0..100 {
+                            core::hint::spin_loop();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(50));
+
+        let waiting = Instant::now();
+        *lock.write() += 1;
+        let waited = waiting.elapsed();
+
+        stop.store(true, Ordering::SeqCst);
+
+        for r in readers {
+            r.join().unwrap();
+        }
+
+        assert!(
+            waited < Duration::from_secs(1),
+            "writer waited {:?}",
+            waited
+        );
+    }
+
+    #[test]
+    fn test_rwlock_unsized() {
+        let rw: &RwLock<[i32]> = &RwLock::new([1, 2, 3]);
+
+        {
+            let b = &mut *rw.write();
+            b[0] = 4;
+            b[2] = 5;
+        }
+
+        let comp: &[i32] = &[4, 2, 5];
+
+        assert_eq!(&*rw.read(), comp);
+    }
+
+    #[test]
+    fn test_rwlock_try_write() {
+        use std::mem::drop;
+
+        let lock = RwLock::new(0isize);
+        let read_guard = lock.read();
+        let write_result = lock.try_write();
+
+        match write_result {
+            None => (),
+            Some(_) => panic!("try_write should not succeed while read_guard is in scope"),
+        }
+
+        drop(read_guard);
+    }
+
+    #[test]
+    fn test_try_for() {
+        let m = RwLock::new(0);
+
+        {
+            let _w = m.write();
+            let start = Instant::now();
+            assert!(m.try_read_for(Duration::from_millis(20)).is_none());
+            assert!(m.try_write_for(Duration::from_millis(20)).is_none());
+            assert!(start.elapsed() >= Duration::from_millis(40));
+        }
+
+        {
+            let _r = m.try_read_for(Duration::from_millis(20)).unwrap();
+            assert!(m.try_read_for(Duration::from_millis(20)).is_some());
+            assert!(m.try_write_for(Duration::from_millis(20)).is_none());
+        }
+
+        assert!(m.try_write_for(Duration::from_millis(20)).is_some());
+    }
+
+    #[test]
+    fn test_fair_try_write_for_gives_up() {
+        let m = RwLock::new_fair(());
+
+        let r = m.read();
+        assert!(m.try_write_for(Duration::from_millis(20)).is_none());
+        assert!(m.try_read().is_some());
+        drop(r);
+    }
+
+    #[test]
+    fn test_rw_try_read() {
+        let m = RwLock::new(0);
+
+        mem::forget(m.write());
+
+        assert!(m.try_read().is_none());
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let m = RwLock::new(NonCopy(10));
+
+        assert_eq!(m.into_inner(), NonCopy(10));
+    }
+
+    #[test]
+    fn test_into_inner_drop() {
+        struct Foo(Arc<AtomicUsize>);
+
+        impl Drop for Foo {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let num_drops = Arc::new(AtomicUsize::new(0));
+        let m = RwLock::new(Foo(num_drops.clone()));
+        assert_eq!(num_drops.load(Ordering::SeqCst), 0);
+
+        {
+            let _inner = m.into_inner();
+            assert_eq!(num_drops.load(Ordering::SeqCst), 0);
+        }
+
+        assert_eq!(num_drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_force_read_decrement() {
+        let m = RwLock::new(());
+        ::std::mem::forget(m.read());
+        ::std::mem::forget(m.read());
+        ::std::mem::forget(m.read());
+        assert!(m.try_write().is_none());
+
+        unsafe {
+            m.force_read_decrement();
+            m.force_read_decrement();
+        }
+
+        assert!(m.try_write().is_none());
+
+        unsafe {
+            m.force_read_decrement();
+        }
+
+        assert!(m.try_write().is_some());
+    }
+
+    #[test]
+    fn test_force_write_unlock() {
+        let m = RwLock::new(());
+
+        ::std::mem::forget(m.write());
+
+        assert!(m.try_read().is_none());
+
+        unsafe {
+            m.force_write_unlock();
+        }
+
+        assert!(m.try_read().is_some());
+    }
+
+    #[test]
+    fn test_upgrade_downgrade() {
+        let m = RwLock::new(());
+
+        {
+            let _r = m.read();
+            let upg = m.try_upgradeable_read().unwrap();
+            #[cfg(not(feature = "parking_lot"))]
+            assert!(m.try_read().is_none());
+            assert!(m.try_write().is_none());
+            assert!(upg.try_upgrade().is_err());
+        }
+
+        {
+            let w = m.write();
+            assert!(m.try_upgradeable_read().is_none());
+            let _r = w.downgrade();
+            assert!(m.try_upgradeable_read().is_some());
+            assert!(m.try_read().is_some());
+            assert!(m.try_write().is_none());
+        }
+
+        {
+            let _u = m.upgradeable_read();
+            assert!(m.try_upgradeable_read().is_none());
+        }
+
+        assert!(m.try_upgradeable_read().unwrap().try_upgrade().is_ok());
+    }
+}
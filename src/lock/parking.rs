@@ -0,0 +1,132 @@
+use core::fmt;
+use parking_lot::lock_api::{
+    RawRwLock as _, RawRwLockDowngrade as _, RawRwLockRecursive as _, RawRwLockRecursiveTimed as _,
+    RawRwLockTimed as _, RawRwLockUpgrade as _, RawRwLockUpgradeDowngrade as _,
+};
+use std::time::Instant;
+
+/// Reader-writer lock word backed by `parking_lot`.
+/// Waiting threads spin briefly and are then parked.
+///
+/// `parking_lot` blocks new readers while a writer is parked, which would deadlock a thread
+/// read locking a shard it already holds a read lock on. So outside of fair mode read locks are
+/// taken recursively, getting in even while a writer waits.
+pub struct RawRwLock {
+    inner: parking_lot::RawRwLock,
+    fair: bool,
+}
+
+impl fmt::Debug for RawRwLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawRwLock").finish()
+    }
+}
+
+impl RawRwLock {
+    pub const fn new() -> Self {
+        Self {
+            inner: parking_lot::RawRwLock::INIT,
+            fair: false,
+        }
+    }
+
+    pub const fn new_fair() -> Self {
+        Self {
+            inner: parking_lot::RawRwLock::INIT,
+            fair: true,
+        }
+    }
+
+    pub fn lock_shared(&self) {
+        if self.fair {
+            self.inner.lock_shared()
+        } else {
+            self.inner.lock_shared_recursive()
+        }
+    }
+
+    pub fn try_lock_shared(&self) -> bool {
+        if self.fair {
+            self.inner.try_lock_shared()
+        } else {
+            self.inner.try_lock_shared_recursive()
+        }
+    }
+
+    pub fn try_lock_shared_until(&self, deadline: Instant) -> bool {
+        if self.fair {
+            self.inner.try_lock_shared_until(deadline)
+        } else {
+            self.inner.try_lock_shared_recursive_until(deadline)
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in read mode.
+    pub unsafe fn unlock_shared(&self) {
+        self.inner.unlock_shared()
+    }
+
+    pub fn lock_exclusive(&self) {
+        self.inner.lock_exclusive()
+    }
+
+    pub fn try_lock_exclusive(&self) -> bool {
+        self.inner.try_lock_exclusive()
+    }
+
+    pub fn try_lock_exclusive_until(&self, deadline: Instant) -> bool {
+        self.inner.try_lock_exclusive_until(deadline)
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn unlock_exclusive(&self) {
+        self.inner.unlock_exclusive()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn downgrade(&self) {
+        self.inner.downgrade()
+    }
+
+    pub fn lock_upgradable(&self) {
+        self.inner.lock_upgradable()
+    }
+
+    pub fn try_lock_upgradable(&self) -> bool {
+        self.inner.try_lock_upgradable()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn unlock_upgradable(&self) {
+        self.inner.unlock_upgradable()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn upgrade(&self) {
+        self.inner.upgrade()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn try_upgrade(&self) -> bool {
+        self.inner.try_upgrade()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn downgrade_upgradable(&self) {
+        self.inner.downgrade_upgradable()
+    }
+}
@@ -0,0 +1,234 @@
+use core::hint::spin_loop as cpu_relax;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const READER: usize = 1 << 2;
+
+const UPGRADED: usize = 1 << 1;
+
+const WRITER: usize = 1;
+
+/// Spinning reader-writer lock word. Waiting threads loop on `spin_loop` until the lock is free.
+///
+/// In fair mode writers announce themselves in `waiting_writers` before they start spinning,
+/// and new readers back off while any writer is waiting.
+#[derive(Debug)]
+pub struct RawRwLock {
+    lock: AtomicUsize,
+    waiting_writers: AtomicUsize,
+    fair: bool,
+}
+
+impl RawRwLock {
+    pub const fn new() -> Self {
+        Self {
+            lock: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            fair: false,
+        }
+    }
+
+    pub const fn new_fair() -> Self {
+        Self {
+            lock: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            fair: true,
+        }
+    }
+
+    fn writer_waiting(&self) -> bool {
+        self.fair && self.waiting_writers.load(Ordering::Relaxed) != 0
+    }
+
+    pub fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            cpu_relax();
+        }
+    }
+
+    pub fn try_lock_shared(&self) -> bool {
+        let value = self.lock.fetch_add(READER, Ordering::Acquire);
+
+        // We check the UPGRADED bit here so that new readers are prevented when an UPGRADED lock is held.
+        // This helps reduce writer starvation.
+        if value & (WRITER | UPGRADED) != 0 || self.writer_waiting() {
+            // Lock is taken, undo.
+            self.lock.fetch_sub(READER, Ordering::Release);
+
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn try_lock_shared_until(&self, deadline: Instant) -> bool {
+        loop {
+            if self.try_lock_shared() {
+                return true;
+            }
+
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            cpu_relax();
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in read mode.
+    pub unsafe fn unlock_shared(&self) {
+        debug_assert!(self.lock.load(Ordering::Relaxed) & !(WRITER | UPGRADED) > 0);
+
+        self.lock.fetch_sub(READER, Ordering::Release);
+    }
+
+    fn try_lock_exclusive_internal(&self, strong: bool) -> bool {
+        compare_exchange(
+            &self.lock,
+            0,
+            WRITER,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+            strong,
+        )
+        .is_ok()
+    }
+
+    pub fn lock_exclusive(&self) {
+        self.lock_exclusive_until(None);
+    }
+
+    pub fn try_lock_exclusive_until(&self, deadline: Instant) -> bool {
+        self.lock_exclusive_until(Some(deadline))
+    }
+
+    fn lock_exclusive_until(&self, deadline: Option<Instant>) -> bool {
+        if self.try_lock_exclusive_internal(false) {
+            return true;
+        }
+
+        if self.fair {
+            self.waiting_writers.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let acquired = loop {
+            if self.try_lock_exclusive_internal(false) {
+                break true;
+            }
+
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                break false;
+            }
+
+            cpu_relax();
+        };
+
+        if self.fair {
+            self.waiting_writers.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        acquired
+    }
+
+    pub fn try_lock_exclusive(&self) -> bool {
+        self.try_lock_exclusive_internal(true)
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn unlock_exclusive(&self) {
+        debug_assert_eq!(self.lock.load(Ordering::Relaxed) & WRITER, WRITER);
+
+        self.lock.fetch_and(!(WRITER | UPGRADED), Ordering::Release);
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in write mode.
+    pub unsafe fn downgrade(&self) {
+        self.lock.fetch_add(READER, Ordering::Acquire);
+
+        self.unlock_exclusive();
+    }
+
+    pub fn lock_upgradable(&self) {
+        while !self.try_lock_upgradable() {
+            cpu_relax();
+        }
+    }
+
+    pub fn try_lock_upgradable(&self) -> bool {
+        if self.writer_waiting() {
+            return false;
+        }
+
+        self.lock.fetch_or(UPGRADED, Ordering::Acquire) & (WRITER | UPGRADED) == 0
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn unlock_upgradable(&self) {
+        debug_assert_eq!(
+            self.lock.load(Ordering::Relaxed) & (WRITER | UPGRADED),
+            UPGRADED
+        );
+
+        self.lock.fetch_sub(UPGRADED, Ordering::AcqRel);
+    }
+
+    unsafe fn try_upgrade_internal(&self, strong: bool) -> bool {
+        compare_exchange(
+            &self.lock,
+            UPGRADED,
+            WRITER,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+            strong,
+        )
+        .is_ok()
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn upgrade(&self) {
+        while !self.try_upgrade_internal(false) {
+            cpu_relax();
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn try_upgrade(&self) -> bool {
+        self.try_upgrade_internal(true)
+    }
+
+    /// # Safety
+    ///
+    /// The lock must be locked in upgradeable mode.
+    pub unsafe fn downgrade_upgradable(&self) {
+        self.lock.fetch_add(READER, Ordering::Acquire);
+
+        self.unlock_upgradable();
+    }
+}
+
+fn compare_exchange(
+    atomic: &AtomicUsize,
+    current: usize,
+    new: usize,
+    success: Ordering,
+    failure: Ordering,
+    strong: bool,
+) -> Result<usize, usize> {
+    if strong {
+        atomic.compare_exchange(current, new, success, failure)
+    } else {
+        atomic.compare_exchange_weak(current, new, success, failure)
+    }
+}
@@ -1,11 +1,13 @@
 use super::one::RefMut;
 use crate::lock::RwLockWriteGuard;
+use crate::notify::{Change, Pending};
+use crate::shard::HashedShard;
 use crate::util;
 use crate::util::SharedValue;
 use crate::HashMap;
+use core::fmt;
 use core::hash::{BuildHasher, Hash};
 use core::mem;
-use core::ptr;
 use std::collections::hash_map::RandomState;
 
 pub enum Entry<'a, K, V, S = RandomState> {
@@ -18,8 +20,18 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> Entry<'a, K, V, S> {
     pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
         match self {
             Entry::Occupied(mut entry) => {
+                let old = entry.pending.observed().map(|o| o.clone_value(entry.get()));
+
                 f(entry.get_mut());
 
+                if let Some(old) = old {
+                    let (key, new) = (entry.elem.0, &*entry.elem.1);
+
+                    entry
+                        .pending
+                        .record(|o| Change::Updated(o.clone_key(key), old, o.clone_value(new)));
+                }
+
                 Entry::Occupied(entry)
             }
 
@@ -73,6 +85,20 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> Entry<'a, K, V, S> {
         }
     }
 
+    /// Return a mutable reference to the element if it exists,
+    /// otherwise insert the result of a provided function, which is given the key,
+    /// and return a mutable reference to that.
+    pub fn or_insert_with_key(self, value: impl FnOnce(&K) -> V) -> RefMut<'a, K, V, S> {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => {
+                let value = value(entry.key());
+
+                entry.insert(value)
+            }
+        }
+    }
+
     pub fn or_try_insert_with<E>(
         self,
         value: impl FnOnce() -> Result<V, E>,
@@ -87,6 +113,9 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> Entry<'a, K, V, S> {
 pub struct VacantEntry<'a, K, V, S> {
     shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
     key: K,
+    hash: u64,
+    /// Delivered once the shard above was unlocked.
+    pending: Pending<K, V>,
 }
 
 unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send for VacantEntry<'a, K, V, S> {}
@@ -97,27 +126,58 @@ unsafe impl<'a, K: Eq + Hash + Send + Sync, V: Send + Sync, S: BuildHasher> Sync
 }
 
 impl<'a, K: Eq + Hash, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
-    pub(crate) fn new(shard: RwLockWriteGuard<'a, HashMap<K, V, S>>, key: K) -> Self {
-        Self { shard, key }
+    pub(crate) fn new(
+        shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+        key: K,
+        hash: u64,
+        pending: Pending<K, V>,
+    ) -> Self {
+        Self {
+            shard,
+            key,
+            hash,
+            pending,
+        }
     }
 
     pub fn insert(mut self, value: V) -> RefMut<'a, K, V, S> {
-        unsafe {
-            let c: K = ptr::read(&self.key);
+        let key = &self.key;
 
-            self.shard.insert(self.key, SharedValue::new(value));
+        self.pending
+            .record(|o| Change::Inserted(o.clone_key(key), o.clone_value(&value)));
 
-            let (k, v) = self.shard.get_key_value(&c).unwrap();
+        unsafe {
+            let (k, v) =
+                self.shard
+                    .insert_unique_hashed(self.hash, self.key, SharedValue::new(value));
 
             let k = util::change_lifetime_const(k);
 
             let v = &mut *v.as_ptr();
 
-            let r = RefMut::new(self.shard, k, v);
+            RefMut::new(self.shard, k, v).with_pending(self.pending)
+        }
+    }
 
-            mem::forget(c);
+    /// Like [`insert`](VacantEntry::insert), but returns the entry of the inserted element.
+    ///
+    /// The returned entry keeps a copy of the key for [`OccupiedEntry::into_key`] and
+    /// [`OccupiedEntry::replace_entry`], hence `K: Clone`.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    where
+        K: Clone,
+    {
+        let key = self.key.clone();
+        let hash = self.hash;
+        let mut r = self.insert(value);
 
-            r
+        unsafe {
+            let k = util::change_lifetime_const(r.key());
+            let v = &mut *(r.value_mut() as *mut V);
+
+            let (shard, pending) = r.into_parts();
+
+            OccupiedEntry::new(shard, key, hash, (k, v), pending)
         }
     }
 
@@ -134,6 +194,9 @@ pub struct OccupiedEntry<'a, K, V, S> {
     shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
     elem: (&'a K, &'a mut V),
     key: K,
+    hash: u64,
+    /// Delivered once the shard above was unlocked.
+    pending: Pending<K, V>,
 }
 
 unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send for OccupiedEntry<'a, K, V, S> {}
@@ -147,9 +210,17 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
     pub(crate) fn new(
         shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
         key: K,
+        hash: u64,
         elem: (&'a K, &'a mut V),
+        pending: Pending<K, V>,
     ) -> Self {
-        Self { shard, elem, key }
+        Self {
+            shard,
+            elem,
+            key,
+            hash,
+            pending,
+        }
     }
 
     pub fn get(&self) -> &V {
@@ -161,11 +232,17 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
     }
 
     pub fn insert(&mut self, value: V) -> V {
-        mem::replace(self.elem.1, value)
+        let old = mem::replace(self.elem.1, value);
+        let (key, new) = (self.elem.0, &*self.elem.1);
+
+        self.pending
+            .record(|o| Change::Updated(o.clone_key(key), o.clone_value(&old), o.clone_value(new)));
+
+        old
     }
 
     pub fn into_ref(self) -> RefMut<'a, K, V, S> {
-        RefMut::new(self.shard, self.elem.0, self.elem.1)
+        RefMut::new(self.shard, self.elem.0, self.elem.1).with_pending(self.pending)
     }
 
     pub fn into_key(self) -> K {
@@ -176,23 +253,81 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
         self.elem.0
     }
 
-    pub fn remove(mut self) -> V {
-        self.shard.remove(self.elem.0).unwrap().into_inner()
+    pub fn remove(self) -> V {
+        self.remove_entry().1
     }
 
     pub fn remove_entry(mut self) -> (K, V) {
-        let (k, v) = self.shard.remove_entry(self.elem.0).unwrap();
+        let (k, v) = self
+            .shard
+            .remove_entry_hashed(self.hash, self.elem.0)
+            .unwrap();
+        let v = v.into_inner();
 
-        (k, v.into_inner())
+        self.pending
+            .record(|o| Change::Removed(o.clone_key(&k), o.clone_value(&v)));
+
+        (k, v)
     }
 
     pub fn replace_entry(mut self, value: V) -> (K, V) {
+        let (key, old) = (&self.key, &*self.elem.1);
+
+        self.pending.record(|o| {
+            Change::Updated(o.clone_key(key), o.clone_value(old), o.clone_value(&value))
+        });
+
         let nk = self.key;
 
-        let (k, v) = self.shard.remove_entry(self.elem.0).unwrap();
+        let (k, v) = self
+            .shard
+            .remove_entry_hashed(self.hash, self.elem.0)
+            .unwrap();
 
-        self.shard.insert(nk, SharedValue::new(value));
+        self.shard
+            .insert_unique_hashed(self.hash, nk, SharedValue::new(value));
 
         (k, v.into_inner())
     }
 }
+
+/// The error returned by `DashMap::try_insert` when the key is already present.
+///
+/// Holds the entry of the present element together with the value that was not inserted.
+pub struct OccupiedError<'a, K, V, S = RandomState> {
+    /// The entry of the element that is already in the map.
+    pub entry: OccupiedEntry<'a, K, V, S>,
+    /// The value that was not inserted.
+    pub value: V,
+}
+
+impl<'a, K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher> fmt::Debug
+    for OccupiedError<'a, K, V, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.entry.key())
+            .field("old_value", self.entry.get())
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
+
+impl<'a, K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher> fmt::Display
+    for OccupiedError<'a, K, V, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value,
+            self.entry.key(),
+            self.entry.get(),
+        )
+    }
+}
+
+impl<'a, K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher> std::error::Error
+    for OccupiedError<'a, K, V, S>
+{
+}
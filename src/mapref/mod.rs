@@ -1,3 +1,6 @@
 pub mod entry;
 pub mod multiple;
 pub mod one;
+#[cfg(feature = "hashbrown")]
+pub mod raw_entry;
+pub mod upgradeable;
@@ -54,6 +54,31 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> Deref for RefMulti<'a, K, V, S> {
     }
 }
 
+// --
+// -- Key
+/// A reference to the key of an element, yielded by `DashMap::keys`.
+pub struct KeyRefMulti<'a, K, V, S = RandomState> {
+    r: RefMulti<'a, K, V, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> KeyRefMulti<'a, K, V, S> {
+    pub(crate) fn new(r: RefMulti<'a, K, V, S>) -> Self {
+        Self { r }
+    }
+
+    pub fn key(&self) -> &K {
+        self.r.key()
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Deref for KeyRefMulti<'a, K, V, S> {
+    type Target = K;
+
+    fn deref(&self) -> &K {
+        self.key()
+    }
+}
+
 // --
 // -- Unique
 pub struct RefMutMulti<'a, K, V, S = RandomState> {
@@ -1,4 +1,5 @@
 use crate::lock::{RwLockReadGuard, RwLockWriteGuard};
+use crate::notify::Pending;
 use crate::HashMap;
 use core::hash::{BuildHasher, Hash};
 use core::ops::{Deref, DerefMut};
@@ -9,6 +10,8 @@ pub struct Ref<'a, K, V, S = RandomState> {
     _guard: RwLockReadGuard<'a, HashMap<K, V, S>>,
     k: &'a K,
     v: &'a V,
+    /// Delivered once the guard above was dropped.
+    _pending: Pending<K, V>,
 }
 
 unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send for Ref<'a, K, V, S> {}
@@ -24,6 +27,7 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> Ref<'a, K, V, S> {
             _guard: guard,
             k,
             v,
+            _pending: Pending::none(),
         }
     }
 
@@ -54,6 +58,8 @@ pub struct RefMut<'a, K, V, S = RandomState> {
     guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
     k: &'a K,
     v: &'a mut V,
+    /// Delivered once the guard above was dropped.
+    pending: Pending<K, V>,
 }
 
 unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send for RefMut<'a, K, V, S> {}
@@ -69,7 +75,23 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> RefMut<'a, K, V, S> {
         k: &'a K,
         v: &'a mut V,
     ) -> Self {
-        Self { guard, k, v }
+        Self {
+            guard,
+            k,
+            v,
+            pending: Pending::none(),
+        }
+    }
+
+    pub(crate) fn with_pending(mut self, pending: Pending<K, V>) -> Self {
+        self.pending = pending;
+
+        self
+    }
+
+    /// Splits off the guard and the pending changes, dropping the references into the shard.
+    pub(crate) fn into_parts(self) -> (RwLockWriteGuard<'a, HashMap<K, V, S>>, Pending<K, V>) {
+        (self.guard, self.pending)
     }
 
     pub fn key(&self) -> &K {
@@ -93,7 +115,12 @@ impl<'a, K: Eq + Hash, V, S: BuildHasher> RefMut<'a, K, V, S> {
     }
 
     pub fn downgrade(self) -> Ref<'a, K, V, S> {
-        Ref::new(self.guard.downgrade(), self.k, self.v)
+        Ref {
+            _guard: self.guard.downgrade(),
+            k: self.k,
+            v: self.v,
+            _pending: self.pending,
+        }
     }
 }
 
@@ -0,0 +1,333 @@
+//! Lookups by a precomputed hash and a matching closure, available with the `hashbrown` feature.
+//!
+//! The hash has to be the one the map's hasher produces for the key, as returned by
+//! `DashMap::hash_u64`. It selects both the shard and the bucket, so neither is hashed again.
+
+use super::one::{Ref, RefMut};
+use crate::lock::RwLockWriteGuard;
+use crate::notify::{Change, Pending};
+use crate::shard::HashedShard;
+use crate::util::{self, SharedValue};
+use crate::{hash_map, DashMap, HashMap};
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+use std::collections::hash_map::RandomState;
+
+/// Builds an immutable lookup, returned by `DashMap::raw_entry`.
+pub struct RawEntryBuilder<'a, K, V, S = RandomState> {
+    map: &'a DashMap<K, V, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> RawEntryBuilder<'a, K, V, S> {
+    pub(crate) fn new(map: &'a DashMap<K, V, S>) -> Self {
+        Self { map }
+    }
+
+    /// Looks up `key`, hashing it once for both the shard and the bucket.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_key<Q>(self, key: &Q) -> Option<Ref<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.map.hash_u64(&key);
+
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Looks up `key` by its precomputed `hash`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> Option<Ref<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Looks up the element whose key has the precomputed `hash` and satisfies `is_match`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_hash(
+        self,
+        hash: u64,
+        is_match: impl FnMut(&K) -> bool,
+    ) -> Option<Ref<'a, K, V, S>> {
+        let shard = self.map.read_shard_for(hash);
+
+        let (kptr, vptr) = shard.raw_entry().from_hash(hash, is_match)?;
+
+        unsafe {
+            let kptr = util::change_lifetime_const(kptr);
+
+            let vptr = util::change_lifetime_const(vptr);
+
+            Some(Ref::new(shard, kptr, vptr.get()))
+        }
+    }
+}
+
+/// Builds a lookup that write locks the shard, returned by `DashMap::raw_entry_mut`.
+pub struct RawEntryBuilderMut<'a, K, V, S = RandomState> {
+    map: &'a DashMap<K, V, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> RawEntryBuilderMut<'a, K, V, S> {
+    pub(crate) fn new(map: &'a DashMap<K, V, S>) -> Self {
+        Self { map }
+    }
+
+    /// Looks up `key`, hashing it once for both the shard and the bucket.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_key<Q>(self, key: &Q) -> RawEntryMut<'a, K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.map.hash_u64(&key);
+
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Looks up `key` by its precomputed `hash`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> RawEntryMut<'a, K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Looks up the element whose key has the precomputed `hash` and satisfies `is_match`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn from_hash(
+        self,
+        hash: u64,
+        is_match: impl FnMut(&K) -> bool,
+    ) -> RawEntryMut<'a, K, V, S> {
+        let pending = Pending::new(self.map.observers.observe());
+
+        let mut shard = self.map.write_shard_for(hash);
+
+        let elem = match shard.raw_entry_mut().from_hash(hash, is_match) {
+            hash_map::RawEntryMut::Occupied(mut entry) => {
+                let (k, v) = entry.get_key_value_mut();
+
+                Some((k as *const K, v.get_mut() as *mut V))
+            }
+            hash_map::RawEntryMut::Vacant(_) => None,
+        };
+
+        match elem {
+            Some((k, v)) => unsafe {
+                RawEntryMut::Occupied(RawOccupiedEntryMut {
+                    shard,
+                    elem: (&*k, &mut *v),
+                    hash,
+                    pending,
+                })
+            },
+            None => RawEntryMut::Vacant(RawVacantEntryMut {
+                shard,
+                map: self.map,
+                hash,
+                pending,
+            }),
+        }
+    }
+}
+
+/// The result of `RawEntryBuilderMut`, with the shard write locked.
+pub enum RawEntryMut<'a, K, V, S = RandomState> {
+    Occupied(RawOccupiedEntryMut<'a, K, V, S>),
+    Vacant(RawVacantEntryMut<'a, K, V, S>),
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> RawEntryMut<'a, K, V, S> {
+    /// Return a mutable reference to the element if it exists,
+    /// otherwise insert the given key and value and return a mutable reference to that.
+    ///
+    /// The key has to have the hash the entry was looked up with.
+    pub fn or_insert(self, key: K, value: V) -> RefMut<'a, K, V, S> {
+        match self {
+            RawEntryMut::Occupied(entry) => entry.into_ref(),
+            RawEntryMut::Vacant(entry) => entry.insert(key, value),
+        }
+    }
+
+    /// Return a mutable reference to the element if it exists,
+    /// otherwise insert the key and value returned by a provided function and return a mutable reference to that.
+    ///
+    /// The key has to have the hash the entry was looked up with.
+    pub fn or_insert_with(self, f: impl FnOnce() -> (K, V)) -> RefMut<'a, K, V, S> {
+        match self {
+            RawEntryMut::Occupied(entry) => entry.into_ref(),
+            RawEntryMut::Vacant(entry) => {
+                let (key, value) = f();
+
+                entry.insert(key, value)
+            }
+        }
+    }
+
+    /// Apply a function to the stored value if it exists.
+    pub fn and_modify(self, f: impl FnOnce(&K, &mut V)) -> Self {
+        match self {
+            RawEntryMut::Occupied(mut entry) => {
+                let old = entry.pending.observed().map(|o| o.clone_value(entry.get()));
+
+                let (key, value) = entry.get_key_value_mut();
+
+                f(key, value);
+
+                if let Some(old) = old {
+                    let (key, new) = (entry.elem.0, &*entry.elem.1);
+
+                    entry
+                        .pending
+                        .record(|o| Change::Updated(o.clone_key(key), old, o.clone_value(new)));
+                }
+
+                RawEntryMut::Occupied(entry)
+            }
+
+            RawEntryMut::Vacant(entry) => RawEntryMut::Vacant(entry),
+        }
+    }
+}
+
+pub struct RawOccupiedEntryMut<'a, K, V, S = RandomState> {
+    shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    elem: (&'a K, &'a mut V),
+    hash: u64,
+    /// Delivered once the shard above was unlocked.
+    pending: Pending<K, V>,
+}
+
+unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send
+    for RawOccupiedEntryMut<'a, K, V, S>
+{
+}
+
+unsafe impl<'a, K: Eq + Hash + Send + Sync, V: Send + Sync, S: BuildHasher> Sync
+    for RawOccupiedEntryMut<'a, K, V, S>
+{
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> RawOccupiedEntryMut<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        self.elem.0
+    }
+
+    pub fn get(&self) -> &V {
+        self.elem.1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.elem.1
+    }
+
+    pub fn get_key_value_mut(&mut self) -> (&K, &mut V) {
+        (self.elem.0, self.elem.1)
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        let old = mem::replace(self.elem.1, value);
+        let (key, new) = (self.elem.0, &*self.elem.1);
+
+        self.pending
+            .record(|o| Change::Updated(o.clone_key(key), o.clone_value(&old), o.clone_value(new)));
+
+        old
+    }
+
+    pub fn into_ref(self) -> RefMut<'a, K, V, S> {
+        RefMut::new(self.shard, self.elem.0, self.elem.1).with_pending(self.pending)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(mut self) -> (K, V) {
+        let (k, v) = self
+            .shard
+            .remove_entry_hashed(self.hash, self.elem.0)
+            .unwrap();
+        let v = v.into_inner();
+
+        self.pending
+            .record(|o| Change::Removed(o.clone_key(&k), o.clone_value(&v)));
+
+        (k, v)
+    }
+}
+
+pub struct RawVacantEntryMut<'a, K, V, S = RandomState> {
+    shard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    map: &'a DashMap<K, V, S>,
+    hash: u64,
+    /// Delivered once the shard above was unlocked.
+    pending: Pending<K, V>,
+}
+
+unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher + Sync> Send
+    for RawVacantEntryMut<'a, K, V, S>
+{
+}
+
+unsafe impl<'a, K: Eq + Hash + Send + Sync, V: Send + Sync, S: BuildHasher + Sync> Sync
+    for RawVacantEntryMut<'a, K, V, S>
+{
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher + Clone> RawVacantEntryMut<'a, K, V, S> {
+    /// The hash the entry was looked up with.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Inserts `key` and `value`, returning a mutable reference to them.
+    ///
+    /// # Panics
+    ///
+    /// With debug assertions, panics if `key` does not have the hash the entry was looked up with,
+    /// since it would be stored where lookups by key can not find it.
+    pub fn insert(mut self, key: K, value: V) -> RefMut<'a, K, V, S> {
+        debug_assert_eq!(
+            self.map.hash_u64(&key),
+            self.hash,
+            "key inserted into a raw entry of another hash"
+        );
+
+        let key_ref = &key;
+
+        self.pending
+            .record(|o| Change::Inserted(o.clone_key(key_ref), o.clone_value(&value)));
+
+        unsafe {
+            let (k, v) = self
+                .shard
+                .insert_unique_hashed(self.hash, key, SharedValue::new(value));
+
+            let k = util::change_lifetime_const(k);
+
+            let v = &mut *v.as_ptr();
+
+            RefMut::new(self.shard, k, v).with_pending(self.pending)
+        }
+    }
+}
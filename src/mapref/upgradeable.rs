@@ -0,0 +1,210 @@
+use super::entry::{Entry, OccupiedEntry, VacantEntry};
+use super::one::Ref;
+use crate::lock::RwLockUpgradeableGuard;
+use crate::notify::Pending;
+use crate::util::SharedValue;
+use crate::HashMap;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+
+/// An entry that holds its shard with an upgradeable read lock, returned by `DashMap::entry_upgradeable`.
+///
+/// The lock is only upgraded to a write lock to insert or once [`upgrade`](UpgradeableEntry::upgrade)
+/// is called, and the references it hands out are read references.
+pub enum UpgradeableEntry<'a, K, V, S = RandomState> {
+    Occupied(UpgradeableOccupiedEntry<'a, K, V, S>),
+    Vacant(UpgradeableVacantEntry<'a, K, V, S>),
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> UpgradeableEntry<'a, K, V, S> {
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K {
+        match *self {
+            UpgradeableEntry::Occupied(ref entry) => entry.key(),
+            UpgradeableEntry::Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    /// Into the key of the entry.
+    pub fn into_key(self) -> K {
+        match self {
+            UpgradeableEntry::Occupied(entry) => entry.into_key(),
+            UpgradeableEntry::Vacant(entry) => entry.into_key(),
+        }
+    }
+
+    /// Write locks the shard, turning this into a regular entry.
+    pub fn upgrade(self) -> Entry<'a, K, V, S> {
+        match self {
+            UpgradeableEntry::Occupied(entry) => Entry::Occupied(entry.upgrade()),
+            UpgradeableEntry::Vacant(entry) => Entry::Vacant(entry.upgrade()),
+        }
+    }
+
+    /// Return a reference to the element if it exists,
+    /// otherwise insert the default and return a reference to that.
+    pub fn or_default(self) -> Ref<'a, K, V, S>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Return a reference to the element if it exists,
+    /// otherwise insert a provided value and return a reference to that.
+    pub fn or_insert(self, value: V) -> Ref<'a, K, V, S> {
+        match self {
+            UpgradeableEntry::Occupied(entry) => entry.into_ref(),
+            UpgradeableEntry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Return a reference to the element if it exists,
+    /// otherwise insert the result of a provided function and return a reference to that.
+    pub fn or_insert_with(self, value: impl FnOnce() -> V) -> Ref<'a, K, V, S> {
+        match self {
+            UpgradeableEntry::Occupied(entry) => entry.into_ref(),
+            UpgradeableEntry::Vacant(entry) => entry.insert(value()),
+        }
+    }
+
+    /// Return a reference to the element if it exists,
+    /// otherwise insert the result of a provided function, which is given the key,
+    /// and return a reference to that.
+    pub fn or_insert_with_key(self, value: impl FnOnce(&K) -> V) -> Ref<'a, K, V, S> {
+        match self {
+            UpgradeableEntry::Occupied(entry) => entry.into_ref(),
+            UpgradeableEntry::Vacant(entry) => {
+                let value = value(entry.key());
+
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_try_insert_with<E>(
+        self,
+        value: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Ref<'a, K, V, S>, E> {
+        match self {
+            UpgradeableEntry::Occupied(entry) => Ok(entry.into_ref()),
+            UpgradeableEntry::Vacant(entry) => Ok(entry.insert(value()?)),
+        }
+    }
+}
+
+pub struct UpgradeableVacantEntry<'a, K, V, S> {
+    shard: RwLockUpgradeableGuard<'a, HashMap<K, V, S>>,
+    key: K,
+    hash: u64,
+    /// Handed to the entry this one is upgraded to.
+    pending: Pending<K, V>,
+}
+
+unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send
+    for UpgradeableVacantEntry<'a, K, V, S>
+{
+}
+
+unsafe impl<'a, K: Eq + Hash + Send + Sync, V: Send + Sync, S: BuildHasher> Sync
+    for UpgradeableVacantEntry<'a, K, V, S>
+{
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> UpgradeableVacantEntry<'a, K, V, S> {
+    pub(crate) fn new(
+        shard: RwLockUpgradeableGuard<'a, HashMap<K, V, S>>,
+        key: K,
+        hash: u64,
+        pending: Pending<K, V>,
+    ) -> Self {
+        Self {
+            shard,
+            key,
+            hash,
+            pending,
+        }
+    }
+
+    /// Upgrades the lock, inserts `value` and downgrades the lock to a read lock again.
+    pub fn insert(self, value: V) -> Ref<'a, K, V, S> {
+        self.upgrade().insert(value).downgrade()
+    }
+
+    /// Write locks the shard, turning this into a regular vacant entry.
+    pub fn upgrade(self) -> VacantEntry<'a, K, V, S> {
+        VacantEntry::new(self.shard.upgrade(), self.key, self.hash, self.pending)
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+pub struct UpgradeableOccupiedEntry<'a, K, V, S> {
+    shard: RwLockUpgradeableGuard<'a, HashMap<K, V, S>>,
+    elem: (&'a K, &'a SharedValue<V>),
+    key: K,
+    hash: u64,
+    /// Handed to the entry this one is upgraded to.
+    pending: Pending<K, V>,
+}
+
+unsafe impl<'a, K: Eq + Hash + Send, V: Send, S: BuildHasher> Send
+    for UpgradeableOccupiedEntry<'a, K, V, S>
+{
+}
+
+unsafe impl<'a, K: Eq + Hash + Send + Sync, V: Send + Sync, S: BuildHasher> Sync
+    for UpgradeableOccupiedEntry<'a, K, V, S>
+{
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> UpgradeableOccupiedEntry<'a, K, V, S> {
+    pub(crate) fn new(
+        shard: RwLockUpgradeableGuard<'a, HashMap<K, V, S>>,
+        key: K,
+        hash: u64,
+        elem: (&'a K, &'a SharedValue<V>),
+        pending: Pending<K, V>,
+    ) -> Self {
+        Self {
+            shard,
+            elem,
+            key,
+            hash,
+            pending,
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        self.elem.1.get()
+    }
+
+    /// Downgrades the lock to a read lock, keeping a reference to the element.
+    pub fn into_ref(self) -> Ref<'a, K, V, S> {
+        Ref::new(self.shard.downgrade(), self.elem.0, self.elem.1.get())
+    }
+
+    /// Write locks the shard, turning this into a regular occupied entry.
+    pub fn upgrade(self) -> OccupiedEntry<'a, K, V, S> {
+        let shard = self.shard.upgrade();
+
+        // SAFETY: The shard is write locked now and was not modified since `elem` was looked up.
+        let v = unsafe { &mut *self.elem.1.as_ptr() };
+
+        OccupiedEntry::new(shard, self.key, self.hash, (self.elem.0, v), self.pending)
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn key(&self) -> &K {
+        self.elem.0
+    }
+}
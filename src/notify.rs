@@ -0,0 +1,275 @@
+//! Change notifications for `DashMap::subscribe`.
+//!
+//! Mutating operations ask [`Observers::observe`] for the current listeners before they lock a
+//! shard, which is a single atomic load while nobody is subscribed. With listeners present they
+//! copy whatever the events need while holding the lock and deliver the events once it has
+//! been released, so that listeners are free to use the map themselves.
+
+use crate::lock::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A mutation of a `DashMap`, passed to the listeners registered with `DashMap::subscribe`.
+#[derive(Debug)]
+pub enum Event<'a, K, V> {
+    /// `key` was not in the map and now maps to `value`.
+    Inserted { key: &'a K, value: &'a V },
+    /// The value of `key` was replaced, or changed in place by `alter` or `alter_all`.
+    Updated { key: &'a K, old: &'a V, new: &'a V },
+    /// `key` was removed from the map together with `value`.
+    Removed { key: &'a K, value: &'a V },
+}
+
+impl<'a, K, V> Event<'a, K, V> {
+    /// The key the event is about.
+    pub fn key(&self) -> &'a K {
+        match *self {
+            Event::Inserted { key, .. }
+            | Event::Updated { key, .. }
+            | Event::Removed { key, .. } => key,
+        }
+    }
+}
+
+/// Identifies a listener registered with `DashMap::subscribe`, to be passed to `DashMap::unsubscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Listener<K, V> = Arc<dyn Fn(&Event<'_, K, V>) + Send + Sync>;
+
+struct State<K, V> {
+    next_id: u64,
+    /// Replaced as a whole on every change so that deliveries can keep using a snapshot.
+    listeners: Arc<[(u64, Listener<K, V>)]>,
+    /// How to copy keys and values. Only known once `subscribe`, which requires `Clone`, was called.
+    cloners: Option<(fn(&K) -> K, fn(&V) -> V)>,
+}
+
+/// The listeners of one map.
+pub(crate) struct Observers<K, V> {
+    active: AtomicBool,
+    /// Only locked by `subscribe` and `unsubscribe`.
+    state: Mutex<State<K, V>>,
+    /// The snapshot mutating operations load, replaced as a whole whenever `state` changes.
+    /// Loading it only read locks, so mutations of different shards do not serialize on it.
+    published: RwLock<Option<Observed<K, V>>>,
+}
+
+impl<K, V> Observers<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            state: Mutex::new(State {
+                next_id: 0,
+                listeners: Arc::new([]),
+                cloners: None,
+            }),
+            published: RwLock::new(None),
+        }
+    }
+
+    /// Publishes the listeners of `state` to mutating operations.
+    fn publish(&self, state: &State<K, V>) {
+        let observed = match state.cloners {
+            Some((clone_key, clone_value)) if !state.listeners.is_empty() => Some(Observed {
+                listeners: state.listeners.clone(),
+                clone_key,
+                clone_value,
+            }),
+            _ => None,
+        };
+
+        self.active.store(observed.is_some(), Ordering::Release);
+
+        *self.published.write() = observed;
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<K, V>> {
+        // Listeners never run under this lock, so it can not be poisoned half way.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn subscribe(
+        &self,
+        listener: Listener<K, V>,
+        cloners: (fn(&K) -> K, fn(&V) -> V),
+    ) -> SubscriptionId {
+        let mut state = self.state();
+        let id = state.next_id;
+
+        let mut listeners = state.listeners.to_vec();
+        listeners.push((id, listener));
+
+        state.next_id += 1;
+        state.listeners = listeners.into();
+        state.cloners = Some(cloners);
+
+        self.publish(&state);
+
+        SubscriptionId(id)
+    }
+
+    pub(crate) fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut state = self.state();
+
+        if !state.listeners.iter().any(|(i, _)| *i == id.0) {
+            return false;
+        }
+
+        let listeners: Vec<_> = state
+            .listeners
+            .iter()
+            .filter(|(i, _)| *i != id.0)
+            .cloned()
+            .collect();
+
+        state.listeners = listeners.into();
+
+        self.publish(&state);
+
+        true
+    }
+
+    /// Copies `key` and `value` for the event of an insert, if anyone is listening.
+    pub(crate) fn insertion(&self, key: &K, value: &V) -> Option<Insertion<K, V>> {
+        let observed = self.observe()?;
+        let (key, value) = observed.clone_pair(key, value);
+
+        Some(Insertion {
+            observed,
+            key,
+            value,
+        })
+    }
+
+    /// The current listeners, or `None` if there are none.
+    pub(crate) fn observe(&self) -> Option<Observed<K, V>> {
+        if !self.active.load(Ordering::Acquire) {
+            return None;
+        }
+
+        self.published.read().clone()
+    }
+}
+
+/// A snapshot of the listeners of a map, taken by an operation before it locks a shard.
+pub(crate) struct Observed<K, V> {
+    listeners: Arc<[(u64, Listener<K, V>)]>,
+    clone_key: fn(&K) -> K,
+    clone_value: fn(&V) -> V,
+}
+
+impl<K, V> Clone for Observed<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            listeners: self.listeners.clone(),
+            clone_key: self.clone_key,
+            clone_value: self.clone_value,
+        }
+    }
+}
+
+impl<K, V> Observed<K, V> {
+    pub(crate) fn clone_key(&self, key: &K) -> K {
+        (self.clone_key)(key)
+    }
+
+    pub(crate) fn clone_value(&self, value: &V) -> V {
+        (self.clone_value)(value)
+    }
+
+    pub(crate) fn clone_pair(&self, key: &K, value: &V) -> (K, V) {
+        (self.clone_key(key), self.clone_value(value))
+    }
+
+    pub(crate) fn deliver(&self, event: &Event<'_, K, V>) {
+        for (_, listener) in self.listeners.iter() {
+            listener(event);
+        }
+    }
+
+    pub(crate) fn removed(&self, key: &K, value: &V) {
+        self.deliver(&Event::Removed { key, value });
+    }
+}
+
+/// Copies of a key and value taken before an insert moved them into the map.
+pub(crate) struct Insertion<K, V> {
+    observed: Observed<K, V>,
+    key: K,
+    value: V,
+}
+
+impl<K, V> Insertion<K, V> {
+    /// Delivers `Inserted`, or `Updated` if the insert replaced `old`.
+    pub(crate) fn deliver(self, old: Option<&V>) {
+        let (key, value) = (&self.key, &self.value);
+
+        match old {
+            Some(old) => self.observed.deliver(&Event::Updated {
+                key,
+                old,
+                new: value,
+            }),
+            None => self.observed.deliver(&Event::Inserted { key, value }),
+        }
+    }
+}
+
+/// An owned copy of an event, kept until it can be delivered.
+pub(crate) enum Change<K, V> {
+    Inserted(K, V),
+    Updated(K, V, V),
+    Removed(K, V),
+}
+
+impl<K, V> Change<K, V> {
+    pub(crate) fn event(&self) -> Event<'_, K, V> {
+        match self {
+            Change::Inserted(key, value) => Event::Inserted { key, value },
+            Change::Updated(key, old, new) => Event::Updated { key, old, new },
+            Change::Removed(key, value) => Event::Removed { key, value },
+        }
+    }
+}
+
+/// Changes made through a guard that still holds the shard lock, such as an entry.
+///
+/// Declared after the guard in the structs holding it, so that the lock is released by the
+/// time the changes are delivered on drop.
+pub(crate) struct Pending<K, V>(Option<Box<(Observed<K, V>, Vec<Change<K, V>>)>>);
+
+impl<K, V> Pending<K, V> {
+    pub(crate) fn none() -> Self {
+        Pending(None)
+    }
+
+    pub(crate) fn new(observed: Option<Observed<K, V>>) -> Self {
+        Pending(observed.map(|observed| Box::new((observed, Vec::new()))))
+    }
+
+    pub(crate) fn observed(&self) -> Option<&Observed<K, V>> {
+        self.0.as_ref().map(|pending| &pending.0)
+    }
+
+    /// Records the change built by `change`, if anyone is listening.
+    pub(crate) fn record(&mut self, change: impl FnOnce(&Observed<K, V>) -> Change<K, V>) {
+        if let Some(pending) = &mut self.0 {
+            let change = change(&pending.0);
+
+            pending.1.push(change);
+        }
+    }
+}
+
+impl<K, V> Drop for Pending<K, V> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.0.take() {
+            let (observed, changes) = *pending;
+
+            for change in &changes {
+                observed.deliver(&change.event());
+            }
+        }
+    }
+}
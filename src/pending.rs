@@ -0,0 +1,95 @@
+//! Per-key markers used by the unlocked async post-process methods.
+//!
+//! A marker is keyed by the hash of the key and is held for the whole duration of an operation,
+//! including its `.await` points. Keys whose hashes collide share a marker, which only costs
+//! them concurrency. Tasks that want to operate on a marked key register their waker
+//! and are woken once the marker is released, instead of spinning on a shard lock.
+//!
+//! The markers are spread over stripes by hash, each behind a blocking mutex, so tasks marking
+//! unrelated keys rarely wait for each other and never spin.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+type Stripe = Mutex<HashMap<usize, Vec<Waker>>>;
+
+pub(crate) struct PendingKeys {
+    stripes: Box<[Stripe]>,
+}
+
+impl Default for PendingKeys {
+    fn default() -> Self {
+        let stripes = (0..crate::default_shard_amount())
+            .map(|_| Mutex::new(HashMap::new()))
+            .collect();
+
+        Self { stripes }
+    }
+}
+
+impl PendingKeys {
+    /// Locks the stripe holding the marker for `hash`.
+    fn lock(&self, hash: usize) -> MutexGuard<'_, HashMap<usize, Vec<Waker>>> {
+        // The stripe count is a power of two, like the shard amount it is taken from.
+        let stripe = &self.stripes[hash & (self.stripes.len() - 1)];
+
+        // A panic cannot leave a stripe half updated, so poisoning can be ignored.
+        stripe.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a future resolving once the marker for `hash` has been acquired.
+    pub(crate) fn acquire(&self, hash: usize) -> Acquire<'_> {
+        Acquire { keys: self, hash }
+    }
+}
+
+pub(crate) struct Acquire<'a> {
+    keys: &'a PendingKeys,
+    hash: usize,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = PendingGuard<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut waiters = self.keys.lock(self.hash);
+
+        match waiters.get_mut(&self.hash) {
+            Some(wakers) => {
+                if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+
+                Poll::Pending
+            }
+
+            None => {
+                waiters.insert(self.hash, Vec::new());
+
+                Poll::Ready(PendingGuard {
+                    keys: self.keys,
+                    hash: self.hash,
+                })
+            }
+        }
+    }
+}
+
+pub(crate) struct PendingGuard<'a> {
+    keys: &'a PendingKeys,
+    hash: usize,
+}
+
+impl<'a> Drop for PendingGuard<'a> {
+    fn drop(&mut self) {
+        let wakers = self.keys.lock(self.hash).remove(&self.hash);
+
+        // Wake outside of the lock, a waker may poll the woken task inline.
+        for waker in wakers.into_iter().flatten() {
+            waker.wake();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::lock::RwLock;
+use crate::lock::{RwLock, RwLockReadGuard};
 use crate::mapref::multiple::{RefMulti, RefMutMulti};
 use crate::util;
 use crate::{DashMap, HashMap};
@@ -60,8 +60,8 @@ where
 // Implementation note: while the shards will iterate in parallel, we flatten
 // sequentially within each shard (`flat_map_iter`), because the standard
 // `HashMap` only implements `ParallelIterator` by collecting to a `Vec` first.
-// There is real parallel support in the `hashbrown/rayon` feature, but we don't
-// always use that map.
+// With the `hashbrown` feature the shards are hashbrown maps, whose `rayon`
+// support splits their tables, so each shard is iterated in parallel as well.
 
 impl<K, V, S> IntoParallelIterator for DashMap<K, V, S>
 where
@@ -73,14 +73,20 @@ where
     type Item = (K, V);
 
     fn into_par_iter(self) -> Self::Iter {
-        OwningIter {
-            shards: self.shards,
-        }
+        // Retired tables are left with empty shards, so every shard can simply be drained.
+        let shards = self
+            .tables
+            .into_inner()
+            .into_iter()
+            .flat_map(|table| Vec::from(table.into_shards()))
+            .collect();
+
+        OwningIter { shards }
     }
 }
 
 pub struct OwningIter<K, V, S = RandomState> {
-    shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    shards: Vec<RwLock<HashMap<K, V, S>>>,
 }
 
 impl<K, V, S> ParallelIterator for OwningIter<K, V, S>
@@ -95,15 +101,23 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        Vec::from(self.shards)
-            .into_par_iter()
-            .flat_map_iter(|shard| {
-                shard
-                    .into_inner()
-                    .into_iter()
-                    .map(|(k, v)| (k, v.into_inner()))
-            })
-            .drive_unindexed(consumer)
+        #[cfg(feature = "hashbrown")]
+        let iter = self.shards.into_par_iter().flat_map(|shard| {
+            shard
+                .into_inner()
+                .into_par_iter()
+                .map(|(k, v)| (k, v.into_inner()))
+        });
+
+        #[cfg(not(feature = "hashbrown"))]
+        let iter = self.shards.into_par_iter().flat_map_iter(|shard| {
+            shard
+                .into_inner()
+                .into_iter()
+                .map(|(k, v)| (k, v.into_inner()))
+        });
+
+        iter.drive_unindexed(consumer)
     }
 }
 
@@ -119,13 +133,15 @@ where
 
     fn into_par_iter(self) -> Self::Iter {
         Iter {
-            shards: &self.shards,
+            _layout: self.layout.read(),
+            shards: self.all_shards(),
         }
     }
 }
 
 pub struct Iter<'a, K, V, S = RandomState> {
-    shards: &'a [RwLock<HashMap<K, V, S>>],
+    _layout: RwLockReadGuard<'a, ()>,
+    shards: Vec<&'a RwLock<HashMap<K, V, S>>>,
 }
 
 impl<'a, K, V, S> ParallelIterator for Iter<'a, K, V, S>
@@ -140,19 +156,31 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        self.shards
-            .into_par_iter()
-            .flat_map_iter(|shard| {
-                let guard = shard.read();
-                let sref: &'a HashMap<K, V, S> = unsafe { util::change_lifetime_const(&*guard) };
-
-                let guard = Arc::new(guard);
-                sref.iter().map(move |(k, v)| {
-                    let guard = Arc::clone(&guard);
-                    RefMulti::new(guard, k, v.get())
-                })
+        #[cfg(feature = "hashbrown")]
+        let iter = self.shards.into_par_iter().flat_map(|shard| {
+            let guard = shard.read();
+            let sref: &'a HashMap<K, V, S> = unsafe { util::change_lifetime_const(&*guard) };
+
+            let guard = Arc::new(guard);
+            sref.into_par_iter().map(move |(k, v)| {
+                let guard = Arc::clone(&guard);
+                RefMulti::new(guard, k, v.get())
             })
-            .drive_unindexed(consumer)
+        });
+
+        #[cfg(not(feature = "hashbrown"))]
+        let iter = self.shards.into_par_iter().flat_map_iter(|shard| {
+            let guard = shard.read();
+            let sref: &'a HashMap<K, V, S> = unsafe { util::change_lifetime_const(&*guard) };
+
+            let guard = Arc::new(guard);
+            sref.iter().map(move |(k, v)| {
+                let guard = Arc::clone(&guard);
+                RefMulti::new(guard, k, v.get())
+            })
+        });
+
+        iter.drive_unindexed(consumer)
     }
 }
 
@@ -168,12 +196,13 @@ where
 
     fn into_par_iter(self) -> Self::Iter {
         IterMut {
-            shards: &self.shards,
+            _layout: self.layout.read(),
+            shards: self.all_shards(),
         }
     }
 }
 
-impl<'a, K, V, S> DashMap<K, V, S>
+impl<K, V, S> DashMap<K, V, S>
 where
     K: Send + Sync + Eq + Hash,
     V: Send + Sync,
@@ -182,13 +211,15 @@ where
     // Unlike `IntoParallelRefMutIterator::par_iter_mut`, we only _need_ `&self`.
     pub fn par_iter_mut(&self) -> IterMut<'_, K, V, S> {
         IterMut {
-            shards: &self.shards,
+            _layout: self.layout.read(),
+            shards: self.all_shards(),
         }
     }
 }
 
 pub struct IterMut<'a, K, V, S = RandomState> {
-    shards: &'a [RwLock<HashMap<K, V, S>>],
+    _layout: RwLockReadGuard<'a, ()>,
+    shards: Vec<&'a RwLock<HashMap<K, V, S>>>,
 }
 
 impl<'a, K, V, S> ParallelIterator for IterMut<'a, K, V, S>
@@ -203,19 +234,30 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        self.shards
-            .into_par_iter()
-            .flat_map_iter(|shard| {
-                let mut guard = shard.write();
-                let sref: &'a mut HashMap<K, V, S> =
-                    unsafe { util::change_lifetime_mut(&mut *guard) };
-
-                let guard = Arc::new(guard);
-                sref.iter_mut().map(move |(k, v)| {
-                    let guard = Arc::clone(&guard);
-                    RefMutMulti::new(guard, k, v.get_mut())
-                })
+        #[cfg(feature = "hashbrown")]
+        let iter = self.shards.into_par_iter().flat_map(|shard| {
+            let mut guard = shard.write();
+            let sref: &'a mut HashMap<K, V, S> = unsafe { util::change_lifetime_mut(&mut *guard) };
+
+            let guard = Arc::new(guard);
+            sref.into_par_iter().map(move |(k, v)| {
+                let guard = Arc::clone(&guard);
+                RefMutMulti::new(guard, k, v.get_mut())
+            })
+        });
+
+        #[cfg(not(feature = "hashbrown"))]
+        let iter = self.shards.into_par_iter().flat_map_iter(|shard| {
+            let mut guard = shard.write();
+            let sref: &'a mut HashMap<K, V, S> = unsafe { util::change_lifetime_mut(&mut *guard) };
+
+            let guard = Arc::new(guard);
+            sref.iter_mut().map(move |(k, v)| {
+                let guard = Arc::clone(&guard);
+                RefMutMulti::new(guard, k, v.get_mut())
             })
-            .drive_unindexed(consumer)
+        });
+
+        iter.drive_unindexed(consumer)
     }
 }
@@ -1,3 +1,4 @@
+use crate::shard::HashedShard;
 use crate::t::Map;
 use crate::{DashMap, HashMap};
 use core::borrow::Borrow;
@@ -59,13 +60,13 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> ReadOnlyView<K, V, S>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = self.map.hash_usize(&key);
+        let hash = self.map.hash_u64(&key);
 
-        let idx = self.map.determine_shard(hash);
+        let idx = self.map.determine_shard(hash as usize);
 
         let shard = unsafe { self.map._get_read_shard(idx) };
 
-        shard.contains_key(key)
+        shard.get_key_value_hashed(hash, key).is_some()
     }
 
     /// Returns a reference to the value corresponding to the key.
@@ -74,13 +75,13 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> ReadOnlyView<K, V, S>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = self.map.hash_usize(&key);
+        let hash = self.map.hash_u64(&key);
 
-        let idx = self.map.determine_shard(hash);
+        let idx = self.map.determine_shard(hash as usize);
 
         let shard = unsafe { self.map._get_read_shard(idx) };
 
-        shard.get(key).map(|v| v.get())
+        shard.get_key_value_hashed(hash, key).map(|(_, v)| v.get())
     }
 
     /// Returns the key-value pair corresponding to the supplied key.
@@ -89,13 +90,15 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> ReadOnlyView<K, V, S>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = self.map.hash_usize(&key);
+        let hash = self.map.hash_u64(&key);
 
-        let idx = self.map.determine_shard(hash);
+        let idx = self.map.determine_shard(hash as usize);
 
         let shard = unsafe { self.map._get_read_shard(idx) };
 
-        shard.get_key_value(key).map(|(k, v)| (k, v.get()))
+        shard
+            .get_key_value_hashed(hash, key)
+            .map(|(k, v)| (k, v.get()))
     }
 
     fn shard_read_iter(&'a self) -> impl Iterator<Item = &'a HashMap<K, V, S>> + 'a {
@@ -124,7 +127,6 @@ impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone> ReadOnlyView<K, V, S>
 }
 
 #[cfg(test)]
-
 mod tests {
 
     use crate::DashMap;
@@ -172,7 +174,10 @@ mod tests {
 
         let view = map.clone().into_read_only();
 
-        for key in map.iter().map(|entry| *entry.key()) {
+        // Collected first, since `fair-lock` does not allow `get` while iterating the same map.
+        let keys: Vec<i32> = map.iter().map(|entry| *entry.key()).collect();
+
+        for key in keys {
             assert!(view.contains_key(&key));
 
             let map_entry = map.get(&key).unwrap();
@@ -199,7 +204,7 @@ mod tests {
         for (key, value) in view.iter() {
             map.contains_key(key);
 
-            let map_entry = map.get(&key).unwrap();
+            let map_entry = map.get(key).unwrap();
 
             assert_eq!(key, map_entry.key());
 
@@ -213,7 +218,7 @@ mod tests {
         for key in view.keys() {
             map.contains_key(key);
 
-            let map_entry = map.get(&key).unwrap();
+            let map_entry = map.get(key).unwrap();
 
             assert_eq!(key, map_entry.key());
 
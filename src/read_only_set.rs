@@ -0,0 +1,103 @@
+use crate::{DashSet, ReadOnlyView};
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+
+/// A read-only view into a `DashSet`. Allows to obtain raw references to the stored keys.
+pub struct ReadOnlySetView<K, S = RandomState> {
+    view: ReadOnlyView<K, (), S>,
+}
+
+impl<K: Eq + Hash + Clone, S: Clone> Clone for ReadOnlySetView<K, S> {
+    fn clone(&self) -> Self {
+        Self {
+            view: self.view.clone(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + fmt::Debug, S: BuildHasher + Clone> fmt::Debug for ReadOnlySetView<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K, S> ReadOnlySetView<K, S> {
+    pub(crate) fn new(view: ReadOnlyView<K, (), S>) -> Self {
+        Self { view }
+    }
+
+    /// Consumes this `ReadOnlySetView`, returning the underlying `DashSet`.
+    pub fn into_inner(self) -> DashSet<K, S> {
+        DashSet::from_map(self.view.into_inner())
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> ReadOnlySetView<K, S> {
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.view.len()
+    }
+
+    /// Returns `true` if the set contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.view.is_empty()
+    }
+
+    /// Returns the number of keys the set can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.view.capacity()
+    }
+
+    /// Returns `true` if the set contains the key.
+    pub fn contains<Q>(&'a self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.view.contains_key(key)
+    }
+
+    /// Returns a reference to the key in the set equal to the given one.
+    pub fn get<Q>(&'a self, key: &Q) -> Option<&'a K>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.view.get_key_value(key).map(|(k, _)| k)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element type is `&'a K`.
+    pub fn iter(&'a self) -> impl Iterator<Item = &'a K> + 'a {
+        self.view.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DashSet;
+
+    #[test]
+    fn test_read_only_set() {
+        let set: DashSet<u32> = (0..10).collect();
+
+        let view = set.clone().into_read_only();
+
+        assert_eq!(view.len(), 10);
+        assert!(!view.is_empty());
+        assert_eq!(view.get(&3), Some(&3));
+        assert!(view.contains(&9) && !view.contains(&10));
+
+        let mut keys: Vec<u32> = view.iter().copied().collect();
+
+        keys.sort_unstable();
+
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+
+        let set = view.into_inner();
+
+        assert!(set.insert(10));
+        assert_eq!(set.len(), 11);
+    }
+}
@@ -1,15 +1,24 @@
-use crate::iter_set::{Iter, OwningIter};
+use crate::cas::{InsertIfAbsent, Replace};
+use crate::iter::{Cursor, ExtractShards};
+use crate::iter_set::{Drain, ExtractIf, Iter, IterBatched, OwningIter};
 #[cfg(feature = "raw-api")]
 use crate::lock::RwLock;
+use crate::lock::RwLockReadGuard;
+use crate::mapref;
+use crate::setref::entry::{Entry, OccupiedEntry, VacantEntry};
 use crate::setref::one::Ref;
-use crate::DashMap;
-#[cfg(feature = "raw-api")]
-use crate::HashMap;
+use crate::shard::HashedShard;
+use crate::util::SharedValue;
+use crate::{DashMap, Event, HashMap, ReadOnlySetView, SubscriptionId, TryLockError};
 use cfg_if::cfg_if;
 use core::borrow::Borrow;
 use core::fmt;
+use core::future::Future;
 use core::hash::{BuildHasher, Hash};
 use core::iter::FromIterator;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
 use std::collections::hash_map::RandomState;
 
 /// DashSet is a thin wrapper around [`DashMap`] using `()` as the value type. It uses
@@ -18,6 +27,16 @@ use std::collections::hash_map::RandomState;
 /// [`DashMap`]: struct.DashMap.html
 pub struct DashSet<K, S = RandomState> {
     pub(crate) inner: DashMap<K, (), S>,
+    /// Shared by sets created from one another by `clone` or `empty_like`, whose hashers are
+    /// clones of each other. Only those pair up their shards in set algebra.
+    hasher_id: usize,
+}
+
+/// A fresh `DashSet::hasher_id`.
+fn next_hasher_id() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    NEXT.fetch_add(1, Ordering::Relaxed)
 }
 
 impl<K: Eq + Hash + fmt::Debug, S: BuildHasher + Clone> fmt::Debug for DashSet<K, S> {
@@ -27,14 +46,17 @@ impl<K: Eq + Hash + fmt::Debug, S: BuildHasher + Clone> fmt::Debug for DashSet<K
 }
 
 impl<K: Eq + Hash + Clone, S: Clone> Clone for DashSet<K, S> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            hasher_id: self.hasher_id,
         }
     }
 
     fn clone_from(&mut self, source: &Self) {
-        self.inner.clone_from(&source.inner)
+        self.inner.clone_from(&source.inner);
+        self.hasher_id = source.hasher_id;
     }
 }
 
@@ -48,7 +70,7 @@ where
     }
 }
 
-impl<'a, K: 'a + Eq + Hash> DashSet<K, RandomState> {
+impl<K: Eq + Hash> DashSet<K, RandomState> {
     /// Creates a new DashSet with a capacity of 0.
     ///
     /// # Examples
@@ -77,6 +99,34 @@ impl<'a, K: 'a + Eq + Hash> DashSet<K, RandomState> {
     pub fn with_capacity(capacity: usize) -> Self {
         Self::with_capacity_and_hasher(capacity, RandomState::default())
     }
+
+    /// Creates a new DashSet with a capacity of 0 split into `shard_amount` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let numbers = DashSet::with_shard_amount(4);
+    /// numbers.insert(2);
+    /// numbers.insert(8);
+    /// ```
+    pub fn with_shard_amount(shard_amount: usize) -> Self {
+        Self::with_capacity_and_hasher_and_shard_amount(0, RandomState::default(), shard_amount)
+    }
+}
+
+impl<K, S> DashSet<K, S> {
+    pub(crate) fn from_map(inner: DashMap<K, (), S>) -> Self {
+        Self {
+            inner,
+            hasher_id: next_hasher_id(),
+        }
+    }
 }
 
 impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
@@ -110,9 +160,76 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
     /// numbers.insert(8);
     /// ```
     pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
-        Self {
-            inner: DashMap::with_capacity_and_hasher(capacity, hasher),
-        }
+        Self::from_map(DashMap::with_capacity_and_hasher(capacity, hasher))
+    }
+
+    /// Creates a new DashSet with a specified starting capacity and hasher, split into `shard_amount` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let s = RandomState::new();
+    /// let numbers = DashSet::with_capacity_and_hasher_and_shard_amount(2, s, 32);
+    /// numbers.insert(2);
+    /// numbers.insert(8);
+    /// ```
+    pub fn with_capacity_and_hasher_and_shard_amount(
+        capacity: usize,
+        hasher: S,
+        shard_amount: usize,
+    ) -> Self {
+        Self::from_map(DashMap::with_capacity_and_hasher_and_shard_amount(
+            capacity,
+            hasher,
+            shard_amount,
+        ))
+    }
+
+    /// Wraps this `DashSet` into a read-only view. This view allows to obtain raw references to the stored keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let set = DashSet::new();
+    /// set.insert("Joe");
+    /// let view = set.into_read_only();
+    /// assert!(view.contains("Joe"));
+    /// ```
+    pub fn into_read_only(self) -> ReadOnlySetView<K, S> {
+        ReadOnlySetView::new(self.inner.into_read_only())
+    }
+
+    /// Copies the set as it was at a single instant, see [`DashMap::snapshot`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let online = DashSet::new();
+    /// online.insert("alice");
+    ///
+    /// let snapshot = online.snapshot();
+    /// online.insert("bob");
+    /// assert_eq!(snapshot.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn snapshot(&self) -> ReadOnlySetView<K, S>
+    where
+        K: Clone,
+    {
+        ReadOnlySetView::new(self.inner.snapshot())
     }
 
     /// Hash a given item to produce a usize.
@@ -201,10 +318,251 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
     /// let set = DashSet::new();
     /// set.insert("I am the key!");
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn insert(&self, key: K) -> bool {
         self.inner.insert(key, ()).is_none()
     }
 
+    /// Inserts a key into the set if its shard can be locked right away, returning whether it
+    /// was not in the set yet. On failure the key is handed back inside the error.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::{DashSet, TryLockError};
+    ///
+    /// let set = DashSet::new();
+    /// assert!(set.try_insert_now("apple").unwrap());
+    ///
+    /// let guard = set.get("apple").unwrap();
+    /// assert_eq!(set.try_insert_now("apple"), Err(TryLockError::WouldBlock("apple")));
+    /// drop(guard);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_insert_now(&'a self, key: K) -> Result<bool, TryLockError<K>> {
+        self.inner
+            .try_insert_now(key, ())
+            .map(|old| old.is_none())
+            .map_err(|e| e.map(|(k, _)| k))
+    }
+
+    /// Inserts a key into the set, waiting at most `timeout` for its shard.
+    /// On failure the key is handed back inside the error.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_timeout(&'a self, key: K, timeout: Duration) -> Result<bool, TryLockError<K>> {
+        self.inner
+            .insert_timeout(key, (), timeout)
+            .map(|old| old.is_none())
+            .map_err(|e| e.map(|(k, _)| k))
+    }
+
+    /// Inserts all of `keys`, locking each shard once for all the keys that belong to it.
+    /// Returns whether each key was not in the set yet, in the order of `keys`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let tags = DashSet::new();
+    /// tags.insert("a");
+    ///
+    /// assert_eq!(tags.insert_many(vec!["a", "b", "b"]), [false, true, false]);
+    /// assert_eq!(tags.len(), 2);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_many(&self, keys: impl IntoIterator<Item = K>) -> Vec<bool> {
+        self.inner
+            .insert_many(keys.into_iter().map(|k| (k, ())))
+            .into_iter()
+            .map(|old| old.is_none())
+            .collect()
+    }
+
+    /// Inserts a key only if it is not in the set yet, handing it back otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::cas::InsertIfAbsent;
+    /// use dashmap::DashSet;
+    ///
+    /// let seen = DashSet::new();
+    /// assert_eq!(seen.insert_if_absent("a"), InsertIfAbsent::Inserted);
+    /// assert_eq!(seen.insert_if_absent("a"), InsertIfAbsent::Present("a", ()));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_if_absent(&self, key: K) -> InsertIfAbsent<K, ()> {
+        self.inner.insert_if_absent(key, ())
+    }
+
+    /// Inserts a key into the set. After insert the key, f will execute before return.
+    /// Returns whether the key was not in the set yet, together with the result of `f`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_with<T, E>(
+        &self,
+        key: K,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> (bool, Result<T, E>) {
+        let (old, ret) = self.inner.insert_with(key, (), f);
+
+        (old.is_none(), ret)
+    }
+
+    /// Inserts a key into the set. After insert the key, execute key_exists_func if key already
+    /// present before this insert or not_exists_func if key is newly insert.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_and_post_process<T1, E1, T2, E2, T3, E3>(
+        &self,
+        key: K,
+        key_exists_func: impl FnOnce() -> Result<T1, E1>,
+        not_exists_func: impl FnOnce() -> Result<T2, E2>,
+        post_func: Option<impl FnOnce() -> Result<T3, E3>>,
+    ) -> (
+        bool,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+        Option<Result<T3, E3>>,
+    ) {
+        let (old, exists_ret, not_exists_ret, post_ret) = self.inner.insert_and_post_process(
+            key,
+            (),
+            |_| key_exists_func(),
+            not_exists_func,
+            post_func,
+        );
+
+        (old.is_none(), exists_ret, not_exists_ret, post_ret)
+    }
+
+    /// It's the same as insert_and_post_process besides the function are async, see
+    /// [`DashMap::insert_and_post_process_async_fn`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    pub async fn insert_and_post_process_async_fn<T1, E1, T2, E2, T3, E3, Fut1, Fut2, Fut3>(
+        &self,
+        key: K,
+        key_exists_func: impl FnOnce() -> Fut1,
+        not_exists_func: impl FnOnce() -> Fut2,
+        post_func: Option<impl FnOnce() -> Fut3>,
+    ) -> (
+        bool,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+        Option<Result<T3, E3>>,
+    )
+    where
+        Fut1: Future<Output = Result<T1, E1>>,
+        Fut2: Future<Output = Result<T2, E2>>,
+        Fut3: Future<Output = Result<T3, E3>>,
+    {
+        let (old, exists_ret, not_exists_ret, post_ret) = self
+            .inner
+            .insert_and_post_process_async_fn(
+                key,
+                (),
+                |_| key_exists_func(),
+                not_exists_func,
+                post_func,
+            )
+            .await;
+
+        (old.is_none(), exists_ret, not_exists_ret, post_ret)
+    }
+
+    /// Like `insert_and_post_process_async_fn`, but releases the shard lock before any of the
+    /// futures are awaited, see [`DashMap::insert_and_post_process_async_fn_unlocked`].
+    ///
+    /// **Only `*_unlocked` methods respect the pending mark.** `contains`, `insert` and the other
+    /// non-`_unlocked` methods see the key as soon as it is inserted, before `post_func` has run.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    pub async fn insert_and_post_process_async_fn_unlocked<
+        T1,
+        E1,
+        T2,
+        E2,
+        T3,
+        E3,
+        Fut1,
+        Fut2,
+        Fut3,
+    >(
+        &self,
+        key: K,
+        key_exists_func: impl FnOnce() -> Fut1,
+        not_exists_func: impl FnOnce() -> Fut2,
+        post_func: Option<impl FnOnce() -> Fut3>,
+    ) -> (
+        bool,
+        Option<Result<T1, E1>>,
+        Option<Result<T2, E2>>,
+        Option<Result<T3, E3>>,
+    )
+    where
+        Fut1: Future<Output = Result<T1, E1>>,
+        Fut2: Future<Output = Result<T2, E2>>,
+        Fut3: Future<Output = Result<T3, E3>>,
+    {
+        let (old, exists_ret, not_exists_ret, post_ret) = self
+            .inner
+            .insert_and_post_process_async_fn_unlocked(
+                key,
+                (),
+                |_| key_exists_func(),
+                not_exists_func,
+                post_func,
+            )
+            .await;
+
+        (old.is_none(), exists_ret, not_exists_ret, post_ret)
+    }
+
+    /// Replaces the key stored in the set with an equal `key`, only if there is one.
+    /// Returns the replaced key, or hands `key` back if it was not in the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::cas::Replace;
+    /// use dashmap::DashSet;
+    ///
+    /// let names = DashSet::new();
+    /// names.insert(String::from("ann"));
+    ///
+    /// assert!(names.replace(String::from("ann")).is_replaced());
+    /// assert_eq!(names.replace(String::from("bo")), Replace::Absent(String::from("bo")));
+    /// assert!(!names.contains("bo"));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn replace(&self, key: K) -> Replace<K> {
+        let hash = self.inner.hash_u64(&key);
+
+        let shard = self.inner.upgradeable_shard_for(hash);
+
+        if shard.get_key_value_hashed(hash, &key).is_none() {
+            return Replace::Absent(key);
+        }
+
+        let mut shard = shard.upgrade();
+
+        let (old, _) = shard.remove_entry_hashed(hash, &key).unwrap();
+
+        shard.insert_unique_hashed(hash, key, SharedValue::new(()));
+
+        Replace::Replaced(old)
+    }
+
     /// Removes an entry from the map, returning the key if it existed in the map.
     ///
     /// # Examples
@@ -216,6 +574,7 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
     /// soccer_team.insert("Jack");
     /// assert_eq!(soccer_team.remove("Jack").unwrap(), "Jack");
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn remove<Q>(&self, key: &Q) -> Option<K>
     where
         K: Borrow<Q>,
@@ -224,6 +583,74 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
         self.inner.remove(key).map(|(k, _)| k)
     }
 
+    /// Removes a key from the set if its shard can be locked right away.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let soccer_team = DashSet::new();
+    /// soccer_team.insert("Jack");
+    /// assert_eq!(soccer_team.try_remove("Jack").unwrap(), Some("Jack"));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_remove<Q>(&'a self, key: &Q) -> Result<Option<K>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.inner.try_remove(key)?.map(|(k, _)| k))
+    }
+
+    /// Removes a key from the set, waiting at most `timeout` for its shard.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_timeout<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<K>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.inner.remove_timeout(key, timeout)?.map(|(k, _)| k))
+    }
+
+    /// Removes all of `keys`, locking each shard once for all the keys that belong to it.
+    /// Returns the removed keys, in the order of `keys`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let jobs = DashSet::new();
+    /// jobs.insert(1);
+    /// jobs.insert(2);
+    ///
+    /// assert_eq!(jobs.remove_many(&[2, 3]), [Some(2), None]);
+    /// assert_eq!(jobs.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_many<'k, Q>(&self, keys: impl IntoIterator<Item = &'k Q>) -> Vec<Option<K>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'k,
+    {
+        self.inner
+            .remove_many(keys)
+            .into_iter()
+            .map(|removed| removed.map(|(k, _)| k))
+            .collect()
+    }
+
     /// Removes an entry from the set, returning the key
     /// if the entry existed and the provided conditional function returned true.
     ///
@@ -243,6 +670,7 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
     /// soccer_team.remove_if("Jacob", |player| player.starts_with("Ja"));
     /// assert!(!soccer_team.contains("Jacob"));
     /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&K) -> bool) -> Option<K>
     where
         K: Borrow<Q>,
@@ -252,6 +680,50 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
         self.inner.remove_if(key, |k, _| f(k)).map(|(k, _)| k)
     }
 
+    /// Removes a key from the set, returning it if it was there. The same as
+    /// [`remove`](DashSet::remove), named after `HashSet::take`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let names = DashSet::new();
+    /// names.insert(String::from("ann"));
+    /// assert_eq!(names.take("ann"), Some(String::from("ann")));
+    /// assert_eq!(names.take("ann"), None);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn take<Q>(&self, key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key)
+    }
+
+    /// Removes a key from the set. Before the shard is unlocked, execute key_exists_func with
+    /// the key if it was removed, or not_exists_func if it was not in the set.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove_and_post_process<Q, T1, E1, T2, E2>(
+        &self,
+        key: &Q,
+        key_exists_func: impl FnOnce(&Q) -> Result<T1, E1>,
+        not_exists_func: Option<impl FnOnce() -> Result<T2, E2>>,
+    ) -> (Option<K>, Option<Result<T1, E1>>, Option<Result<T2, E2>>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (kv, exists_ret, not_exists_ret) =
+            self.inner
+                .remove_and_post_process(key, |k, _| key_exists_func(k), not_exists_func);
+
+        (kv.map(|(k, _)| k), exists_ret, not_exists_ret)
+    }
+
     /// Creates an iterator over a DashMap yielding immutable references.
     ///
     /// # Examples
@@ -269,123 +741,1099 @@ impl<'a, K: 'a + Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
         Iter::new(iter)
     }
 
-    /// Get a reference to an entry in the set
+    /// Iterator cloning the keys out of the set in batches of about `batch_size`, see
+    /// [`DashMap::iter_batched`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashSet;
     ///
-    /// let youtubers = DashSet::new();
-    /// youtubers.insert("Bosnian Bill");
-    /// assert_eq!(*youtubers.get("Bosnian Bill").unwrap(), "Bosnian Bill");
+    /// let ids: DashSet<u32> = (0..1000).collect();
+    ///
+    /// let mut seen = Vec::new();
+    ///
+    /// for id in ids.iter_batched(64) {
+    ///     // Writers are not blocked while this runs.
+    ///     ids.insert(id + 1000);
+    ///     seen.push(id);
+    /// }
+    ///
+    /// // Keys inserted meanwhile may or may not be yielded.
+    /// assert!((0..1000).all(|id| seen.contains(&id)));
     /// ```
-    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, S>>
+    pub fn iter_batched(&'a self, batch_size: usize) -> IterBatched<'a, K, S>
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        K: Clone,
     {
-        self.inner.get(key).map(Ref::new)
-    }
-
-    /// Remove excess capacity to reduce memory usage.
-    pub fn shrink_to_fit(&self) {
-        self.inner.shrink_to_fit()
+        IterBatched::new(self.inner.iter_batched(batch_size))
     }
 
-    /// Retain elements that whose predicates return true
-    /// and discard elements whose predicates return false.
+    /// Clones up to `limit` keys out of the set, starting at `cursor`, and returns them with the
+    /// cursor to pass to the next call, or `None` once the whole set was scanned. See
+    /// [`DashMap::scan`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
     ///
     /// # Examples
     ///
     /// ```
+    /// use dashmap::iter::Cursor;
     /// use dashmap::DashSet;
     ///
-    /// let people = DashSet::new();
-    /// people.insert("Albin");
-    /// people.insert("Jones");
-    /// people.insert("Charlie");
-    /// people.retain(|name| name.contains('i'));
-    /// assert_eq!(people.len(), 2);
+    /// let users: DashSet<u32> = (0..250).collect();
+    ///
+    /// let mut seen = 0;
+    /// let mut cursor = Some(Cursor::START);
+    ///
+    /// while let Some(current) = cursor {
+    ///     let (page, next) = users.scan(current, 100);
+    ///
+    ///     seen += page.len();
+    ///     cursor = next;
+    /// }
+    ///
+    /// assert_eq!(seen, 250);
     /// ```
-    pub fn retain(&self, mut f: impl FnMut(&K) -> bool) {
-        self.inner.retain(|k, _| f(k))
+    pub fn scan(&'a self, cursor: Cursor, limit: usize) -> (Vec<K>, Option<Cursor>)
+    where
+        K: Clone,
+    {
+        let (batch, next) = self.inner.scan(cursor, limit);
+
+        (batch.into_iter().map(|(k, _)| k).collect(), next)
     }
 
-    /// Fetches the total number of keys stored in the set.
+    /// Removes every key of the set, yielding them, see [`DashMap::drain`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashSet;
     ///
-    /// let people = DashSet::new();
-    /// people.insert("Albin");
-    /// people.insert("Jones");
-    /// people.insert("Charlie");
-    /// assert_eq!(people.len(), 3);
+    /// let queue = DashSet::new();
+    /// queue.insert(1);
+    /// queue.insert(2);
+    ///
+    /// let mut jobs: Vec<_> = queue.drain().collect();
+    /// jobs.sort_unstable();
+    /// assert_eq!(jobs, [1, 2]);
+    /// assert!(queue.is_empty());
     /// ```
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    pub fn drain(&'a self) -> Drain<'a, K, S> {
+        Drain::new(self.inner.drain())
     }
 
-    /// Checks if the set is empty or not.
+    /// Removes the keys `pred` returns `true` for, yielding them, see [`DashMap::extract_if`].
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashSet;
     ///
-    /// let map = DashSet::<()>::new();
-    /// assert!(map.is_empty());
+    /// let numbers: DashSet<u32> = (0..10).collect();
+    ///
+    /// let mut odd: Vec<u32> = numbers.extract_if(|n| *n % 2 == 1).collect();
+    /// odd.sort_unstable();
+    /// assert_eq!(odd, [1, 3, 5, 7, 9]);
+    /// assert_eq!(numbers.len(), 5);
     /// ```
-    pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+    pub fn extract_if<F: FnMut(&K) -> bool>(&'a self, pred: F) -> ExtractIf<'a, K, S, F> {
+        ExtractIf::new(ExtractShards::new(&self.inner), pred)
     }
 
-    /// Removes all keys in the set.
+    /// Get a reference to an entry in the set
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashSet;
     ///
-    /// let people = DashSet::new();
-    /// people.insert("Albin");
-    /// assert!(!people.is_empty());
-    /// people.clear();
-    /// assert!(people.is_empty());
-    /// ```
+    /// let youtubers = DashSet::new();
+    /// youtubers.insert("Bosnian Bill");
+    /// assert_eq!(*youtubers.get("Bosnian Bill").unwrap(), "Bosnian Bill");
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.get(key).map(Ref::new)
+    }
+
+    /// Get a reference to an entry in the set if its shard can be locked right away.
+    ///
+    /// **Locking behaviour:** Never blocks, returns [`TryLockError::WouldBlock`] if the shard is write locked.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn try_get<Q>(&'a self, key: &Q) -> Result<Option<Ref<'a, K, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.inner.try_get(key)?.map(Ref::new))
+    }
+
+    /// Get a reference to an entry in the set, waiting at most `timeout` for its shard.
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_timeout<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<Ref<'a, K, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.inner.get_timeout(key, timeout)?.map(Ref::new))
+    }
+
+    /// Get a reference to an entry in the set. Before return execute `post_func`
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_with<Q, T, E>(
+        &'a self,
+        key: &Q,
+        post_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (r, ret) = self.inner.get_with(key, post_func);
+
+        (r.map(Ref::new), ret)
+    }
+
+    /// Get a reference to an entry in the set. Before return execute `key_exists_func`
+    /// if key exists or execute not_exists_func if key doesn't exists.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_and_post_process<Q, T, E>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce() -> Result<T, E>,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (r, ret) = self
+            .inner
+            .get_and_post_process(key, |_| key_exists_func(), not_exists_func);
+
+        (r.map(Ref::new), ret)
+    }
+
+    /// It's the same as get_and_post_process besides `key_exists_func` is async, see
+    /// [`DashMap::get_and_post_process_ke_async`].
+    pub async fn get_and_post_process_ke_async<Q, T, E, Fut1>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce() -> Fut1,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        Fut1: Future<Output = Result<T, E>>,
+    {
+        let (r, ret) = self
+            .inner
+            .get_and_post_process_ke_async(key, |_| key_exists_func(), not_exists_func)
+            .await;
+
+        (r.map(Ref::new), ret)
+    }
+
+    /// Like `get_and_post_process_ke_async`, but releases the shard lock before the future is
+    /// awaited, see [`DashMap::get_and_post_process_ke_async_unlocked`].
+    ///
+    /// **Only `*_unlocked` methods respect the pending mark.** Other methods may remove the key
+    /// while `key_exists_func` is being awaited.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    pub async fn get_and_post_process_ke_async_unlocked<Q, T, E, Fut1>(
+        &'a self,
+        key: &Q,
+        key_exists_func: impl FnOnce() -> Fut1,
+        not_exists_func: impl FnOnce() -> Result<T, E>,
+    ) -> (Option<Ref<'a, K, S>>, Result<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        Fut1: Future<Output = Result<T, E>>,
+    {
+        let (r, ret) = self
+            .inner
+            .get_and_post_process_ke_async_unlocked(key, |_| key_exists_func(), not_exists_func)
+            .await;
+
+        (r.map(Ref::new), ret)
+    }
+
+    /// Returns a reference to the key in the set, inserting `key` first if there is none.
+    ///
+    /// Like [`DashMap::get_or_insert_with`], the shard is only write locked to insert.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let tags = DashSet::new();
+    /// assert_eq!(*tags.get_or_insert("rust"), "rust");
+    /// assert_eq!(tags.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_or_insert(&'a self, key: K) -> Ref<'a, K, S> {
+        Ref::new(self.inner.get_or_insert_with(key, || ()))
+    }
+
+    /// Returns a reference to the key in the set equal to `value`, inserting the key `f` creates
+    /// from it if there is none. `f` is only called if the key is missing, so an owned key is
+    /// only built when it is inserted.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key `f` returns is not equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let interned: DashSet<String> = DashSet::new();
+    /// assert_eq!(*interned.get_or_insert_with("apple", str::to_owned), "apple");
+    /// assert_eq!(*interned.get_or_insert_with("apple", |_| unreachable!()), "apple");
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_or_insert_with<Q>(&'a self, value: &Q, f: impl FnOnce(&Q) -> K) -> Ref<'a, K, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(r) = self.get(value) {
+            return r;
+        }
+
+        let key = f(value);
+
+        assert!(
+            key.borrow() == value,
+            "new key is not equal to the value it was created from"
+        );
+
+        self.get_or_insert(key)
+    }
+
+    /// Enters an entry for the key, write locking its shard.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::setref::entry::Entry;
+    /// use dashmap::DashSet;
+    ///
+    /// let seen = DashSet::new();
+    ///
+    /// match seen.entry("page") {
+    ///     Entry::Occupied(_) => unreachable!(),
+    ///     Entry::Vacant(entry) => {
+    ///         entry.insert();
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(seen.entry("page").key(), &"page");
+    /// assert_eq!(*seen.entry("page").or_insert(), "page");
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn entry(&'a self, key: K) -> Entry<'a, K, S> {
+        match self.inner.entry(key) {
+            mapref::entry::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry::new(entry)),
+            mapref::entry::Entry::Vacant(entry) => Entry::Vacant(VacantEntry::new(entry)),
+        }
+    }
+
+    /// Remove excess capacity to reduce memory usage.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn shrink_to_fit(&self) {
+        self.inner.shrink_to_fit()
+    }
+
+    /// Returns the number of shards the set is split into.
+    pub fn shard_amount(&self) -> usize {
+        self.inner.shard_amount()
+    }
+
+    /// Splits the set into `shard_amount` shards while it stays in use, see [`DashMap::reshard`].
+    ///
+    /// Set algebra only pairs up the shards of sets with the same shard amount, so resharding one
+    /// of two related sets makes operations between them look up every key on its own.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference or iterator into the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let set = DashSet::with_shard_amount(4);
+    /// set.insert("apple");
+    /// set.reshard(64);
+    /// assert_eq!(set.shard_amount(), 64);
+    /// assert!(set.contains("apple"));
+    /// ```
+    pub fn reshard(&self, shard_amount: usize) {
+        self.inner.reshard(shard_amount)
+    }
+
+    /// Like [`reshard`](DashSet::reshard), but gives up once the move was held back for
+    /// `timeout`, see [`DashMap::try_reshard_for`].
+    ///
+    /// **Locking behaviour:** Returns [`TryLockError::TimedOut`] instead of waiting past `timeout`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_amount` is not a power of two greater than 1.
+    pub fn try_reshard_for(
+        &self,
+        shard_amount: usize,
+        timeout: Duration,
+    ) -> Result<(), TryLockError> {
+        self.inner.try_reshard_for(shard_amount, timeout)
+    }
+
+    /// Retain elements that whose predicates return true
+    /// and discard elements whose predicates return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let people = DashSet::new();
+    /// people.insert("Albin");
+    /// people.insert("Jones");
+    /// people.insert("Charlie");
+    /// people.retain(|name| name.contains('i'));
+    /// assert_eq!(people.len(), 2);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn retain(&self, mut f: impl FnMut(&K) -> bool) {
+        self.inner.retain(|k, _| f(k))
+    }
+
+    /// Fetches the total number of keys stored in the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let people = DashSet::new();
+    /// people.insert("Albin");
+    /// people.insert("Jones");
+    /// people.insert("Charlie");
+    /// assert_eq!(people.len(), 3);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Checks if the set is empty or not.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let map = DashSet::<()>::new();
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Removes all keys in the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let people = DashSet::new();
+    /// people.insert("Albin");
+    /// assert!(!people.is_empty());
+    /// people.clear();
+    /// assert!(people.is_empty());
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     pub fn clear(&self) {
         self.inner.clear()
     }
 
-    /// Returns how many keys the set can store without reallocating.
-    pub fn capacity(&self) -> usize {
-        self.inner.capacity()
+    /// Returns how many keys the set can store without reallocating.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Checks if the set contains a specific key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let people = DashSet::new();
+    /// people.insert("Dakota Cherries");
+    /// assert!(people.contains("Dakota Cherries"));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.contains_key(key)
+    }
+
+    /// Checks for all of `keys`, locking each shard once for all the keys that belong to it.
+    /// Returns whether each key is in the set, in the order of `keys`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let people = DashSet::new();
+    /// people.insert("ann");
+    /// people.insert("bo");
+    ///
+    /// assert_eq!(people.contains_many(vec!["bo", "cy", "ann"]), [true, false, true]);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn contains_many<'k, Q>(&self, keys: impl IntoIterator<Item = &'k Q>) -> Vec<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'k,
+    {
+        self.inner
+            .get_many(keys)
+            .into_iter()
+            .map(|found| found.is_some())
+            .collect()
+    }
+
+    /// Registers `listener` to be called after every insert and removal of a key, see
+    /// [`DashMap::subscribe`]. The values of the events are always `()`, and inserting a key that
+    /// is already present is reported as [`Event::Updated`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::{DashSet, Event};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let set = DashSet::new();
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let sink = log.clone();
+    /// let id = set.subscribe(move |event: &Event<'_, &str, ()>| {
+    ///     if let Event::Inserted { key, .. } = event {
+    ///         sink.lock().unwrap().push(**key);
+    ///     }
+    /// });
+    ///
+    /// set.insert("a");
+    /// assert!(set.unsubscribe(id));
+    /// set.insert("b");
+    ///
+    /// assert_eq!(*log.lock().unwrap(), ["a"]);
+    /// ```
+    pub fn subscribe(
+        &self,
+        listener: impl Fn(&Event<'_, K, ()>) + Send + Sync + 'static,
+    ) -> SubscriptionId
+    where
+        K: Clone,
+    {
+        self.inner.subscribe(listener)
+    }
+
+    /// Removes a listener registered with [`subscribe`](DashSet::subscribe).
+    /// Returns `false` if it was already removed.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.inner.unsubscribe(id)
+    }
+}
+
+/// The other operand of the set operations of [`DashSet`]: another `DashSet` with the same
+/// hasher type, or anything iterable over keys or references to keys.
+///
+/// A set and its clones, as well as the results of set operations on them, keep equal keys in
+/// shards of the same index as long as they have the same shard amount. Operations between them
+/// visit the shards of both pairwise instead of looking up every key on its own. Hashers can not
+/// be compared, so other sets are never paired up, even if built with clones of one hasher.
+pub trait SetOperand<K, S> {
+    /// The operand as a set, if it is one.
+    #[doc(hidden)]
+    fn as_set(&self) -> Option<&DashSet<K, S>>;
+
+    /// Calls `f` on every key until it returns `false`, returning whether it never did.
+    #[doc(hidden)]
+    fn all_keys(self, f: impl FnMut(&K) -> bool) -> bool;
+}
+
+impl<K: Eq + Hash, S: BuildHasher + Clone> SetOperand<K, S> for &DashSet<K, S> {
+    fn as_set(&self) -> Option<&DashSet<K, S>> {
+        Some(self)
+    }
+
+    fn all_keys(self, mut f: impl FnMut(&K) -> bool) -> bool {
+        self.iter().all(|k| f(k.key()))
+    }
+}
+
+impl<K, S, I> SetOperand<K, S> for I
+where
+    I: IntoIterator,
+    I::Item: Borrow<K>,
+{
+    fn as_set(&self) -> Option<&DashSet<K, S>> {
+        None
+    }
+
+    fn all_keys(self, mut f: impl FnMut(&K) -> bool) -> bool {
+        self.into_iter().all(|k| f(k.borrow()))
+    }
+}
+
+/// How many keys `union_with` clones out of a set it can not pair up with per shard lock.
+const UNION_BATCH_SIZE: usize = 1024;
+
+/// Set algebra. Operations between two sets take the shards of both one pair at a time, so
+/// each result reflects every shard pair at the moment it was visited, but not the sets as a
+/// whole at any single instant.
+impl<K: Eq + Hash, S: BuildHasher + Clone> DashSet<K, S> {
+    /// Returns a set of the keys in `self`, `other` or both.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// let b = a.union([2, 3, 4].iter());
+    /// assert_eq!(b.len(), 5);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn union(&self, other: impl SetOperand<K, S>) -> Self
+    where
+        K: Clone,
+    {
+        let union = self.clone();
+
+        union.union_with(other);
+
+        union
+    }
+
+    /// Returns a set of the keys in both `self` and `other`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..5).collect();
+    /// let b = a.intersection(vec![3, 4, 5]);
+    /// assert_eq!(b.len(), 2);
+    /// assert!(b.contains(&3) && b.contains(&4));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn intersection(&self, other: impl SetOperand<K, S>) -> Self
+    where
+        K: Clone,
+    {
+        let intersection = self.empty_like();
+
+        if let Some(set) = other.as_set() {
+            if ptr::eq(set, self) {
+                return self.clone();
+            }
+
+            if let Some(_layouts) = self.lock_paired(set) {
+                self.read_shard_pairs(set, |a, b| {
+                    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+                    for k in small.keys().filter(|k| large.contains_key(*k)) {
+                        intersection.insert(k.clone());
+                    }
+
+                    true
+                });
+
+                return intersection;
+            }
+        }
+
+        other.all_keys(|k| {
+            if self.contains(k) {
+                intersection.insert(k.clone());
+            }
+
+            true
+        });
+
+        intersection
+    }
+
+    /// Returns a set of the keys in `self` but not in `other`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..5).collect();
+    /// let b = a.difference(&[1, 2]);
+    /// assert_eq!(b.len(), 3);
+    /// assert!(!b.contains(&1));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn difference(&self, other: impl SetOperand<K, S>) -> Self
+    where
+        K: Clone,
+    {
+        if let Some(set) = other.as_set() {
+            if ptr::eq(set, self) {
+                return self.empty_like();
+            }
+
+            if let Some(_layouts) = self.lock_paired(set) {
+                let difference = self.empty_like();
+
+                self.read_shard_pairs(set, |a, b| {
+                    for k in a.keys().filter(|k| !b.contains_key(*k)) {
+                        difference.insert(k.clone());
+                    }
+
+                    true
+                });
+
+                return difference;
+            }
+        }
+
+        let difference = self.clone();
+
+        other.all_keys(|k| {
+            difference.remove(k);
+
+            true
+        });
+
+        difference
+    }
+
+    /// Returns a set of the keys in either `self` or `other`, but not in both.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// let b = a.symmetric_difference(vec![2, 3]);
+    /// assert_eq!(b.len(), 3);
+    /// assert!(b.contains(&3) && !b.contains(&2));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn symmetric_difference(&self, other: impl SetOperand<K, S>) -> Self
+    where
+        K: Clone,
+    {
+        let collected;
+
+        let set = match other.as_set() {
+            Some(set) => set,
+            None => {
+                collected = self.collect_like(other);
+
+                &collected
+            }
+        };
+
+        let difference = self.empty_like();
+
+        if ptr::eq(set, self) {
+            return difference;
+        }
+
+        if let Some(_layouts) = self.lock_paired(set) {
+            self.read_shard_pairs(set, |a, b| {
+                for k in a.keys().filter(|k| !b.contains_key(*k)) {
+                    difference.insert(k.clone());
+                }
+
+                for k in b.keys().filter(|k| !a.contains_key(*k)) {
+                    difference.insert(k.clone());
+                }
+
+                true
+            });
+
+            return difference;
+        }
+
+        for k in self.iter().filter(|k| !set.contains(k.key())) {
+            difference.insert(k.key().clone());
+        }
+
+        for k in set.iter().filter(|k| !self.contains(k.key())) {
+            difference.insert(k.key().clone());
+        }
+
+        difference
+    }
+
+    /// Checks if every key of `self` is in `other`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// assert!(a.is_subset(0..10));
+    /// assert!(!a.is_subset(&[0, 1]));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_subset(&self, other: impl SetOperand<K, S>) -> bool
+    where
+        K: Clone,
+    {
+        match other.as_set() {
+            Some(set) => set.contains_all(self),
+            None => self.collect_like(other).contains_all(self),
+        }
+    }
+
+    /// Checks if every key of `other` is in `self`.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// assert!(a.is_superset(&[0, 2]));
+    /// assert!(!a.is_superset(1..5));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_superset(&self, other: impl SetOperand<K, S>) -> bool {
+        match other.as_set() {
+            Some(set) => self.contains_all(set),
+            None => other.all_keys(|k| self.contains(k)),
+        }
+    }
+
+    /// Checks if `self` and `other` have no keys in common.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// assert!(a.is_disjoint(3..6));
+    /// assert!(!a.is_disjoint(&[2, 3]));
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_disjoint(&self, other: impl SetOperand<K, S>) -> bool {
+        if let Some(set) = other.as_set() {
+            if ptr::eq(set, self) {
+                return self.is_empty();
+            }
+
+            if let Some(_layouts) = self.lock_paired(set) {
+                return self.read_shard_pairs(set, |a, b| {
+                    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+                    !small.keys().any(|k| large.contains_key(k))
+                });
+            }
+        }
+
+        other.all_keys(|k| !self.contains(k))
+    }
+
+    /// Inserts every key of `other` that is not in `self` yet.
+    ///
+    /// Only one shard is locked at a time: keys taken from another set are cloned out of its
+    /// shard before the shard of `self` they go to is write locked.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::DashSet;
+    ///
+    /// let a: DashSet<i32> = (0..3).collect();
+    /// a.union_with(vec![2, 3]);
+    /// assert_eq!(a.len(), 4);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn union_with(&self, other: impl SetOperand<K, S>)
+    where
+        K: Clone,
+    {
+        let set = match other.as_set() {
+            Some(set) => set,
+            None => {
+                other.all_keys(|k| {
+                    if !self.contains(k) {
+                        self.insert(k.clone());
+                    }
+
+                    true
+                });
+
+                return;
+            }
+        };
+
+        if ptr::eq(set, self) {
+            return;
+        }
+
+        if let Some(_layouts) = self.lock_paired(set) {
+            for i in 0..self.inner.shard_amount() {
+                let keys: Vec<K> = set.inner.read_shard(i).keys().cloned().collect();
+
+                let mut shard = self.inner.write_shard(i);
+
+                for k in keys {
+                    if !shard.contains_key(&k) {
+                        let hash = self.inner.hash_u64(&k);
+
+                        shard.insert_hashed(hash, k, SharedValue::new(()));
+                    }
+                }
+            }
+
+            return;
+        }
+
+        for (k, ()) in set.inner.iter_batched(UNION_BATCH_SIZE) {
+            self.insert(k);
+        }
     }
 
-    /// Checks if the set contains a specific key.
+    /// Removes every key of `self` that is not in `other`.
+    ///
+    /// The shards of `self` are write locked one at a time. Keys are checked against a set
+    /// paired up with `self` under the read lock of its shard of the same index, and against
+    /// anything else after copying it.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into either set.
     ///
     /// # Examples
     ///
     /// ```
     /// use dashmap::DashSet;
     ///
-    /// let people = DashSet::new();
-    /// people.insert("Dakota Cherries");
-    /// assert!(people.contains("Dakota Cherries"));
+    /// let a: DashSet<i32> = (0..5).collect();
+    /// a.retain_intersection(vec![1, 3, 7]);
+    /// assert_eq!(a.len(), 2);
     /// ```
-    pub fn contains<Q>(&self, key: &Q) -> bool
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn retain_intersection(&self, other: impl SetOperand<K, S>)
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        K: Clone,
     {
-        self.inner.contains_key(key)
+        if let Some(set) = other.as_set() {
+            if ptr::eq(set, self) {
+                return;
+            }
+
+            if let Some(_layouts) = self.lock_paired(set) {
+                for i in 0..self.inner.shard_amount() {
+                    let (mut a, b) = if self.shard_first(set, i) {
+                        let a = self.inner.write_shard(i);
+
+                        (a, set.inner.read_shard(i))
+                    } else {
+                        let b = set.inner.read_shard(i);
+
+                        (self.inner.write_shard(i), b)
+                    };
+
+                    a.retain(|k, _| b.contains_key(k));
+                }
+
+                return;
+            }
+        }
+
+        // Another set is copied rather than read locked, since it may be running this very
+        // operation against `self` on another thread.
+        let other = self.collect_like(other);
+
+        self.retain(|k| other.contains(k));
+    }
+
+    /// An empty set with the hasher and shard amount of `self`, so that it pairs up with it.
+    fn empty_like(&self) -> Self {
+        Self {
+            hasher_id: self.hasher_id,
+            ..Self::with_capacity_and_hasher_and_shard_amount(
+                0,
+                self.inner.hasher.clone(),
+                self.inner.shard_amount(),
+            )
+        }
+    }
+
+    /// Copies the keys of `other` into a set paired up with `self`.
+    fn collect_like(&self, other: impl SetOperand<K, S>) -> Self
+    where
+        K: Clone,
+    {
+        let set = self.empty_like();
+
+        other.all_keys(|k| {
+            set.insert(k.clone());
+
+            true
+        });
+
+        set
+    }
+
+    /// Checks if every key of `other` is in `self`.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn contains_all(&self, other: &Self) -> bool {
+        if ptr::eq(self, other) {
+            return true;
+        }
+
+        if let Some(_layouts) = self.lock_paired(other) {
+            return self.read_shard_pairs(other, |a, b| b.keys().all(|k| a.contains_key(k)));
+        }
+
+        other.iter().all(|k| self.contains(k.key()))
+    }
+
+    /// Locks the layouts of both sets if `other` splits keys into shards like `self`, meaning
+    /// that shard `i` of one only shares keys with shard `i` of the other.
+    ///
+    /// Hashers can not be compared, so only sets sharing a `hasher_id` are known to hash alike.
+    fn lock_paired<'s>(
+        &'s self,
+        other: &'s Self,
+    ) -> Option<(RwLockReadGuard<'s, ()>, RwLockReadGuard<'s, ()>)> {
+        if self.hasher_id != other.hasher_id {
+            return None;
+        }
+
+        let (a, b) = (&self.inner, &other.inner);
+
+        let layouts = (a.layout.read(), b.layout.read());
+
+        // A resize in progress spreads the keys over two tables, which do not pair up.
+        let single_table = |m: &DashMap<K, (), S>| m.shard_count() == m.shard_amount();
+
+        if a.shard_amount() == b.shard_amount() && single_table(a) && single_table(b) {
+            Some(layouts)
+        } else {
+            None
+        }
+    }
+
+    /// Whether shard `i` of `self` is locked before shard `i` of `other`. Pairs of shards are
+    /// locked in address order, so operations locking both can not deadlock against each other.
+    fn shard_first(&self, other: &Self, i: usize) -> bool {
+        let a: *const _ = self.inner.shard(i);
+        let b: *const _ = other.inner.shard(i);
+
+        (a as usize) < (b as usize)
+    }
+
+    /// Calls `f` on every pair of shards of the same index, read locking both, until it returns
+    /// `false`. Returns whether it never did. The caller has to hold the layouts locked by
+    /// [`lock_paired`](DashSet::lock_paired).
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn read_shard_pairs(
+        &self,
+        other: &Self,
+        mut f: impl FnMut(&HashMap<K, (), S>, &HashMap<K, (), S>) -> bool,
+    ) -> bool {
+        (0..self.inner.shard_amount()).all(|i| {
+            let (a, b) = if self.shard_first(other, i) {
+                let a = self.inner.read_shard(i);
+
+                (a, other.inner.read_shard(i))
+            } else {
+                let b = other.inner.read_shard(i);
+
+                (self.inner.read_shard(i), b)
+            };
+
+            f(&a, &b)
+        })
     }
 }
 
-impl<'a, K: Eq + Hash, S: BuildHasher + Clone> IntoIterator for DashSet<K, S> {
+impl<K: Eq + Hash, S: BuildHasher + Clone> IntoIterator for DashSet<K, S> {
     type Item = K;
 
     type IntoIter = OwningIter<K, S>;
@@ -416,6 +1864,16 @@ impl<K: Eq + Hash> FromIterator<K> for DashSet<K, RandomState> {
 #[cfg(test)]
 mod tests {
     use crate::DashSet;
+    use core::hash::BuildHasher;
+    use std::collections::hash_map::RandomState;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn fill<S: BuildHasher + Clone>(set: &DashSet<u32, S>, keys: impl IntoIterator<Item = u32>) {
+        for k in keys {
+            set.insert(k);
+        }
+    }
 
     #[test]
     fn test_basic() {
@@ -455,4 +1913,450 @@ mod tests {
             assert_eq!(None, set.remove(&i));
         }
     }
+
+    #[test]
+    fn test_many_and_try() {
+        let set = DashSet::with_shard_amount(4);
+
+        assert_eq!(set.insert_many(vec![1, 2, 2, 3]), [true, true, false, true]);
+        assert_eq!(set.contains_many(&[3, 4, 1]), [true, false, true]);
+        assert_eq!(set.remove_many(&[1, 4]), [Some(1), None]);
+
+        assert_eq!(set.try_insert_now(5), Ok(true));
+        assert_eq!(set.try_insert_now(5), Ok(false));
+        assert_eq!(set.try_remove(&5), Ok(Some(5)));
+        assert!(set.try_get(&2).unwrap().is_some());
+
+        let guard = set.get(&2).unwrap();
+
+        assert!(set.try_insert_now(2).unwrap_err().into_inner() == 2);
+        assert!(set
+            .remove_timeout(&2, std::time::Duration::from_millis(10))
+            .unwrap_err()
+            .is_timeout());
+
+        drop(guard);
+
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_drain_extract_if_and_scan() {
+        let set: DashSet<u32> = DashSet::with_shard_amount(4);
+
+        fill(&set, 0..100);
+
+        let mut odd: Vec<u32> = set.extract_if(|k| k % 2 == 1).collect();
+        odd.sort_unstable();
+
+        assert_eq!(odd, (0..100).filter(|k| k % 2 == 1).collect::<Vec<_>>());
+
+        set.reshard(16);
+
+        assert_eq!(set.shard_amount(), 16);
+
+        let mut scanned = HashSet::new();
+        let mut cursor = Some(crate::iter::Cursor::START);
+
+        while let Some(current) = cursor {
+            let (page, next) = set.scan(current, 7);
+
+            scanned.extend(page);
+            cursor = next;
+        }
+
+        let batched: HashSet<u32> = set.iter_batched(7).collect();
+
+        assert_eq!(scanned, (0..100).filter(|k| k % 2 == 0).collect());
+        assert_eq!(batched, scanned);
+
+        let drained: HashSet<u32> = set.drain().collect();
+
+        assert_eq!(drained, scanned);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let set = DashSet::new();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = events.clone();
+
+        let id = set.subscribe(move |event| {
+            let kind = match event {
+                crate::Event::Inserted { .. } => "inserted",
+                crate::Event::Updated { .. } => "updated",
+                crate::Event::Removed { .. } => "removed",
+            };
+
+            log.lock().unwrap().push((kind, *event.key()));
+        });
+
+        set.insert(1);
+        set.insert(1);
+        set.remove(&1);
+
+        assert!(set.unsubscribe(id));
+
+        set.insert(2);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [("inserted", 1), ("updated", 1), ("removed", 1)]
+        );
+    }
+
+    #[test]
+    fn test_replace_swaps_stored_key() {
+        use core::hash::{Hash, Hasher};
+
+        // Equal by name only, so replacing changes which version is stored.
+        #[derive(Debug)]
+        struct Tagged(&'static str, u32);
+
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl Eq for Tagged {}
+
+        impl Hash for Tagged {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.hash(state);
+            }
+        }
+
+        let set = DashSet::new();
+
+        assert!(set.insert_if_absent(Tagged("a", 1)).is_inserted());
+        assert!(!set.insert_if_absent(Tagged("a", 2)).is_inserted());
+        assert_eq!(set.get(&Tagged("a", 0)).unwrap().1, 1);
+
+        match set.replace(Tagged("a", 3)) {
+            crate::cas::Replace::Replaced(old) => assert_eq!(old.1, 1),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(set.get(&Tagged("a", 0)).unwrap().1, 3);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_entry() {
+        use crate::setref::entry::Entry;
+
+        let set = DashSet::new();
+
+        assert_eq!(*set.entry(1).or_insert(), 1);
+
+        match set.entry(1) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => panic!("key not found"),
+        }
+
+        assert!(set.is_empty());
+
+        match set.entry(2) {
+            Entry::Vacant(entry) => assert_eq!(*entry.insert(), 2),
+            Entry::Occupied(_) => panic!("key found"),
+        }
+
+        assert!(set.contains(&2));
+    }
+
+    #[test]
+    fn test_post_process() {
+        let set = DashSet::new();
+
+        let (inserted, ret) = set.insert_with(1, || Ok::<_, ()>(1));
+        assert!(inserted);
+        assert_eq!(ret, Ok(1));
+
+        let (inserted, exists, not_exists, post) = set.insert_and_post_process(
+            1,
+            || Ok::<_, ()>("exists"),
+            || Ok::<_, ()>("new"),
+            Some(|| Ok::<_, ()>("post")),
+        );
+        assert!(!inserted);
+        assert_eq!(
+            (exists, not_exists, post),
+            (Some(Ok("exists")), None, Some(Ok("post")))
+        );
+
+        let (r, ret) = set.get_and_post_process(&1, || Ok::<_, ()>(true), || Ok(false));
+        assert_eq!(r.as_deref(), Some(&1));
+        assert_eq!(ret, Ok(true));
+        drop(r);
+
+        let (r, ret) = set.get_with(&2, || Ok::<_, ()>(()));
+        assert!(r.is_none() && ret.is_ok());
+
+        let (removed, exists, not_exists) =
+            set.remove_and_post_process(&1, |k| Ok::<_, ()>(*k), Some(|| Ok::<_, ()>(())));
+        assert_eq!((removed, exists, not_exists), (Some(1), Some(Ok(1)), None));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let set: DashSet<String> = DashSet::new();
+
+        assert_eq!(*set.get_or_insert("a".to_owned()), "a");
+        assert_eq!(*set.get_or_insert_with("a", |_| unreachable!()), "a");
+        assert_eq!(*set.get_or_insert_with("b", str::to_owned), "b");
+        assert_eq!(set.len(), 2);
+
+        assert_eq!(set.take("b"), Some("b".to_owned()));
+        assert_eq!(set.take("b"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not equal")]
+    fn test_get_or_insert_with_other_key() {
+        let set: DashSet<String> = DashSet::new();
+
+        set.get_or_insert_with("a", |_| "b".to_owned());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_extend_ref() {
+        use rayon::iter::{IntoParallelIterator, ParallelExtend, ParallelIterator};
+
+        let set = DashSet::new();
+
+        (&set).par_extend((0..100u32).into_par_iter().map(|i| i % 2));
+        (&set).par_extend((0..100u32).into_par_iter().map(|_| 2));
+
+        assert_eq!(sorted(&set), [0, 1, 2]);
+    }
+
+    fn sorted<S: BuildHasher + Clone>(set: &DashSet<u32, S>) -> Vec<u32> {
+        let mut keys: Vec<u32> = set.iter().map(|k| *k).collect();
+
+        keys.sort_unstable();
+
+        keys
+    }
+
+    fn check_algebra(a: &DashSet<u32>, b: &DashSet<u32>) {
+        let (x, y): (HashSet<u32>, HashSet<u32>) = (
+            a.iter().map(|k| *k).collect(),
+            b.iter().map(|k| *k).collect(),
+        );
+
+        let expect = |keys: Vec<&u32>| {
+            let mut keys: Vec<u32> = keys.into_iter().copied().collect();
+
+            keys.sort_unstable();
+
+            keys
+        };
+
+        let ys: Vec<u32> = y.iter().copied().collect();
+
+        assert_eq!(sorted(&a.union(b)), expect(x.union(&y).collect()));
+        assert_eq!(sorted(&a.union(&ys)), expect(x.union(&y).collect()));
+        assert_eq!(
+            sorted(&a.intersection(b)),
+            expect(x.intersection(&y).collect())
+        );
+        assert_eq!(
+            sorted(&a.intersection(&ys)),
+            expect(x.intersection(&y).collect())
+        );
+        assert_eq!(sorted(&a.difference(b)), expect(x.difference(&y).collect()));
+        assert_eq!(
+            sorted(&a.difference(&ys)),
+            expect(x.difference(&y).collect())
+        );
+
+        let symmetric = expect(x.symmetric_difference(&y).collect());
+
+        assert_eq!(sorted(&a.symmetric_difference(b)), symmetric);
+        assert_eq!(sorted(&a.symmetric_difference(&ys)), symmetric);
+
+        assert_eq!(a.is_subset(b), x.is_subset(&y));
+        assert_eq!(a.is_subset(&ys), x.is_subset(&y));
+        assert_eq!(a.is_superset(b), x.is_superset(&y));
+        assert_eq!(a.is_superset(&ys), x.is_superset(&y));
+        assert_eq!(a.is_disjoint(b), x.is_disjoint(&y));
+        assert_eq!(a.is_disjoint(&ys), x.is_disjoint(&y));
+
+        let c = a.clone();
+        c.union_with(b);
+        assert_eq!(sorted(&c), expect(x.union(&y).collect()));
+
+        let c = a.clone();
+        c.union_with(ys.iter());
+        assert_eq!(sorted(&c), expect(x.union(&y).collect()));
+
+        let c = a.clone();
+        c.retain_intersection(b);
+        assert_eq!(sorted(&c), expect(x.intersection(&y).collect()));
+
+        let c = a.clone();
+        c.retain_intersection(ys);
+        assert_eq!(sorted(&c), expect(x.intersection(&y).collect()));
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let hasher = RandomState::new();
+        let origin = DashSet::with_capacity_and_hasher_and_shard_amount(0, hasher.clone(), 8);
+
+        let paired = |keys: core::ops::Range<u32>| {
+            let set = origin.empty_like();
+
+            fill(&set, keys);
+
+            set
+        };
+
+        let (a, b) = (paired(0..300), paired(200..500));
+
+        assert!(a.lock_paired(&b).is_some());
+
+        check_algebra(&a, &b);
+        check_algebra(&b, &a);
+        check_algebra(&a, &paired(0..100));
+        check_algebra(&a, &paired(400..500));
+
+        let c: DashSet<u32> = (200..500).collect();
+
+        assert!(a.lock_paired(&c).is_none());
+
+        check_algebra(&a, &c);
+        check_algebra(&c, &a);
+
+        // Pairing also needs the same shard amount.
+        let d = paired(200..500);
+
+        d.inner.reshard(4);
+
+        assert!(a.lock_paired(&d).is_none());
+
+        check_algebra(&a, &d);
+        check_algebra(&a, &a);
+
+        // Nor are sets paired whose hashers were not cloned from one another, even if they hash alike.
+        let e = DashSet::with_capacity_and_hasher_and_shard_amount(0, hasher, 8);
+
+        fill(&e, 200..500);
+
+        assert!(a.lock_paired(&e).is_none());
+
+        check_algebra(&a, &e);
+    }
+
+    #[test]
+    fn test_set_algebra_stateful_hasher() {
+        use core::hash::Hasher;
+        use std::collections::hash_map::DefaultHasher;
+
+        /// Hashes integers alike but salts strings with `salt`.
+        #[derive(Clone)]
+        struct Salted {
+            salt: u8,
+        }
+
+        struct SaltedHasher {
+            salt: u8,
+            inner: DefaultHasher,
+        }
+
+        impl Hasher for SaltedHasher {
+            fn finish(&self) -> u64 {
+                self.inner.finish()
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                self.inner.write(&[self.salt]);
+                self.inner.write(bytes);
+            }
+
+            fn write_u64(&mut self, i: u64) {
+                self.inner.write_u64(i);
+            }
+        }
+
+        impl BuildHasher for Salted {
+            type Hasher = SaltedHasher;
+
+            fn build_hasher(&self) -> SaltedHasher {
+                SaltedHasher {
+                    salt: self.salt,
+                    inner: DefaultHasher::new(),
+                }
+            }
+        }
+
+        let set = |salt, keys: core::ops::Range<u32>| {
+            let set = DashSet::with_capacity_and_hasher_and_shard_amount(0, Salted { salt }, 8);
+
+            for i in keys {
+                set.insert(i.to_string());
+            }
+
+            set
+        };
+
+        let (a, b, c) = (set(1, 0..300), set(2, 200..500), set(2, 0..100));
+
+        assert!(a.lock_paired(&b).is_none());
+        assert_eq!(a.intersection(&b).len(), 100);
+        assert_eq!(a.difference(&b).len(), 200);
+        assert!(a.is_superset(&c));
+        assert!(!a.is_disjoint(&b));
+    }
+
+    #[test]
+    fn test_set_algebra_in_place_concurrently() {
+        let hasher = RandomState::new();
+
+        for &shard_amount in &[8, 16] {
+            let a = Arc::new(DashSet::with_capacity_and_hasher_and_shard_amount(
+                0,
+                hasher.clone(),
+                8,
+            ));
+            let b = Arc::new(a.empty_like());
+
+            b.inner.reshard(shard_amount);
+
+            fill(&a, 0..1000);
+            fill(&b, 500..1500);
+
+            // Each thread locks shards of both sets, in opposite roles.
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    let (a, b) = if t % 2 == 0 {
+                        (a.clone(), b.clone())
+                    } else {
+                        (b.clone(), a.clone())
+                    };
+
+                    std::thread::spawn(move || {
+                        for _ in 0..20 {
+                            a.union_with(&*b);
+                            a.retain_intersection(&*b);
+                        }
+                    })
+                })
+                .collect();
+
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            // Keys in both sets from the start are never removed, and no others show up.
+            for set in &[a, b] {
+                assert!((500..1000).all(|k| set.contains(&k)));
+                assert!(set.iter().all(|k| *k < 1500));
+            }
+        }
+    }
 }
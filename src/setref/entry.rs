@@ -0,0 +1,95 @@
+use super::one::Ref;
+use crate::mapref;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+
+/// An entry of a `DashSet`, returned by `DashSet::entry`. Holds the write lock of its shard.
+pub enum Entry<'a, K, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, S>),
+    Vacant(VacantEntry<'a, K, S>),
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher> Entry<'a, K, S> {
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    /// Into the key of the entry.
+    pub fn into_key(self) -> K {
+        match self {
+            Entry::Occupied(entry) => entry.into_key(),
+            Entry::Vacant(entry) => entry.into_key(),
+        }
+    }
+
+    /// Return a reference to the key in the set, inserting the key of the entry if there is none.
+    pub fn or_insert(self) -> Ref<'a, K, S> {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => entry.insert(),
+        }
+    }
+}
+
+pub struct VacantEntry<'a, K, S = RandomState> {
+    inner: mapref::entry::VacantEntry<'a, K, (), S>,
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher> VacantEntry<'a, K, S> {
+    pub(crate) fn new(inner: mapref::entry::VacantEntry<'a, K, (), S>) -> Self {
+        Self { inner }
+    }
+
+    /// Inserts the key of the entry, downgrading the lock to a read lock.
+    pub fn insert(self) -> Ref<'a, K, S> {
+        Ref::new(self.inner.insert(()).downgrade())
+    }
+
+    pub fn into_key(self) -> K {
+        self.inner.into_key()
+    }
+
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+}
+
+pub struct OccupiedEntry<'a, K, S = RandomState> {
+    inner: mapref::entry::OccupiedEntry<'a, K, (), S>,
+}
+
+impl<'a, K: Eq + Hash, S: BuildHasher> OccupiedEntry<'a, K, S> {
+    pub(crate) fn new(inner: mapref::entry::OccupiedEntry<'a, K, (), S>) -> Self {
+        Self { inner }
+    }
+
+    /// Downgrades the lock to a read lock, keeping a reference to the key in the set.
+    pub fn into_ref(self) -> Ref<'a, K, S> {
+        Ref::new(self.inner.into_ref().downgrade())
+    }
+
+    /// The key the entry was looked up with.
+    pub fn into_key(self) -> K {
+        self.inner.into_key()
+    }
+
+    /// The key in the set.
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    /// Removes the key from the set, returning it.
+    pub fn remove(self) -> K {
+        self.inner.remove_entry().0
+    }
+
+    /// Replaces the key in the set with the one the entry was looked up with, returning the
+    /// replaced key.
+    pub fn replace(self) -> K {
+        self.inner.replace_entry(()).0
+    }
+}
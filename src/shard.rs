@@ -0,0 +1,167 @@
+use crate::util::SharedValue;
+use crate::HashMap;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+#[cfg(not(feature = "hashbrown"))]
+use core::{mem, ptr};
+
+/// Shard operations taking the hash that already selected the shard.
+///
+/// With the `hashbrown` feature the hash is handed on to the table, so a key is only hashed
+/// once per operation. The std map can not take it and hashes the key again.
+pub(crate) trait HashedShard<K, V> {
+    fn get_key_value_hashed<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    fn get_mut_hashed<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut SharedValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    fn insert_hashed(&mut self, hash: u64, key: K, value: SharedValue<V>)
+        -> Option<SharedValue<V>>;
+
+    /// Inserts `key`, which must not be present yet, returning references to the stored element.
+    fn insert_unique_hashed(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: SharedValue<V>,
+    ) -> (&K, &SharedValue<V>);
+
+    fn remove_entry_hashed<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+}
+
+#[cfg(feature = "hashbrown")]
+impl<K: Eq + Hash, V, S: BuildHasher> HashedShard<K, V> for HashMap<K, V, S> {
+    fn get_key_value_hashed<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.raw_entry().from_key_hashed_nocheck(hash, key)
+    }
+
+    fn get_mut_hashed<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut SharedValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        use hashbrown::hash_map::RawEntryMut;
+
+        match self.raw_entry_mut().from_key_hashed_nocheck(hash, key) {
+            RawEntryMut::Occupied(entry) => Some(entry.into_mut()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
+    fn insert_hashed(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: SharedValue<V>,
+    ) -> Option<SharedValue<V>> {
+        use hashbrown::hash_map::RawEntryMut;
+
+        match self.raw_entry_mut().from_key_hashed_nocheck(hash, &key) {
+            RawEntryMut::Occupied(mut entry) => Some(entry.insert(value)),
+            RawEntryMut::Vacant(entry) => {
+                entry.insert_hashed_nocheck(hash, key, value);
+
+                None
+            }
+        }
+    }
+
+    fn insert_unique_hashed(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: SharedValue<V>,
+    ) -> (&K, &SharedValue<V>) {
+        use hashbrown::hash_map::RawEntryMut;
+
+        match self.raw_entry_mut().from_hash(hash, |_| false) {
+            RawEntryMut::Vacant(entry) => {
+                let (k, v) = entry.insert_hashed_nocheck(hash, key, value);
+
+                (k, v)
+            }
+            RawEntryMut::Occupied(_) => unreachable!(),
+        }
+    }
+
+    fn remove_entry_hashed<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        use hashbrown::hash_map::RawEntryMut;
+
+        match self.raw_entry_mut().from_key_hashed_nocheck(hash, key) {
+            RawEntryMut::Occupied(entry) => Some(entry.remove_entry()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+}
+
+#[cfg(not(feature = "hashbrown"))]
+impl<K: Eq + Hash, V, S: BuildHasher> HashedShard<K, V> for HashMap<K, V, S> {
+    fn get_key_value_hashed<Q>(&self, _hash: u64, key: &Q) -> Option<(&K, &SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key)
+    }
+
+    fn get_mut_hashed<Q>(&mut self, _hash: u64, key: &Q) -> Option<&mut SharedValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key)
+    }
+
+    fn insert_hashed(
+        &mut self,
+        _hash: u64,
+        key: K,
+        value: SharedValue<V>,
+    ) -> Option<SharedValue<V>> {
+        self.insert(key, value)
+    }
+
+    fn insert_unique_hashed(
+        &mut self,
+        _hash: u64,
+        key: K,
+        value: SharedValue<V>,
+    ) -> (&K, &SharedValue<V>) {
+        // The std map does not return the stored key, so it is looked up again by a bitwise copy.
+        unsafe {
+            let c: K = ptr::read(&key);
+
+            self.insert(key, value);
+
+            let kv = self.get_key_value(&c).unwrap();
+
+            mem::forget(c);
+
+            kv
+        }
+    }
+
+    fn remove_entry_hashed<Q>(&mut self, _hash: u64, key: &Q) -> Option<(K, SharedValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key)
+    }
+}
@@ -1,12 +1,16 @@
 //! Central map trait to ease modifications and extensions down the road.
 
+use crate::cas::{CompareAndSwap, InsertIfAbsent, RemoveIfEq, Replace, UpdateIf};
+use crate::error::TryLockError;
 use crate::iter::{Iter, IterMut};
 use crate::lock::{RwLockReadGuard, RwLockWriteGuard};
 use crate::mapref::entry::Entry;
 use crate::mapref::one::{Ref, RefMut};
+use crate::mapref::upgradeable::UpgradeableEntry;
 use crate::HashMap;
 use core::borrow::Borrow;
 use core::hash::{BuildHasher, Hash};
+use core::time::Duration;
 
 /// Implementation detail that is exposed due to generic constraints in public types.
 pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
@@ -27,8 +31,41 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
     /// The index must not be out of bounds.
     unsafe fn _yield_write_shard(&'a self, i: usize) -> RwLockWriteGuard<'a, HashMap<K, V, S>>;
 
+    /// Tries to lock a shard for reading, waiting at most `timeout` or not at all if `None`.
+    ///
+    /// # Safety
+    ///
+    /// The index must not be out of bounds.
+    unsafe fn _try_yield_read_shard(
+        &'a self,
+        i: usize,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockReadGuard<'a, HashMap<K, V, S>>>;
+
+    /// Tries to lock a shard for writing, waiting at most `timeout` or not at all if `None`.
+    ///
+    /// # Safety
+    ///
+    /// The index must not be out of bounds.
+    unsafe fn _try_yield_write_shard(
+        &'a self,
+        i: usize,
+        timeout: Option<Duration>,
+    ) -> Option<RwLockWriteGuard<'a, HashMap<K, V, S>>>;
+
+    /// Keeps entries from moving between shards while the guard is held,
+    /// so that visiting every shard sees each entry exactly once.
+    fn _lock_layout(&'a self) -> RwLockReadGuard<'a, ()>;
+
     fn _insert(&self, key: K, value: V) -> Option<V>;
 
+    fn _try_insert_now(
+        &'a self,
+        key: K,
+        value: V,
+        timeout: Option<Duration>,
+    ) -> Result<Option<V>, TryLockError<(K, V)>>;
+
     fn _insert_with<T, E>(
         &self,
         key: K,
@@ -55,6 +92,15 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    fn _try_remove<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<(K, V)>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
     fn _remove_if<Q>(&self, key: &Q, f: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)>
     where
         K: Borrow<Q>,
@@ -74,6 +120,35 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    fn _compare_and_swap<Q>(&self, key: &Q, expected: &V, new: V) -> CompareAndSwap<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq;
+
+    fn _insert_if_absent(&self, key: K, value: V) -> InsertIfAbsent<K, V>;
+
+    fn _replace<Q>(&self, key: &Q, value: V) -> Replace<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    fn _update_if<Q, R>(
+        &self,
+        key: &Q,
+        predicate: impl FnOnce(&K, &V) -> bool,
+        f: impl FnOnce(&K, &mut V) -> R,
+    ) -> UpdateIf<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    fn _remove_if_eq<Q>(&self, key: &Q, expected: &V) -> RemoveIfEq<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq;
+
     fn _iter(&'a self) -> Iter<'a, K, V, S, Self>
     where
         Self: Sized;
@@ -87,6 +162,15 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    fn _try_get<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Ref<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
     fn _get_with<Q, T, E>(
         &'a self,
         key: &Q,
@@ -111,6 +195,15 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    fn _try_get_mut<Q>(
+        &'a self,
+        key: &Q,
+        timeout: Option<Duration>,
+    ) -> Result<Option<RefMut<'a, K, V, S>>, TryLockError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
     fn _shrink_to_fit(&self);
 
     fn _retain(&self, f: impl FnMut(&K, &mut V) -> bool);
@@ -128,13 +221,23 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
 
     fn _entry(&'a self, key: K) -> Entry<'a, K, V, S>;
 
+    fn _try_entry(
+        &'a self,
+        key: K,
+        timeout: Option<Duration>,
+    ) -> Result<Entry<'a, K, V, S>, TryLockError<K>>;
+
+    fn _entry_upgradeable(&'a self, key: K) -> UpgradeableEntry<'a, K, V, S>;
+
     fn _hasher(&self) -> S;
 
     // provided
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn _clear(&self) {
         self._retain(|_, _| false)
     }
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn _contains_key<Q>(&'a self, key: &Q) -> bool
     where
         K: Borrow<Q>,
@@ -143,6 +246,7 @@ pub trait Map<'a, K: 'a + Eq + Hash, V: 'a, S: 'a + Clone + BuildHasher> {
         self._get(key).is_some()
     }
 
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
     fn _is_empty(&self) -> bool {
         self._len() == 0
     }
@@ -0,0 +1,113 @@
+//! The shard layout of a map.
+//!
+//! A map starts out with a single table. Resharding links a new table from `next` and moves the
+//! entries over one shard at a time while the map stays in use. A key lives in the shard of the
+//! first table on the chain whose shard for it has not been migrated yet, so single-key
+//! operations lock that shard and follow `next` if it turns out to be migrated. Migrated shards
+//! are left empty, which lets whole-map operations simply visit every shard of every table on the
+//! chain as long as no migration step runs concurrently.
+
+use crate::lock::RwLock;
+use crate::{util, HashMap};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+pub(crate) struct Table<K, V, S> {
+    shift: usize,
+    pub(crate) shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    /// `migrated[i]` is set once the entries of `shards[i]` were moved to `next`.
+    /// It is only written while holding the write lock of `shards[i]`.
+    migrated: Box<[AtomicBool]>,
+    /// The table entries are being moved to. Points into the table list owned by the map.
+    next: AtomicPtr<Table<K, V, S>>,
+}
+
+impl<K, V, S> Table<K, V, S> {
+    pub(crate) fn new(shards: Box<[RwLock<HashMap<K, V, S>>]>) -> Self {
+        let shift = util::ptr_size_bits() - shards.len().trailing_zeros() as usize;
+        let migrated = shards.iter().map(|_| AtomicBool::new(false)).collect();
+
+        Self {
+            shift,
+            shards,
+            migrated,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn into_shards(self) -> Box<[RwLock<HashMap<K, V, S>>]> {
+        self.shards
+    }
+
+    pub(crate) fn determine_shard(&self, hash: usize) -> usize {
+        // Leave the high 7 bits for the HashBrown SIMD tag.
+        (hash << 7) >> self.shift
+    }
+
+    /// The shard whose range holds the scan position `pos`, and the position that range ends at,
+    /// `None` for the last shard. See [`scan_position`].
+    pub(crate) fn scan_shard(&self, pos: u64) -> (usize, Option<u64>) {
+        let shift = 64 - self.shards.len().trailing_zeros();
+        let i = (pos >> shift) as usize;
+
+        let end = if i + 1 == self.shards.len() {
+            None
+        } else {
+            Some(((i + 1) as u64) << shift)
+        };
+
+        (i, end)
+    }
+
+    /// Whether the entries of shard `i` were moved to the next table.
+    ///
+    /// Only meaningful while holding a lock on shard `i`, or while no migration step can run.
+    pub(crate) fn is_migrated(&self, i: usize) -> bool {
+        self.migrated[i].load(Ordering::Relaxed)
+    }
+
+    /// Marks shard `i` as migrated. The caller must hold the write lock of shard `i`.
+    pub(crate) fn set_migrated(&self, i: usize) {
+        self.migrated[i].store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn next(&self) -> Option<&Table<K, V, S>> {
+        unsafe { self.next.load(Ordering::Acquire).as_ref() }
+    }
+
+    /// Links the table entries will be moved to. It has to outlive `self`.
+    pub(crate) fn set_next(&self, next: &Table<K, V, S>) {
+        self.next
+            .store(next as *const Self as *mut Self, Ordering::Release);
+    }
+
+    /// Iterates over this table and the tables linked after it.
+    pub(crate) fn chain(&self) -> impl Iterator<Item = &Table<K, V, S>> + '_ {
+        let mut table = Some(self);
+
+        core::iter::from_fn(move || {
+            let current = table?;
+            table = current.next();
+            Some(current)
+        })
+    }
+}
+
+/// The position of `hash` in the order cursor scans visit entries in.
+///
+/// The leading bits of the position are the bits `determine_shard` picks the shard from, so every
+/// shard of every table holds a contiguous range of positions. A scan resuming from a position
+/// therefore skips exactly the entries it already visited, however the map was resharded
+/// meanwhile. The mapping is one to one, so only keys with equal hashes share a position.
+pub(crate) fn scan_position(hash: u64) -> u64 {
+    let bits = util::ptr_size_bits();
+    let rotated = (hash as usize).rotate_left(7) as u64;
+
+    if bits == 64 {
+        rotated
+    } else {
+        // Only the low bits of the hash pick the shard, the high ones break ties.
+        (rotated << (64 - bits)) | (hash >> bits)
+    }
+}
@@ -0,0 +1,156 @@
+use core::borrow::Borrow;
+use core::fmt;
+
+struct Slot<K, V> {
+    key: K,
+    hash: u64,
+    value: Option<V>,
+    /// Position of the shard holding `key` among the shards locked by the transaction.
+    shard: usize,
+    dirty: bool,
+}
+
+/// Working copies of the entries locked by `DashMap::transaction`.
+///
+/// Changes made here are only written back to the map if the transaction closure returns `Ok`.
+/// Only the keys passed to `DashMap::transaction` can be accessed; any other key panics.
+pub struct Transaction<K, V> {
+    slots: Vec<Slot<K, V>>,
+}
+
+impl<K: Eq, V> Transaction<K, V> {
+    pub(crate) fn new() -> Self {
+        Self { slots: Vec::new() }
+    }
+
+    /// Adds `key` with its `hash`, currently holding `value` in the locked shard at position `shard`.
+    /// Keys that were already added are ignored.
+    pub(crate) fn add(
+        &mut self,
+        key: K,
+        hash: u64,
+        shard: usize,
+        value: impl FnOnce(&K) -> Option<V>,
+    ) {
+        if self.slots.iter().any(|slot| slot.key == key) {
+            return;
+        }
+
+        let value = value(&key);
+
+        self.slots.push(Slot {
+            key,
+            hash,
+            value,
+            shard,
+            dirty: false,
+        });
+    }
+
+    /// The keys that were changed, with their hash, the position of their shard and their new value.
+    pub(crate) fn into_changes(self) -> impl Iterator<Item = (K, u64, usize, Option<V>)> {
+        self.slots
+            .into_iter()
+            .filter(|slot| slot.dirty)
+            .map(|slot| (slot.key, slot.hash, slot.shard, slot.value))
+    }
+
+    fn slot<Q>(&self, key: &Q) -> &Slot<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match self.slots.iter().find(|slot| slot.key.borrow() == key) {
+            Some(slot) => slot,
+            None => panic!("key is not part of the transaction"),
+        }
+    }
+
+    fn slot_mut<Q>(&mut self, key: &Q) -> &mut Slot<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match self.slots.iter_mut().find(|slot| slot.key.borrow() == key) {
+            Some(slot) => {
+                slot.dirty = true;
+
+                slot
+            }
+            None => panic!("key is not part of the transaction"),
+        }
+    }
+
+    /// Returns the value of `key`, if present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not part of the transaction.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slot(key).value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value of `key`, if present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not part of the transaction.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slot_mut(key).value.as_mut()
+    }
+
+    /// Checks if `key` is present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not part of the transaction.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of `key`, returning the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not part of the transaction.
+    pub fn insert<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slot_mut(key).value.replace(value)
+    }
+
+    /// Removes `key`, returning its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not part of the transaction.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slot_mut(key).value.take()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Transaction<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.slots.iter().map(|slot| (&slot.key, &slot.value)))
+            .finish()
+    }
+}
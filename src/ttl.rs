@@ -0,0 +1,556 @@
+//! A [`DashMap`] whose entries expire after a time-to-live.
+//!
+//! [`DashMap`]: ../struct.DashMap.html
+
+use crate::mapref::one;
+use crate::DashMap;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+use std::collections::hash_map::RandomState;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Source of the current time for a [`TtlMap`].
+///
+/// Implement this to control time in tests instead of sleeping.
+///
+/// # Examples
+///
+/// ```
+/// use dashmap::ttl::{Clock, TtlMap};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::{Duration, Instant};
+///
+/// struct ManualClock(Mutex<Instant>);
+///
+/// impl Clock for ManualClock {
+///     fn now(&self) -> Instant {
+///         *self.0.lock().unwrap()
+///     }
+/// }
+///
+/// let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+/// let map = TtlMap::with_clock(Duration::from_secs(60), clock.clone());
+/// map.insert("session", 1);
+///
+/// *clock.0.lock().unwrap() += Duration::from_secs(61);
+/// assert!(map.get("session").is_none());
+/// ```
+///
+/// [`TtlMap`]: struct.TtlMap.html
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The clock used by default, reading `Instant::now()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// What `TtlMap::ttl` reports for entries that never expire.
+const FOREVER: Duration = Duration::from_secs(u64::MAX);
+
+/// A value together with the instant it stops being visible, if any.
+#[derive(Clone)]
+pub(crate) struct Expiring<V> {
+    value: V,
+    deadline: Option<Instant>,
+}
+
+impl<V> Expiring<V> {
+    /// A `ttl` too large to be represented never expires.
+    fn new(value: V, now: Instant, ttl: Duration) -> Self {
+        Self {
+            value,
+            deadline: now.checked_add(ttl),
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.deadline, Some(deadline) if deadline <= now)
+    }
+}
+
+/// TtlMap is a thin wrapper around [`DashMap`] whose entries expire a set time after they were inserted.
+///
+/// Every insert is given a time-to-live, either the default of the map or one given per entry.
+/// Expired entries are invisible to lookups. They are removed lazily when a lookup runs into
+/// them and all at once by [`evict_expired`], which visits one shard at a time so that
+/// sweeping a large map does not hold up the rest of it.
+///
+/// Time is read from a [`Clock`], so that expiry can be driven by hand in tests.
+///
+/// [`DashMap`]: ../struct.DashMap.html
+/// [`evict_expired`]: #method.evict_expired
+/// [`Clock`]: trait.Clock.html
+pub struct TtlMap<K, V, S = RandomState, C = SystemClock> {
+    inner: DashMap<K, Expiring<V>, S>,
+    ttl: Duration,
+    clock: C,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: Clone, C: Clone> Clone for TtlMap<K, V, S, C> {
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug, S: BuildHasher + Clone, C: Clock> fmt::Debug
+    for TtlMap<K, V, S, C>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now = self.clock.now();
+        let mut pmap = f.debug_map();
+
+        for r in self.inner.iter() {
+            if !r.is_expired(now) {
+                pmap.entry(r.key(), &r.value);
+            }
+        }
+
+        pmap.finish()
+    }
+}
+
+impl<K: Eq + Hash, V> TtlMap<K, V, RandomState, SystemClock> {
+    /// Creates a new TtlMap whose entries expire `ttl` after their insert by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::TtlMap;
+    /// use std::time::Duration;
+    ///
+    /// let sessions = TtlMap::new(Duration::from_secs(30 * 60));
+    /// sessions.insert("alice", 7);
+    /// assert_eq!(*sessions.get("alice").unwrap(), 7);
+    /// ```
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_hasher_and_clock(ttl, RandomState::default(), SystemClock)
+    }
+}
+
+impl<K: Eq + Hash, V, C: Clock> TtlMap<K, V, RandomState, C> {
+    /// Creates a new TtlMap with a default `ttl` that reads the time from `clock`.
+    pub fn with_clock(ttl: Duration, clock: C) -> Self {
+        Self::with_hasher_and_clock(ttl, RandomState::default(), clock)
+    }
+}
+
+impl<'a, K: 'a + Eq + Hash, V: 'a, S: BuildHasher + Clone, C: Clock> TtlMap<K, V, S, C> {
+    /// Creates a new TtlMap with a default `ttl`, the provided hasher and `clock`.
+    pub fn with_hasher_and_clock(ttl: Duration, hasher: S, clock: C) -> Self {
+        Self {
+            inner: DashMap::with_hasher(hasher),
+            ttl,
+            clock,
+        }
+    }
+
+    /// Returns the time-to-live given to entries inserted with `insert`.
+    pub fn default_ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns a reference to the clock of the map.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Inserts a key and a value that expires after the default time-to-live of the map.
+    /// If the key already had a live value, it is returned. An expired one is dropped.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.insert_with_ttl(key, value, self.ttl)
+    }
+
+    /// Inserts a key and a value that expires after `ttl`.
+    /// If the key already had a live value, it is returned. An expired one is dropped.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::TtlMap;
+    /// use std::time::Duration;
+    ///
+    /// let cache = TtlMap::new(Duration::from_secs(60));
+    /// cache.insert_with_ttl("token", "abc", Duration::from_secs(0));
+    /// assert!(cache.get("token").is_none());
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = self.clock.now();
+
+        self.inner
+            .insert(key, Expiring::new(value, now, ttl))
+            .filter(|old| !old.is_expired(now))
+            .map(|old| old.value)
+    }
+
+    /// Get an immutable reference to a live entry in the map.
+    /// An expired entry found on the way is removed.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get<Q>(&'a self, key: &Q) -> Option<Ref<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+
+        {
+            let r = self.inner.get(key)?;
+
+            if !r.is_expired(now) {
+                return Some(Ref { inner: r });
+            }
+        }
+
+        self.inner.remove_if(key, |_, v| v.is_expired(now));
+
+        None
+    }
+
+    /// Get a mutable reference to a live entry in the map.
+    /// An expired entry found on the way is removed.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn get_mut<Q>(&'a self, key: &Q) -> Option<RefMut<'a, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+
+        {
+            let r = self.inner.get_mut(key)?;
+
+            if !r.is_expired(now) {
+                return Some(RefMut { inner: r });
+            }
+        }
+
+        self.inner.remove_if(key, |_, v| v.is_expired(now));
+
+        None
+    }
+
+    /// Returns how long a live entry has left before it expires.
+    /// Entries inserted with a time-to-live too large to be represented report `u64::MAX` seconds.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn ttl<Q>(&'a self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        let r = self.get(key)?;
+
+        Some(match r.inner.deadline {
+            Some(deadline) => deadline.saturating_duration_since(now),
+            None => FOREVER,
+        })
+    }
+
+    /// Checks if the map contains a live entry for a specific key.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+
+        match self.inner.get(key) {
+            Some(r) => !r.is_expired(now),
+            None => false,
+        }
+    }
+
+    /// Removes an entry from the map, returning the key and value if it was live.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+
+        self.inner
+            .remove(key)
+            .filter(|(_, v)| !v.is_expired(now))
+            .map(|(k, v)| (k, v.value))
+    }
+
+    /// Removes every expired entry, one shard at a time, and returns how many were removed.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dashmap::TtlMap;
+    /// use std::time::Duration;
+    ///
+    /// let cache = TtlMap::new(Duration::from_secs(60));
+    /// cache.insert(1, "kept");
+    /// cache.insert_with_ttl(2, "expired", Duration::from_secs(0));
+    /// assert_eq!(cache.evict_expired(), 1);
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn evict_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut evicted = 0;
+
+        self.inner.retain(|_, v| {
+            let expired = v.is_expired(now);
+
+            evicted += expired as usize;
+
+            !expired
+        });
+
+        evicted
+    }
+
+    /// Returns the number of entries stored in the map, including expired ones that were not removed yet.
+    /// Call `evict_expired` first for an exact count.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Checks if the map stores no entries, including expired ones that were not removed yet.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding a mutable reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Removes all entries in the map.
+    ///
+    /// **Locking behaviour:** May deadlock if called when holding any sort of reference into the map.
+    #[cfg_attr(feature = "deadlock-detection", track_caller)]
+    pub fn clear(&self) {
+        self.inner.clear()
+    }
+}
+
+/// An immutable reference to a live entry of a [`TtlMap`].
+///
+/// [`TtlMap`]: struct.TtlMap.html
+pub struct Ref<'a, K, V, S = RandomState> {
+    inner: one::Ref<'a, K, Expiring<V>, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Ref<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> &V {
+        &self.inner.value().value
+    }
+
+    pub fn pair(&self) -> (&K, &V) {
+        (self.key(), self.value())
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Deref for Ref<'a, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+/// A mutable reference to a live entry of a [`TtlMap`]. Writing through it does not extend its time-to-live.
+///
+/// [`TtlMap`]: struct.TtlMap.html
+pub struct RefMut<'a, K, V, S = RandomState> {
+    inner: one::RefMut<'a, K, Expiring<V>, S>,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> RefMut<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> &V {
+        &self.inner.value().value
+    }
+
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.inner.value_mut().value
+    }
+
+    pub fn pair(&self) -> (&K, &V) {
+        (self.key(), self.value())
+    }
+
+    pub fn pair_mut(&mut self) -> (&K, &mut V) {
+        let (k, v) = self.inner.pair_mut();
+
+        (k, &mut v.value)
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Deref for RefMut<'a, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> DerefMut for RefMut<'a, K, V, S> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.value_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, TtlMap, FOREVER};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    struct TestClock(Mutex<Instant>);
+
+    impl TestClock {
+        fn new() -> Self {
+            TestClock(Mutex::new(Instant::now()))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_lazy_expiry() {
+        let clock = TestClock::new();
+        let map = TtlMap::with_clock(10 * SEC, &clock);
+
+        map.insert(1, 1);
+        map.insert_with_ttl(2, 2, 20 * SEC);
+
+        clock.advance(9 * SEC);
+
+        assert_eq!(*map.get(&1).unwrap(), 1);
+        assert_eq!(map.ttl(&1), Some(SEC));
+        assert!(map.contains_key(&2));
+
+        clock.advance(SEC);
+
+        assert!(map.get(&1).is_none());
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.len(), 1);
+        assert_eq!(*map.get(&2).unwrap(), 2);
+
+        clock.advance(10 * SEC);
+
+        assert!(map.get_mut(&2).is_none());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_expired_values_are_not_returned() {
+        let clock = TestClock::new();
+        let map = TtlMap::with_clock(SEC, &clock);
+
+        map.insert("a", 1);
+        map.insert("b", 2);
+
+        clock.advance(SEC);
+
+        assert_eq!(map.insert("a", 3), None);
+        assert_eq!(map.remove("b"), None);
+        assert_eq!(map.insert("a", 4), Some(3));
+
+        *map.get_mut("a").unwrap() += 1;
+
+        assert_eq!(map.remove("a"), Some(("a", 5)));
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let clock = TestClock::new();
+        let map = TtlMap::with_clock(SEC, &clock);
+
+        for i in 0..100 {
+            map.insert_with_ttl(i, i, (i % 4) * SEC);
+        }
+
+        assert_eq!(map.evict_expired(), 25);
+
+        clock.advance(2 * SEC);
+
+        assert_eq!(map.evict_expired(), 50);
+        assert_eq!(map.len(), 25);
+        assert!((0..100).all(|i| map.contains_key(&i) == (i % 4 == 3)));
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let clock = TestClock::new();
+        let map = TtlMap::with_clock(FOREVER, &clock);
+
+        map.insert(1, 1);
+
+        clock.advance(1_000_000 * SEC);
+
+        assert_eq!(map.evict_expired(), 0);
+        assert_eq!(map.ttl(&1), Some(FOREVER));
+    }
+}